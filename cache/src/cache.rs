@@ -2,7 +2,7 @@ use super::Result;
 
 use async_trait::async_trait;
 use model::channel::Channel;
-use model::guild::{Emoji, Guild, Member, Role, VoiceState};
+use model::guild::{Emoji, Guild, Member, MemberUpdate, Role, VoiceState};
 use model::user::User;
 use model::Snowflake;
 
@@ -10,8 +10,12 @@ use model::Snowflake;
 pub trait Cache: Send + Sync + 'static {
     async fn store_guild(&self, guild: Guild) -> Result<()>;
     async fn store_guilds(&self, guilds: Vec<Guild>) -> Result<()>;
+    /// Applies a GUILD_UPDATE, keeping the fields that are only sent in GUILD_CREATE
+    async fn update_guild(&self, guild: Guild) -> Result<()>;
     async fn get_guild(&self, id: Snowflake) -> Result<Option<Guild>>;
     async fn delete_guild(&self, id: Snowflake) -> Result<()>;
+    /// Marks a cached guild as unavailable during a Discord outage, until its next GUILD_CREATE
+    async fn mark_guild_unavailable(&self, id: Snowflake) -> Result<()>;
     async fn get_guild_count(&self) -> Result<usize>;
 
     async fn store_channel(&self, channel: Channel) -> Result<()>;
@@ -30,6 +34,8 @@ pub trait Cache: Send + Sync + 'static {
         members: Vec<Member>,
         guild_id: Snowflake,
     ) -> Result<()>;
+    /// Applies a GUILD_MEMBER_UPDATE, keeping the cached values of fields that weren't sent
+    async fn update_member(&self, update: MemberUpdate, guild_id: Snowflake) -> Result<()>;
     async fn get_member(
         &self,
         user_id: Snowflake,
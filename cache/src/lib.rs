@@ -4,6 +4,9 @@ pub use cache::Cache;
 mod options;
 pub use options::Options;
 
+mod noop;
+pub use noop::NoOpCache;
+
 mod postgres;
 pub use postgres::{CachePayload, PostgresCache};
 
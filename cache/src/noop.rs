@@ -0,0 +1,141 @@
+use crate::{Cache, Result};
+use model::channel::Channel;
+use model::guild::{Emoji, Guild, Member, MemberUpdate, Role, VoiceState};
+use model::user::User;
+use model::Snowflake;
+
+use async_trait::async_trait;
+
+/// A cache that stores nothing, for deployments that only forward events
+pub struct NoOpCache;
+
+#[async_trait]
+impl Cache for NoOpCache {
+    async fn store_guild(&self, _: Guild) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_guilds(&self, _: Vec<Guild>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn update_guild(&self, _: Guild) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_guild(&self, _: Snowflake) -> Result<Option<Guild>> {
+        Ok(None)
+    }
+
+    async fn delete_guild(&self, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn mark_guild_unavailable(&self, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_guild_count(&self) -> Result<usize> {
+        Ok(0)
+    }
+
+    async fn store_channel(&self, _: Channel) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_channels(&self, _: Vec<Channel>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_channel(&self, _: Snowflake) -> Result<Option<Channel>> {
+        Ok(None)
+    }
+
+    async fn delete_channel(&self, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_user(&self, _: User) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_users(&self, _: Vec<User>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_user(&self, _: Snowflake) -> Result<Option<User>> {
+        Ok(None)
+    }
+
+    async fn delete_user(&self, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_member(&self, _: Member, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_members(&self, _: Vec<Member>, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn update_member(&self, _: MemberUpdate, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_member(&self, _: Snowflake, _: Snowflake) -> Result<Option<Member>> {
+        Ok(None)
+    }
+
+    async fn delete_member(&self, _: Snowflake, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_role(&self, _: Role, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_roles(&self, _: Vec<Role>, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_role(&self, _: Snowflake) -> Result<Option<Role>> {
+        Ok(None)
+    }
+
+    async fn delete_role(&self, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_emoji(&self, _: Emoji, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_emojis(&self, _: Vec<Emoji>, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_emoji(&self, _: Snowflake) -> Result<Option<Emoji>> {
+        Ok(None)
+    }
+
+    async fn delete_emoji(&self, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_voice_state(&self, _: VoiceState) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store_voice_states(&self, _: Vec<VoiceState>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_voice_state(&self, _: Snowflake, _: Snowflake) -> Result<Option<VoiceState>> {
+        Ok(None)
+    }
+
+    async fn delete_voice_state(&self, _: Snowflake, _: Snowflake) -> Result<()> {
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use crate::CacheError;
 use model::channel::Channel;
-use model::guild::{Emoji, Guild, Member, Role, VoiceState};
+use model::guild::{Emoji, Guild, Member, MemberUpdate, Role, VoiceState};
 use model::user::User;
 use model::Snowflake;
 use tokio::sync::oneshot;
@@ -18,6 +18,10 @@ pub enum CachePayload {
         guilds: Vec<Guild>,
         tx: ResultSender<()>,
     },
+    UpdateGuild {
+        guild: Box<Guild>,
+        tx: ResultSender<()>,
+    },
     GetGuild {
         id: Snowflake,
         tx: ResultSender<Option<Guild>>,
@@ -26,6 +30,10 @@ pub enum CachePayload {
         id: Snowflake,
         tx: ResultSender<()>,
     },
+    MarkGuildUnavailable {
+        id: Snowflake,
+        tx: ResultSender<()>,
+    },
     GetGuildCount {
         tx: ResultSender<usize>,
     },
@@ -61,6 +69,11 @@ pub enum CachePayload {
         guild_id: Snowflake,
         tx: ResultSender<()>,
     },
+    UpdateMember {
+        update: Box<MemberUpdate>,
+        guild_id: Snowflake,
+        tx: ResultSender<()>,
+    },
     GetMember {
         user_id: Snowflake,
         guild_id: Snowflake,
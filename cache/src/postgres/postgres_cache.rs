@@ -8,7 +8,7 @@ use crate::postgres::payload::CachePayload;
 use crate::postgres::worker::{PayloadReceiver, Worker};
 use backoff::ExponentialBackoff;
 use model::channel::Channel;
-use model::guild::{Emoji, Guild, Member, Role, VoiceState};
+use model::guild::{Emoji, Guild, Member, MemberUpdate, Role, VoiceState};
 use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot, Mutex};
 use tokio_postgres::tls::NoTlsStream;
@@ -146,6 +146,28 @@ impl Cache for PostgresCache {
             .await
     }
 
+    async fn update_guild(&self, mut guild: Guild) -> Result<()> {
+        if !self.opts.guilds {
+            return Ok(());
+        }
+
+        // GUILD_UPDATE carries the full emoji list, which is stored separately
+        let emojis = std::mem::take(&mut guild.emojis);
+        let guild_id = guild.id;
+
+        let (tx, rx) = oneshot::channel();
+        self.send_payload(
+            rx,
+            CachePayload::UpdateGuild {
+                guild: Box::new(guild),
+                tx,
+            },
+        )
+            .await?;
+
+        self.store_emojis(emojis, guild_id).await
+    }
+
     async fn get_guild(&self, id: Snowflake) -> Result<Option<Guild>> {
         let (tx, rx) = oneshot::channel();
         self.send_payload(rx, CachePayload::GetGuild { id, tx })
@@ -157,6 +179,11 @@ impl Cache for PostgresCache {
         self.send_payload(rx, CachePayload::DeleteGuild { id, tx }).await
     }
 
+    async fn mark_guild_unavailable(&self, id: Snowflake) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.send_payload(rx, CachePayload::MarkGuildUnavailable { id, tx }).await
+    }
+
     async fn get_guild_count(&self) -> Result<usize> {
         let (tx, rx) = oneshot::channel();
         self.send_payload(rx, CachePayload::GetGuildCount { tx }).await
@@ -239,6 +266,23 @@ impl Cache for PostgresCache {
             .await
     }
 
+    async fn update_member(&self, update: MemberUpdate, guild_id: Snowflake) -> Result<()> {
+        if !self.opts.members {
+            return Ok(());
+        }
+
+        let (tx, rx) = oneshot::channel();
+        self.send_payload(
+            rx,
+            CachePayload::UpdateMember {
+                update: Box::new(update),
+                guild_id,
+                tx,
+            },
+        )
+            .await
+    }
+
     async fn get_member(
         &self,
         user_id: Snowflake,
@@ -1,15 +1,25 @@
 use crate::postgres::payload::CachePayload;
 use crate::{CacheError, Result};
 use model::channel::Channel;
-use model::guild::{Emoji, Guild, Member, Role, VoiceState};
+use model::guild::{Emoji, Guild, Member, MemberUpdate, Role, VoiceState};
 use model::user::User;
 use model::Snowflake;
+use serde_json::Value;
 use std::cmp::Ordering::Equal;
 use std::fmt::Display;
 use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot, Mutex};
 use tokio_postgres::Client;
 
+// fields of a guild that are sent in GUILD_CREATE but not GUILD_UPDATE, which are kept from the
+// cached guild. Every other field is replaced, so that fields cleared by an update are removed
+// even though None fields aren't serialized.
+const GUILD_CREATE_ONLY_FIELDS: &[&str] = &["joined_at", "large", "unavailable", "member_count"];
+
+// fields of a member that GUILD_MEMBER_UPDATE may leave out, whose cached values are kept unless
+// the update sends them
+const MEMBER_UPDATE_OPTIONAL_FIELDS: &[&str] = &["deaf", "mute"];
+
 pub struct Worker {
     id: usize,
     client: Client,
@@ -79,12 +89,18 @@ impl Worker {
             CachePayload::StoreGuilds { guilds, tx } => {
                 let _ = tx.send(self.store_guilds(guilds).await);
             }
+            CachePayload::UpdateGuild { guild, tx } => {
+                let _ = tx.send(self.update_guild(*guild).await);
+            }
             CachePayload::GetGuild { id, tx } => {
                 let _ = tx.send(self.get_guild(id).await);
             }
             CachePayload::DeleteGuild { id, tx } => {
                 let _ = tx.send(self.delete_guild(id).await);
             }
+            CachePayload::MarkGuildUnavailable { id, tx } => {
+                let _ = tx.send(self.mark_guild_unavailable(id).await);
+            }
             CachePayload::GetGuildCount { tx } => {
                 let _ = tx.send(self.get_guild_count().await);
             }
@@ -114,6 +130,13 @@ impl Worker {
             } => {
                 let _ = tx.send(self.store_members(members, guild_id).await);
             }
+            CachePayload::UpdateMember {
+                update,
+                guild_id,
+                tx,
+            } => {
+                let _ = tx.send(self.update_member(*update, guild_id).await);
+            }
             CachePayload::GetMember {
                 user_id,
                 guild_id,
@@ -264,6 +287,25 @@ impl Worker {
         res
     }
 
+    async fn update_guild(&self, guild: Guild) -> Result<()> {
+        let mut data = serde_json::to_value(&guild).map_err(CacheError::JsonError)?;
+        if let Value::Object(fields) = &mut data {
+            for field in GUILD_CREATE_ONLY_FIELDS {
+                fields.remove(*field);
+            }
+        }
+
+        // only the GUILD_CREATE fields are taken from the cached guild, the update replaces the rest
+        let query = r#"INSERT INTO guilds("guild_id", "data") VALUES($1, $2) ON CONFLICT("guild_id") DO UPDATE SET "data" = (SELECT COALESCE(jsonb_object_agg("key", "value"), '{}'::jsonb) FROM jsonb_each(guilds.data) WHERE "key" = ANY($3)) || excluded.data;"#;
+        self.client
+            .execute(query, &[&(guild.id.0 as i64), &data, &GUILD_CREATE_ONLY_FIELDS])
+            .await
+            .map_err(CacheError::DatabaseError)?;
+
+        // GUILD_UPDATE carries the full role list, unlike channels and members
+        self.store_roles(guild.roles, guild.id).await
+    }
+
     async fn get_guild(&self, id: Snowflake) -> Result<Option<Guild>> {
         /*self.client
             .query(
@@ -285,6 +327,16 @@ impl Worker {
         Ok(())
     }
 
+    async fn mark_guild_unavailable(&self, id: Snowflake) -> Result<()> {
+        // the next GUILD_CREATE replaces the data, clearing the flag
+        let query = r#"UPDATE guilds SET "data" = "data" || '{"unavailable": true}'::jsonb WHERE "guild_id" = $1;"#;
+        self.client
+            .execute(query, &[&(id.0 as i64)])
+            .await
+            .map_err(CacheError::DatabaseError)?;
+        Ok(())
+    }
+
     async fn get_guild_count(&self) -> Result<usize> {
         let query = r#"SELECT COUNT(guild_id) FROM guilds;"#;
 
@@ -479,6 +531,27 @@ impl Worker {
         Ok(())
     }
 
+    async fn update_member(&self, update: MemberUpdate, guild_id: Snowflake) -> Result<()> {
+        // deaf and mute are left out when they weren't sent, so the cached values are kept
+        let data = serde_json::to_value(&update).map_err(CacheError::JsonError)?;
+
+        let query = r#"INSERT INTO members("guild_id", "user_id", "data") VALUES($1, $2, $3) ON CONFLICT("guild_id", "user_id") DO UPDATE SET "data" = (SELECT COALESCE(jsonb_object_agg("key", "value"), '{}'::jsonb) FROM jsonb_each(members.data) WHERE "key" = ANY($4)) || excluded.data;"#;
+        self.client
+            .execute(
+                query,
+                &[
+                    &(guild_id.0 as i64),
+                    &(update.user.id.0 as i64),
+                    &data,
+                    &MEMBER_UPDATE_OPTIONAL_FIELDS,
+                ],
+            )
+            .await
+            .map_err(CacheError::DatabaseError)?;
+
+        Ok(())
+    }
+
     async fn get_member(
         &self,
         user_id: Snowflake,
@@ -0,0 +1,202 @@
+//! Runs against the database given by TEST_DATABASE_URI, e.g.
+//! postgres://postgres@localhost/postgres. The tests are ignored unless run with
+//! `cargo test -- --ignored`, so that they're reported as skipped where there's no database.
+
+use cache::{Cache, Options, PostgresCache};
+use model::guild::{Guild, Member, MemberUpdate};
+use model::Snowflake;
+use serde_json::{json, Value};
+use tokio_postgres::{Client, NoTls};
+
+async fn connect() -> (PostgresCache, Client) {
+    let uri = std::env::var("TEST_DATABASE_URI").expect("TEST_DATABASE_URI isn't set");
+
+    let (client, conn) = tokio_postgres::connect(&uri, NoTls).await.unwrap();
+    tokio::spawn(conn);
+
+    // create_schema builds indexes concurrently, which can't be done in its transaction, so only
+    // the tables used here are created
+    client
+        .batch_execute(
+            r#"
+BEGIN;
+-- tests run concurrently, and concurrent CREATE TABLE IF NOT EXISTS can conflict
+SELECT pg_advisory_xact_lock(0);
+CREATE TABLE IF NOT EXISTS guilds("guild_id" int8 NOT NULL UNIQUE, "data" jsonb NOT NULL, PRIMARY KEY("guild_id"));
+CREATE TABLE IF NOT EXISTS members("guild_id" int8 NOT NULL, "user_id" int8 NOT NULL, "data" jsonb NOT NULL, PRIMARY KEY("guild_id", "user_id"));
+CREATE TABLE IF NOT EXISTS roles("role_id" int8 NOT NULL UNIQUE, "guild_id" int8 NOT NULL, "data" jsonb NOT NULL, PRIMARY KEY("role_id", "guild_id"));
+CREATE TABLE IF NOT EXISTS emojis("emoji_id" int8 NOT NULL UNIQUE, "guild_id" int8 NOT NULL, "data" jsonb NOT NULL, PRIMARY KEY("emoji_id", "guild_id"));
+COMMIT;
+"#,
+        )
+        .await
+        .unwrap();
+
+    let cache = PostgresCache::connect(uri, Options::default(), 1)
+        .await
+        .unwrap();
+
+    (cache, client)
+}
+
+fn guild(id: &str, update: Value) -> Guild {
+    let mut guild = json!({
+        "id": id,
+        "name": "guild",
+        "icon": null,
+        "owner_id": "2",
+        "region": "europe",
+        "afk_timeout": 300,
+        "verification_level": 0,
+        "default_message_notifications": 0,
+        "explicit_content_filter": 0,
+        "roles": [],
+        "emojis": [],
+        "features": [],
+        "mfa_level": 0,
+        "application_id": null,
+        "rules_channel_id": null,
+        "premium_tier": 0,
+        "preferred_locale": "en-US",
+        "nsfw_level": 0
+    });
+
+    for (key, value) in update.as_object().unwrap() {
+        guild[key] = value.clone();
+    }
+
+    serde_json::from_value(guild).unwrap()
+}
+
+async fn cached_data(client: &Client, query: &str, ids: &[i64]) -> Value {
+    let params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = ids
+        .iter()
+        .map(|id| id as &(dyn tokio_postgres::types::ToSql + Sync))
+        .collect();
+
+    client.query_one(query, &params).await.unwrap().get(0)
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn guild_updates_clear_fields_set_to_null() {
+    let (cache, client) = connect().await;
+
+    let id = 9_000_001;
+    client
+        .execute(r#"DELETE FROM guilds WHERE "guild_id" = $1;"#, &[&id])
+        .await
+        .unwrap();
+
+    let create = guild(
+        "9000001",
+        json!({
+            "afk_channel_id": "3",
+            "system_channel_id": "4",
+            "banner": "a1b2c3",
+            "description": "a guild",
+            "vanity_url_code": "guild",
+            "joined_at": "2021-01-01T00:00:00+00:00",
+            "large": false,
+            "member_count": 10
+        }),
+    );
+    cache.store_guild(create).await.unwrap();
+
+    let update = guild(
+        "9000001",
+        json!({
+            "name": "renamed",
+            "afk_channel_id": null,
+            "system_channel_id": null,
+            "banner": null,
+            "description": null,
+            "vanity_url_code": null
+        }),
+    );
+    cache.update_guild(update).await.unwrap();
+
+    let data = cached_data(
+        &client,
+        r#"SELECT "data" FROM guilds WHERE "guild_id" = $1;"#,
+        &[id],
+    )
+    .await;
+
+    assert_eq!(data["name"], json!("renamed"));
+    for field in &[
+        "afk_channel_id",
+        "system_channel_id",
+        "banner",
+        "description",
+        "vanity_url_code",
+    ] {
+        assert!(data[field].is_null(), "{} wasn't cleared: {}", field, data);
+    }
+
+    // only sent in GUILD_CREATE
+    assert_eq!(data["member_count"], json!(10));
+    assert_eq!(data["large"], json!(false));
+    assert!(!data["joined_at"].is_null());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn member_updates_clear_fields_set_to_null() {
+    let (cache, client) = connect().await;
+
+    let (guild_id, user_id) = (9_000_002, 9_000_003);
+    client
+        .execute(
+            r#"DELETE FROM members WHERE "guild_id" = $1 AND "user_id" = $2;"#,
+            &[&guild_id, &user_id],
+        )
+        .await
+        .unwrap();
+
+    let user =
+        json!({ "id": "9000003", "username": "user", "discriminator": "0001", "avatar": null });
+    let member: Member = serde_json::from_value(json!({
+        "user": user,
+        "nick": "nick",
+        "roles": ["5"],
+        "joined_at": "2021-01-01T00:00:00+00:00",
+        "premium_since": "2021-02-01T00:00:00+00:00",
+        "deaf": true,
+        "mute": true
+    }))
+    .unwrap();
+    cache
+        .store_member(member, Snowflake(guild_id as u64))
+        .await
+        .unwrap();
+
+    let update: MemberUpdate = serde_json::from_value(json!({
+        "user": user,
+        "nick": null,
+        "roles": [],
+        "joined_at": "2021-01-01T00:00:00+00:00",
+        "premium_since": null,
+        "mute": false
+    }))
+    .unwrap();
+    cache
+        .update_member(update, Snowflake(guild_id as u64))
+        .await
+        .unwrap();
+
+    let data = cached_data(
+        &client,
+        r#"SELECT "data" FROM members WHERE "guild_id" = $1 AND "user_id" = $2;"#,
+        &[guild_id, user_id],
+    )
+    .await;
+
+    assert!(data["nick"].is_null());
+    assert!(data["premium_since"].is_null());
+    assert_eq!(data["roles"], json!([]));
+    assert_eq!(data["mute"], json!(false));
+
+    // not sent, so kept
+    assert_eq!(data["deaf"], json!(true));
+}
@@ -1,13 +1,36 @@
-use serde::Serialize;
+use model::Snowflake;
+use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 
 pub const EVENT_KEY: &str = "tickets:events";
 
-#[derive(Serialize, Debug)]
+/// Name of the field holding the serialized `Event` in each stream entry
+pub const EVENT_FIELD: &str = "event";
+
+/// Redis list holding events that could not be delivered to workers
+pub const DEAD_LETTER_KEY: &str = "tickets:events:deadletter";
+
+/// Redis list holding the dead letter being replayed, so that it isn't lost if the replay dies
+pub const DEAD_LETTER_PROCESSING_KEY: &str = "tickets:events:deadletter:processing";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Event<'a> {
     pub bot_token: &'a str,
     pub bot_id: u64,
     pub is_whitelabel: bool,
     pub shard_id: u16,
+    #[serde(borrow)]
     pub event: &'a RawValue,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeadLetter<'a> {
+    #[serde(borrow)]
+    pub event: Event<'a>,
+    pub guild_id: Option<Snowflake>,
+    pub error: String,
+}
+
+pub fn partitioned_key(partition: u16) -> String {
+    format!("{}:{}", EVENT_KEY, partition)
+}
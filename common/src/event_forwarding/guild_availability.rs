@@ -0,0 +1,16 @@
+use model::Snowflake;
+use serde::{Deserialize, Serialize};
+
+/// Forwarded by the sharder when Discord reports an outage in a guild
+pub const GUILD_UNAVAILABLE: &str = "GUILD_UNAVAILABLE";
+
+/// Forwarded by the sharder when a guild that had an outage is received again
+pub const GUILD_AVAILABLE: &str = "GUILD_AVAILABLE";
+
+/// The data of GUILD_UNAVAILABLE and GUILD_AVAILABLE. These aren't sent by the gateway, but are
+/// forwarded in the same form as its dispatches (`{"op": 0, "t": ..., "d": ...}`), without a
+/// sequence number.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GuildAvailability {
+    pub guild_id: Snowflake,
+}
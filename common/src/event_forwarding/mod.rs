@@ -3,3 +3,6 @@ pub use event::*;
 
 mod interaction;
 pub use interaction::*;
+
+mod guild_availability;
+pub use guild_availability::*;
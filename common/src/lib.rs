@@ -1,3 +1,3 @@
 pub mod event_forwarding;
-pub mod status_updates;
-pub mod token_change;
+pub mod request_guild_members;
+pub mod whitelabel_control;
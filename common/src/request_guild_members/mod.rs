@@ -0,0 +1,20 @@
+use model::Snowflake;
+use serde::{Deserialize, Serialize};
+
+pub const KEY: &str = "tickets:requestguildmembers";
+
+/// Asks the sharder holding the given bot and guild to request members from the gateway, which
+/// are then written to the cache. If user_ids is set, query and limit are ignored.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Payload {
+    pub bot_id: Snowflake,
+    pub guild_id: Snowflake,
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u16>,
+    #[serde(default)]
+    pub user_ids: Option<Vec<Snowflake>>,
+    #[serde(default)]
+    pub presences: bool,
+}
@@ -1,2 +0,0 @@
-mod status_update;
-pub use status_update::*;
@@ -0,0 +1,60 @@
+use model::Snowflake;
+use serde::{Deserialize, Serialize};
+
+/// Commands for whitelabel bots are published here, and are handled by the sharder holding the
+/// bot's lease. Start and Restart are handled by a sharder with spare capacity if no sharder holds
+/// the lease, and go unacknowledged if none has capacity.
+pub const KEY: &str = "tickets:whitelabel:control";
+
+/// The sharder that handled a command publishes an Ack here once it has taken effect, or failed
+pub const ACK_KEY: &str = "tickets:whitelabel:control:ack";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Request {
+    /// Chosen by the publisher, and echoed in the Ack
+    pub request_id: String,
+    pub bot_id: Snowflake,
+    pub command: Command,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    /// Connects the bot, reading its token from the database. Succeeds if it's already running.
+    Start,
+    /// Disconnects the bot, without removing it from the database. The bot stays stopped, across
+    /// sharder restarts and wherever its lease moves, until it's started or restarted.
+    Stop,
+    /// Reconnects the bot with a fresh session, starting it if it isn't running
+    Restart,
+    /// Reads the bot's presence from the database and pushes it to the gateway
+    ReloadStatus,
+    /// Reads the bot's intents and forwarded events from the database. Intents are sent with the
+    /// identify, so the bot is reconnected with a fresh session.
+    ReloadIntents,
+    /// Succeeds if the bot is running on the sharder
+    Ping,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Ack {
+    pub request_id: String,
+    pub bot_id: Snowflake,
+    pub sharder_id: u16,
+    pub success: bool,
+    /// Why the command failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Ack {
+    pub fn new(request: &Request, sharder_id: u16, result: Result<(), String>) -> Ack {
+        Ack {
+            request_id: request.request_id.clone(),
+            bot_id: request.bot_id,
+            sharder_id,
+            success: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
@@ -2,7 +2,8 @@ use sqlx::postgres::PgPoolOptions;
 use std::sync::Arc;
 
 use crate::{
-    Table, Whitelabel, WhitelabelErrorTable, WhitelabelGuilds, WhitelabelKeys, WhitelabelStatus,
+    Table, Whitelabel, WhitelabelErrorTable, WhitelabelForwardedEvents, WhitelabelGuilds,
+    WhitelabelIntents, WhitelabelKeys, WhitelabelLeases, WhitelabelStatus, WhitelabelStopped,
 };
 
 pub struct Database {
@@ -11,6 +12,10 @@ pub struct Database {
     pub whitelabel_guilds: WhitelabelGuilds,
     pub whitelabel_status: WhitelabelStatus,
     pub whitelabel_keys: WhitelabelKeys,
+    pub whitelabel_forwarded_events: WhitelabelForwardedEvents,
+    pub whitelabel_intents: WhitelabelIntents,
+    pub whitelabel_leases: WhitelabelLeases,
+    pub whitelabel_stopped: WhitelabelStopped,
 }
 
 impl Database {
@@ -23,6 +28,10 @@ impl Database {
             whitelabel_guilds: WhitelabelGuilds::new(Arc::clone(&pool)),
             whitelabel_status: WhitelabelStatus::new(Arc::clone(&pool)),
             whitelabel_keys: WhitelabelKeys::new(Arc::clone(&pool)),
+            whitelabel_forwarded_events: WhitelabelForwardedEvents::new(Arc::clone(&pool)),
+            whitelabel_intents: WhitelabelIntents::new(Arc::clone(&pool)),
+            whitelabel_leases: WhitelabelLeases::new(Arc::clone(&pool)),
+            whitelabel_stopped: WhitelabelStopped::new(Arc::clone(&pool)),
         })
     }
 
@@ -32,6 +41,10 @@ impl Database {
         self.whitelabel_guilds.create_schema().await?;
         self.whitelabel_status.create_schema().await?;
         self.whitelabel_keys.create_schema().await?;
+        self.whitelabel_forwarded_events.create_schema().await?;
+        self.whitelabel_intents.create_schema().await?;
+        self.whitelabel_leases.create_schema().await?;
+        self.whitelabel_stopped.create_schema().await?;
 
         Ok(())
     }
@@ -10,14 +10,29 @@ pub use whitelabel::*;
 mod whitelabel_error;
 pub use whitelabel_error::*;
 
+mod whitelabel_forwarded_events;
+pub use whitelabel_forwarded_events::WhitelabelForwardedEvents;
+
+mod whitelabel_intents;
+pub use whitelabel_intents::WhitelabelIntents;
+
 mod whitelabel_guilds;
 pub use whitelabel_guilds::WhitelabelGuilds;
 
 mod whitelabel_status;
-pub use whitelabel_status::WhitelabelStatus;
+pub use whitelabel_status::{WhitelabelPresence, WhitelabelStatus};
 
 mod whitelabel_keys;
 pub use whitelabel_keys::WhitelabelKeys;
 
+mod whitelabel_leases;
+pub use whitelabel_leases::{Lease, WhitelabelLeases};
+
+mod whitelabel_stopped;
+pub use whitelabel_stopped::WhitelabelStopped;
+
+#[cfg(feature = "test-support")]
+pub mod test_support;
+
 // re-export sqlx for errors etc
 pub use sqlx;
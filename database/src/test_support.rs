@@ -0,0 +1,66 @@
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Executor, PgPool};
+
+use crate::{Database, WhitelabelBot};
+
+/// A database for a single test, in a schema of its own, so that tests running at the same time
+/// don't claim each other's bots. Connects to the database given by TEST_DATABASE_URI.
+pub struct TestDatabase {
+    pub uri: String,
+    pub schema: &'static str,
+    // search_path is set to the test's schema
+    pub pool: PgPool,
+}
+
+impl TestDatabase {
+    /// Recreates the schema with every table, and inserts a bot for each ID, owned by the user
+    /// with the same ID
+    pub async fn create(schema: &'static str, bot_ids: &[u64]) -> TestDatabase {
+        let uri = std::env::var("TEST_DATABASE_URI").expect("TEST_DATABASE_URI isn't set");
+
+        let pool = pool_options(schema).connect(&uri).await.unwrap();
+        pool.execute(&*format!(
+            "DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0};",
+            schema
+        ))
+        .await
+        .unwrap();
+
+        let test_database = TestDatabase { uri, schema, pool };
+
+        let database = test_database.connect().await;
+        database.create_schema().await.unwrap();
+
+        for &bot_id in bot_ids {
+            database
+                .whitelabel
+                .insert(WhitelabelBot {
+                    user_id: bot_id as i64,
+                    bot_id: bot_id as i64,
+                    token: format!("token-{}", bot_id),
+                })
+                .await
+                .unwrap();
+        }
+
+        test_database
+    }
+
+    pub async fn connect(&self) -> Database {
+        Database::connect(&self.uri, pool_options(self.schema))
+            .await
+            .unwrap()
+    }
+}
+
+fn pool_options(schema: &'static str) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(2)
+        .after_connect(move |conn| {
+            Box::pin(async move {
+                conn.execute(&*format!("SET search_path TO {}", schema))
+                    .await?;
+                Ok(())
+            })
+        })
+}
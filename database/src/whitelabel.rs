@@ -1,11 +1,10 @@
 use async_trait::async_trait;
 
-use sqlx::{Error, PgPool};
+use sqlx::{Error, Executor, PgPool};
 use std::sync::Arc;
 
 use crate::Table;
 
-use futures::TryStreamExt;
 use model::Snowflake;
 
 #[derive(sqlx::FromRow, Debug)]
@@ -22,8 +21,10 @@ pub struct Whitelabel {
 #[async_trait]
 impl Table for Whitelabel {
     async fn create_schema(&self) -> Result<(), Error> {
-        sqlx::query(
-            r#"
+        // several statements can't be sent as a prepared query
+        self.db
+            .execute(
+                r#"
 CREATE TABLE IF NOT EXISTS whitelabel(
 	"user_id" int8 UNIQUE NOT NULL,
 	"bot_id" int8 UNIQUE NOT NULL,
@@ -31,10 +32,9 @@ CREATE TABLE IF NOT EXISTS whitelabel(
 	PRIMARY KEY("user_id")
 );
 CREATE INDEX IF NOT EXISTS whitelabel_bot_id ON whitelabel("bot_id");
-        "#,
-        )
-        .execute(&*self.db)
-        .await?;
+"#,
+            )
+            .await?;
 
         Ok(())
     }
@@ -87,24 +87,11 @@ impl Whitelabel {
         }
     }
 
-    pub async fn get_bots_by_sharder(
-        &self,
-        sharder_count: u16,
-        sharder_id: u16,
-    ) -> Result<Vec<WhitelabelBot>, Error> {
-        let query = r#"SELECT * FROM whitelabel WHERE "bot_id" % $1 = $2"#;
-
-        let mut rows = sqlx::query_as::<_, WhitelabelBot>(query)
-            .bind(sharder_count as i32)
-            .bind(sharder_id as i32)
-            .fetch(&*self.db);
-
-        let mut bots = Vec::new();
-        while let Some(row) = rows.try_next().await? {
-            bots.push(row);
-        }
+    pub async fn count(&self) -> Result<usize, Error> {
+        let query = r#"SELECT COUNT(*) FROM whitelabel;"#;
 
-        Ok(bots)
+        let (count,): (i64,) = sqlx::query_as(query).fetch_one(&*self.db).await?;
+        Ok(count as usize)
     }
 
     pub async fn insert(&self, bot: WhitelabelBot) -> Result<(), Error> {
@@ -116,7 +103,7 @@ VALUES
 ON CONFLICT("user_id") DO
     UPDATE
         SET "bot_id" = $2,
-            "token" = $3";
+            "token" = $3;
 "#;
 
         sqlx::query(query)
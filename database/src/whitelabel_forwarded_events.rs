@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+
+use sqlx::{Error, PgPool};
+use std::sync::Arc;
+
+use crate::Table;
+
+use futures::TryStreamExt;
+use model::Snowflake;
+
+pub struct WhitelabelForwardedEvents {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl Table for WhitelabelForwardedEvents {
+    async fn create_schema(&self) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+CREATE TABLE IF NOT EXISTS whitelabel_forwarded_events(
+	"bot_id" int8 NOT NULL,
+	"event_name" varchar(64) NOT NULL,
+	FOREIGN KEY("bot_id") REFERENCES whitelabel("bot_id") ON DELETE CASCADE ON UPDATE CASCADE,
+	PRIMARY KEY("bot_id", "event_name")
+);
+"#,
+        )
+        .execute(&*self.db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl WhitelabelForwardedEvents {
+    pub fn new(db: Arc<PgPool>) -> WhitelabelForwardedEvents {
+        WhitelabelForwardedEvents { db }
+    }
+
+    /// An empty vec means the bot uses the sharder's default event set
+    pub async fn get(&self, bot_id: Snowflake) -> Result<Vec<String>, Error> {
+        let query = r#"SELECT "event_name" FROM whitelabel_forwarded_events WHERE "bot_id" = $1;"#;
+
+        let mut rows = sqlx::query_as::<_, (String,)>(query)
+            .bind(bot_id.0 as i64)
+            .fetch(&*self.db);
+
+        let mut events = Vec::new();
+        while let Some(row) = rows.try_next().await? {
+            events.push(row.0);
+        }
+
+        Ok(events)
+    }
+
+    pub async fn insert(&self, bot_id: Snowflake, event_name: String) -> Result<(), Error> {
+        let query = r#"INSERT INTO whitelabel_forwarded_events("bot_id", "event_name") VALUES($1, $2) ON CONFLICT("bot_id", "event_name") DO NOTHING;"#;
+
+        sqlx::query(query)
+            .bind(bot_id.0 as i64)
+            .bind(event_name)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, bot_id: Snowflake, event_name: String) -> Result<(), Error> {
+        let query =
+            r#"DELETE FROM whitelabel_forwarded_events WHERE "bot_id" = $1 AND "event_name" = $2;"#;
+
+        sqlx::query(query)
+            .bind(bot_id.0 as i64)
+            .bind(event_name)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+}
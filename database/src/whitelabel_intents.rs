@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+
+use sqlx::{Error, PgPool};
+use std::sync::Arc;
+
+use crate::Table;
+
+use model::Snowflake;
+
+pub struct WhitelabelIntents {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl Table for WhitelabelIntents {
+    async fn create_schema(&self) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+CREATE TABLE IF NOT EXISTS whitelabel_intents(
+	"bot_id" int8 UNIQUE NOT NULL,
+	"intents" int8 NOT NULL,
+	FOREIGN KEY("bot_id") REFERENCES whitelabel("bot_id") ON DELETE CASCADE ON UPDATE CASCADE,
+	PRIMARY KEY("bot_id")
+);
+"#,
+        )
+        .execute(&*self.db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl WhitelabelIntents {
+    pub fn new(db: Arc<PgPool>) -> WhitelabelIntents {
+        WhitelabelIntents { db }
+    }
+
+    pub async fn get(&self, bot_id: Snowflake) -> Result<Option<u64>, Error> {
+        let query = r#"SELECT "intents" FROM whitelabel_intents WHERE "bot_id" = $1;"#;
+
+        match sqlx::query_as::<_, (i64,)>(query)
+            .bind(bot_id.0 as i64)
+            .fetch_one(&*self.db)
+            .await
+        {
+            Ok(row) => Ok(Some(row.0 as u64)),
+            Err(sqlx::Error::RowNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn set(&self, bot_id: Snowflake, intents: u64) -> Result<(), Error> {
+        let query = r#"INSERT INTO whitelabel_intents("bot_id", "intents") VALUES($1, $2) ON CONFLICT("bot_id") DO UPDATE SET "intents" = $2;"#;
+
+        sqlx::query(query)
+            .bind(bot_id.0 as i64)
+            .bind(intents as i64)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, bot_id: Snowflake) -> Result<(), Error> {
+        let query = r#"DELETE FROM whitelabel_intents WHERE "bot_id" = $1;"#;
+
+        sqlx::query(query)
+            .bind(bot_id.0 as i64)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use async_trait::async_trait;
+
+use sqlx::{Error, Executor, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Table, WhitelabelBot};
+
+use futures::TryStreamExt;
+use model::Snowflake;
+
+/// Which whitelabel sharder runs each bot. A sharder heartbeats its row in whitelabel_sharders
+/// and renews its leases, and bots whose leases have expired may be claimed by any sharder.
+pub struct WhitelabelLeases {
+    db: Arc<PgPool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lease {
+    pub bot_id: Snowflake,
+    /// Whether the bot has been stopped through the control channel, and so shouldn't be started
+    pub stopped: bool,
+}
+
+#[async_trait]
+impl Table for WhitelabelLeases {
+    async fn create_schema(&self) -> Result<(), Error> {
+        // several statements can't be sent as a prepared query
+        self.db
+            .execute(
+                r#"
+CREATE TABLE IF NOT EXISTS whitelabel_sharders(
+	"sharder_id" int4 NOT NULL,
+	"expires_at" timestamptz NOT NULL,
+	PRIMARY KEY("sharder_id")
+);
+CREATE TABLE IF NOT EXISTS whitelabel_leases(
+	"bot_id" int8 UNIQUE NOT NULL,
+	"sharder_id" int4 NOT NULL,
+	"expires_at" timestamptz NOT NULL,
+	FOREIGN KEY("bot_id") REFERENCES whitelabel("bot_id") ON DELETE CASCADE ON UPDATE CASCADE,
+	PRIMARY KEY("bot_id")
+);
+CREATE INDEX IF NOT EXISTS whitelabel_leases_sharder_id ON whitelabel_leases("sharder_id");
+"#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl WhitelabelLeases {
+    pub fn new(db: Arc<PgPool>) -> WhitelabelLeases {
+        WhitelabelLeases { db }
+    }
+
+    /// Marks the sharder as live for the given duration, so that it's counted when spreading bots
+    pub async fn heartbeat(&self, sharder_id: u16, ttl: Duration) -> Result<(), Error> {
+        let query = r#"
+INSERT INTO whitelabel_sharders
+    ("sharder_id", "expires_at")
+VALUES
+    ($1, NOW() + $2 * INTERVAL '1 millisecond')
+ON CONFLICT("sharder_id") DO
+    UPDATE
+        SET "expires_at" = EXCLUDED."expires_at";
+"#;
+
+        sqlx::query(query)
+            .bind(sharder_id as i32)
+            .bind(ttl.as_millis() as i64)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn count_live_sharders(&self) -> Result<usize, Error> {
+        let query = r#"SELECT COUNT(*) FROM whitelabel_sharders WHERE "expires_at" > NOW();"#;
+
+        let (count,): (i64,) = sqlx::query_as(query).fetch_one(&*self.db).await?;
+        Ok(count as usize)
+    }
+
+    /// Counts the bots that should be running, i.e. those that haven't been stopped
+    pub async fn count_runnable(&self) -> Result<usize, Error> {
+        let query = r#"
+SELECT COUNT(*) FROM whitelabel
+    WHERE NOT EXISTS(SELECT 1 FROM whitelabel_stopped WHERE whitelabel_stopped."bot_id" = whitelabel."bot_id");
+"#;
+
+        let (count,): (i64,) = sqlx::query_as(query).fetch_one(&*self.db).await?;
+        Ok(count as usize)
+    }
+
+    /// Extends every lease held by the sharder, returning the bots it still holds. Leases that
+    /// expired without being claimed by another sharder are kept.
+    pub async fn renew(&self, sharder_id: u16, ttl: Duration) -> Result<Vec<Lease>, Error> {
+        let query = r#"
+UPDATE whitelabel_leases
+    SET "expires_at" = NOW() + $2 * INTERVAL '1 millisecond'
+    WHERE "sharder_id" = $1
+RETURNING "bot_id", EXISTS(SELECT 1 FROM whitelabel_stopped WHERE whitelabel_stopped."bot_id" = whitelabel_leases."bot_id");
+"#;
+
+        let mut rows = sqlx::query_as::<_, (i64, bool)>(query)
+            .bind(sharder_id as i32)
+            .bind(ttl.as_millis() as i64)
+            .fetch(&*self.db);
+
+        let mut leases = Vec::new();
+        while let Some(row) = rows.try_next().await? {
+            leases.push(Lease {
+                bot_id: Snowflake(row.0 as u64),
+                stopped: row.1,
+            });
+        }
+
+        Ok(leases)
+    }
+
+    /// Claims up to limit bots that no sharder holds a live lease for, and that haven't been
+    /// stopped
+    pub async fn claim(
+        &self,
+        sharder_id: u16,
+        ttl: Duration,
+        limit: usize,
+    ) -> Result<Vec<WhitelabelBot>, Error> {
+        // bots are picked at random so that sharders claiming at the same time rarely contend
+        let query = r#"
+WITH claimed AS (
+    INSERT INTO whitelabel_leases
+        ("bot_id", "sharder_id", "expires_at")
+    SELECT whitelabel."bot_id", $1, NOW() + $2 * INTERVAL '1 millisecond'
+        FROM whitelabel
+        LEFT JOIN whitelabel_leases ON whitelabel_leases."bot_id" = whitelabel."bot_id"
+        WHERE (whitelabel_leases."bot_id" IS NULL OR whitelabel_leases."expires_at" < NOW())
+            AND NOT EXISTS(SELECT 1 FROM whitelabel_stopped WHERE whitelabel_stopped."bot_id" = whitelabel."bot_id")
+        ORDER BY RANDOM()
+        LIMIT $3
+    ON CONFLICT("bot_id") DO
+        UPDATE
+            SET "sharder_id" = EXCLUDED."sharder_id",
+                "expires_at" = EXCLUDED."expires_at"
+            WHERE whitelabel_leases."expires_at" < NOW()
+    RETURNING "bot_id"
+)
+SELECT whitelabel.* FROM whitelabel INNER JOIN claimed ON claimed."bot_id" = whitelabel."bot_id";
+"#;
+
+        let mut rows = sqlx::query_as::<_, WhitelabelBot>(query)
+            .bind(sharder_id as i32)
+            .bind(ttl.as_millis() as i64)
+            .bind(limit as i64)
+            .fetch(&*self.db);
+
+        let mut bots = Vec::new();
+        while let Some(row) = rows.try_next().await? {
+            bots.push(row);
+        }
+
+        Ok(bots)
+    }
+
+    /// Claims the bot if no sharder holds a live lease for it, or if the sharder already holds
+    /// it, whether or not it has been stopped. Returns false if another sharder holds it, or it
+    /// doesn't exist.
+    pub async fn claim_bot(
+        &self,
+        sharder_id: u16,
+        ttl: Duration,
+        bot_id: Snowflake,
+    ) -> Result<bool, Error> {
+        let query = r#"
+INSERT INTO whitelabel_leases
+    ("bot_id", "sharder_id", "expires_at")
+SELECT "bot_id", $1, NOW() + $2 * INTERVAL '1 millisecond'
+    FROM whitelabel
+    WHERE "bot_id" = $3
+ON CONFLICT("bot_id") DO
+    UPDATE
+        SET "sharder_id" = EXCLUDED."sharder_id",
+            "expires_at" = EXCLUDED."expires_at"
+        WHERE whitelabel_leases."expires_at" < NOW() OR whitelabel_leases."sharder_id" = $1
+RETURNING "bot_id";
+"#;
+
+        let claimed = sqlx::query(query)
+            .bind(sharder_id as i32)
+            .bind(ttl.as_millis() as i64)
+            .bind(bot_id.0 as i64)
+            .fetch_optional(&*self.db)
+            .await?;
+
+        Ok(claimed.is_some())
+    }
+
+    /// Gives up the sharder's leases on the given bots, so that other sharders can claim them
+    pub async fn release(&self, sharder_id: u16, bot_ids: &[Snowflake]) -> Result<(), Error> {
+        let query =
+            r#"DELETE FROM whitelabel_leases WHERE "sharder_id" = $1 AND "bot_id" = ANY($2);"#;
+
+        let bot_ids: Vec<i64> = bot_ids.iter().map(|id| id.0 as i64).collect();
+
+        sqlx::query(query)
+            .bind(sharder_id as i32)
+            .bind(bot_ids)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+}
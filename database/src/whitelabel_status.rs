@@ -11,6 +11,18 @@ pub struct WhitelabelStatus {
     db: Arc<PgPool>,
 }
 
+/// A whitelabel bot's presence. The sharder rotates through `status` followed by
+/// `rotating_statuses`.
+#[derive(sqlx::FromRow, Debug)]
+pub struct WhitelabelPresence {
+    pub status: String,
+    // ActivityType, e.g. 2 for listening
+    pub activity_type: i16,
+    // StatusType, e.g. online
+    pub status_type: String,
+    pub rotating_statuses: Vec<String>,
+}
+
 #[async_trait]
 impl Table for WhitelabelStatus {
     async fn create_schema(&self) -> Result<(), Error> {
@@ -27,6 +39,20 @@ CREATE TABLE IF NOT EXISTS whitelabel_statuses(
         .execute(&*self.db)
         .await?;
 
+        // added after the table was first created
+        for column in &[
+            r#""activity_type" int2 NOT NULL DEFAULT 2"#,
+            r#""status_type" varchar(9) NOT NULL DEFAULT 'online'"#,
+            r#""rotating_statuses" varchar(255)[] NOT NULL DEFAULT '{}'"#,
+        ] {
+            let query = format!(
+                "ALTER TABLE whitelabel_statuses ADD COLUMN IF NOT EXISTS {};",
+                column
+            );
+
+            sqlx::query(&query).execute(&*self.db).await?;
+        }
+
         Ok(())
     }
 }
@@ -47,6 +73,45 @@ impl WhitelabelStatus {
         Ok(row.0)
     }
 
+    pub async fn get_presence(
+        &self,
+        bot_id: Snowflake,
+    ) -> Result<Option<WhitelabelPresence>, Error> {
+        let query = r#"SELECT "status", "activity_type", "status_type", "rotating_statuses" FROM whitelabel_statuses WHERE "bot_id" = $1;"#;
+
+        match sqlx::query_as::<_, WhitelabelPresence>(query)
+            .bind(bot_id.0 as i64)
+            .fetch_one(&*self.db)
+            .await
+        {
+            Ok(presence) => Ok(Some(presence)),
+            Err(sqlx::Error::RowNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn set_presence(
+        &self,
+        bot_id: Snowflake,
+        presence: &WhitelabelPresence,
+    ) -> Result<(), Error> {
+        let query = r#"
+INSERT INTO whitelabel_statuses("bot_id", "status", "activity_type", "status_type", "rotating_statuses")
+VALUES($1, $2, $3, $4, $5)
+ON CONFLICT("bot_id") DO UPDATE SET "status" = $2, "activity_type" = $3, "status_type" = $4, "rotating_statuses" = $5;"#;
+
+        sqlx::query(query)
+            .bind(bot_id.0 as i64)
+            .bind(&presence.status)
+            .bind(presence.activity_type)
+            .bind(&presence.status_type)
+            .bind(&presence.rotating_statuses)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn set(&self, bot_id: Snowflake, status: String) -> Result<(), Error> {
         let query = r#"INSERT INTO whitelabel_statuses("bot_id", "status") VALUES($1, $2) ON CONFLICT("bot_id") DO UPDATE SET "status" = $2;"#;
 
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+
+use sqlx::{Error, PgPool};
+use std::sync::Arc;
+
+use crate::Table;
+
+use model::Snowflake;
+
+/// Whitelabel bots stopped through the control channel, which aren't started again, by any
+/// sharder, until they're started or restarted through it
+pub struct WhitelabelStopped {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl Table for WhitelabelStopped {
+    async fn create_schema(&self) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+CREATE TABLE IF NOT EXISTS whitelabel_stopped(
+	"bot_id" int8 UNIQUE NOT NULL,
+	FOREIGN KEY("bot_id") REFERENCES whitelabel("bot_id") ON DELETE CASCADE ON UPDATE CASCADE,
+	PRIMARY KEY("bot_id")
+);
+"#,
+        )
+        .execute(&*self.db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl WhitelabelStopped {
+    pub fn new(db: Arc<PgPool>) -> WhitelabelStopped {
+        WhitelabelStopped { db }
+    }
+
+    pub async fn insert(&self, bot_id: Snowflake) -> Result<(), Error> {
+        let query = r#"INSERT INTO whitelabel_stopped("bot_id") VALUES($1) ON CONFLICT("bot_id") DO NOTHING;"#;
+
+        sqlx::query(query)
+            .bind(bot_id.0 as i64)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, bot_id: Snowflake) -> Result<(), Error> {
+        let query = r#"DELETE FROM whitelabel_stopped WHERE "bot_id" = $1;"#;
+
+        sqlx::query(query)
+            .bind(bot_id.0 as i64)
+            .execute(&*self.db)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! Runs against the database given by TEST_DATABASE_URI, e.g.
+//! postgres://postgres@localhost/postgres. The tests are ignored unless run with
+//! `cargo test -- --ignored`, so that they're reported as skipped where there's no database.
+
+use database::test_support::TestDatabase;
+use database::{Database, Lease};
+use model::Snowflake;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time::sleep;
+
+const TTL: Duration = Duration::from_secs(30);
+
+async fn connect(schema: &'static str, bot_ids: &[u64]) -> Database {
+    TestDatabase::create(schema, bot_ids).await.connect().await
+}
+
+async fn claim(database: &Database, sharder_id: u16, ttl: Duration, limit: usize) -> HashSet<u64> {
+    database
+        .whitelabel_leases
+        .claim(sharder_id, ttl, limit)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|bot| bot.bot_id as u64)
+        .collect()
+}
+
+async fn renew(database: &Database, sharder_id: u16) -> Vec<Lease> {
+    let mut leases = database
+        .whitelabel_leases
+        .renew(sharder_id, TTL)
+        .await
+        .unwrap();
+
+    leases.sort_by_key(|lease| lease.bot_id.0);
+    leases
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn claims_bots_without_a_lease() {
+    let database = connect("test_leases_claim", &[1, 2, 3]).await;
+
+    let first = claim(&database, 1, TTL, 2).await;
+    assert_eq!(first.len(), 2);
+
+    // only the bot the first sharder didn't claim is left
+    let second = claim(&database, 2, TTL, 5).await;
+    assert_eq!(second.len(), 1);
+    assert!(first.is_disjoint(&second));
+
+    assert!(claim(&database, 1, TTL, 5).await.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn claims_bots_whose_leases_have_expired() {
+    let database = connect("test_leases_expiry", &[1, 2]).await;
+
+    assert_eq!(
+        claim(&database, 1, Duration::from_millis(1), 1).await.len(),
+        1
+    );
+    assert_eq!(claim(&database, 1, TTL, 1).await.len(), 1);
+    sleep(Duration::from_millis(50)).await;
+
+    // only the expired lease is taken over
+    let taken = claim(&database, 2, TTL, 5).await;
+    assert_eq!(taken.len(), 1);
+
+    let taken = Snowflake(*taken.iter().next().unwrap());
+    assert!(renew(&database, 1)
+        .await
+        .iter()
+        .all(|lease| lease.bot_id != taken));
+    assert!(!database
+        .whitelabel_leases
+        .claim_bot(1, TTL, taken)
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn claims_single_bots() {
+    let database = connect("test_leases_claim_bot", &[1]).await;
+
+    let leases = &database.whitelabel_leases;
+    assert!(leases.claim_bot(1, TTL, Snowflake(1)).await.unwrap());
+
+    // held by the sharder already, rather than by another
+    assert!(leases.claim_bot(1, TTL, Snowflake(1)).await.unwrap());
+    assert!(!leases.claim_bot(2, TTL, Snowflake(1)).await.unwrap());
+
+    assert!(!leases.claim_bot(1, TTL, Snowflake(2)).await.unwrap());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn renews_held_leases() {
+    let database = connect("test_leases_renew", &[1, 2, 3]).await;
+
+    let leases = &database.whitelabel_leases;
+    assert!(leases.claim_bot(1, TTL, Snowflake(1)).await.unwrap());
+    assert!(leases
+        .claim_bot(1, Duration::from_millis(1), Snowflake(2))
+        .await
+        .unwrap());
+    assert!(leases.claim_bot(2, TTL, Snowflake(3)).await.unwrap());
+    database
+        .whitelabel_stopped
+        .insert(Snowflake(1))
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(50)).await;
+
+    // a lease that expired without being taken over is kept
+    assert_eq!(
+        renew(&database, 1).await,
+        vec![
+            Lease {
+                bot_id: Snowflake(1),
+                stopped: true
+            },
+            Lease {
+                bot_id: Snowflake(2),
+                stopped: false
+            },
+        ]
+    );
+
+    // and can't be taken over once renewed
+    assert!(!leases.claim_bot(2, TTL, Snowflake(2)).await.unwrap());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn released_leases_can_be_claimed() {
+    let database = connect("test_leases_release", &[1, 2]).await;
+
+    assert_eq!(claim(&database, 1, TTL, 2).await.len(), 2);
+    database
+        .whitelabel_leases
+        .release(1, &[Snowflake(1)])
+        .await
+        .unwrap();
+
+    assert_eq!(renew(&database, 1).await.len(), 1);
+    assert_eq!(claim(&database, 2, TTL, 2).await, [1].into());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn stopped_bots_are_not_claimed() {
+    let database = connect("test_leases_stopped", &[1, 2]).await;
+    database
+        .whitelabel_stopped
+        .insert(Snowflake(1))
+        .await
+        .unwrap();
+
+    assert_eq!(claim(&database, 1, TTL, 2).await, [2].into());
+
+    // unless they're started through the control channel
+    assert!(database
+        .whitelabel_leases
+        .claim_bot(1, TTL, Snowflake(1))
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn counts_live_sharders() {
+    let database = connect("test_leases_sharders", &[]).await;
+
+    let leases = &database.whitelabel_leases;
+    leases.heartbeat(1, TTL).await.unwrap();
+    leases.heartbeat(2, TTL).await.unwrap();
+    leases.heartbeat(3, Duration::from_millis(1)).await.unwrap();
+    sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(leases.count_live_sharders().await.unwrap(), 2);
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn counts_runnable_bots() {
+    let database = connect("test_leases_runnable", &[1, 2, 3]).await;
+    database
+        .whitelabel_stopped
+        .insert(Snowflake(1))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        database.whitelabel_leases.count_runnable().await.unwrap(),
+        2
+    );
+}
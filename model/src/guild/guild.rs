@@ -36,8 +36,7 @@ pub struct Guild {
     pub explicit_content_filter: ExplicitContentFilterLevel,
     #[serde(skip_serializing, default)]
     pub roles: Vec<Role>,
-    //#[serde(skip_serializing, default)]
-    #[serde(skip)]
+    #[serde(skip_serializing, default)]
     pub emojis: Vec<Emoji>,
     pub features: Vec<String>,
     pub mfa_level: MFALevel,
@@ -4,7 +4,7 @@ use crate::user::User;
 use crate::Snowflake;
 use chrono::{DateTime, Utc};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Member {
     #[serde(skip_serializing)]
     pub user: Option<User>,
@@ -18,3 +18,20 @@ pub struct Member {
     #[serde(default = "bool::default")]
     pub mute: bool,
 }
+
+/// The fields of a member sent in GUILD_MEMBER_UPDATE. Fields that are None weren't sent, so the
+/// cached values should be kept.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MemberUpdate {
+    #[serde(skip_serializing)]
+    pub user: User,
+    pub nick: Option<String>,
+    #[serde(serialize_with = "Snowflake::serialize_vec_to_ints")]
+    pub roles: Vec<Snowflake>,
+    pub joined_at: DateTime<Utc>,
+    pub premium_since: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deaf: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mute: Option<bool>,
+}
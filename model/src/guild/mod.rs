@@ -14,4 +14,4 @@ mod voice_state;
 pub use voice_state::VoiceState;
 
 mod member;
-pub use member::Member;
+pub use member::{Member, MemberUpdate};
@@ -1,10 +1,47 @@
 use serde_repr::{Deserialize_repr, Serialize_repr};
+use std::convert::TryFrom;
+use std::str::FromStr;
 
-#[derive(Serialize_repr, Deserialize_repr, Debug)]
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum ActivityType {
     Game = 0,
     Streaming = 1,
     Listening = 2,
+    Watching = 3,
     Custom = 4,
+    Competing = 5,
+}
+
+impl TryFrom<u64> for ActivityType {
+    type Error = Box<str>;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Game,
+            1 => Self::Streaming,
+            2 => Self::Listening,
+            3 => Self::Watching,
+            4 => Self::Custom,
+            5 => Self::Competing,
+            _ => return Err(format!("invalid activity type \"{}\"", value).into_boxed_str()),
+        })
+    }
+}
+
+/// Parses the name of the activity type, as shown in the client (e.g. "listening")
+impl FromStr for ActivityType {
+    type Err = Box<str>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match &s.trim().to_lowercase()[..] {
+            "playing" | "game" => Self::Game,
+            "streaming" => Self::Streaming,
+            "listening" => Self::Listening,
+            "watching" => Self::Watching,
+            "custom" => Self::Custom,
+            "competing" => Self::Competing,
+            _ => return Err(format!("invalid activity type \"{}\"", s).into_boxed_str()),
+        })
+    }
 }
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum StatusType {
     Online,
@@ -9,3 +10,18 @@ pub enum StatusType {
     Invisible,
     Offline,
 }
+
+impl FromStr for StatusType {
+    type Err = Box<str>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match &s.trim().to_lowercase()[..] {
+            "online" => Self::Online,
+            "dnd" => Self::Dnd,
+            "idle" => Self::Idle,
+            "invisible" => Self::Invisible,
+            "offline" => Self::Offline,
+            _ => return Err(format!("invalid status type \"{}\"", s).into_boxed_str()),
+        })
+    }
+}
@@ -0,0 +1,208 @@
+//! Compares parsing GUILD_CREATE dispatches the way the shard used to (a full serde_json Value to
+//! read op and s, converted back to a RawValue, then parsed again into an Event) with the current
+//! pipeline (a borrowed envelope, then the data parsed once into the owned models).
+//!
+//! Run with `cargo bench --bench dispatch_parsing`. Set BENCH_RECORDINGS to a comma separated list
+//! of traffic recordings to benchmark the GUILD_CREATEs in them, otherwise synthetic guilds of
+//! increasing size are generated.
+
+// for the json! macro building the guilds
+#![recursion_limit = "256"]
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use serde_json::value::{to_raw_value, RawValue};
+use serde_json::{json, Value};
+use sharder::{Event, Payload, RecordedPayload};
+
+const MIN_DURATION: Duration = Duration::from_secs(2);
+
+fn main() {
+    let payloads = match std::env::var("BENCH_RECORDINGS") {
+        Ok(paths) => load_recordings(&paths),
+        Err(_) => [100, 1_000, 10_000]
+            .iter()
+            .map(|&members| (format!("{} members", members), guild_create(members)))
+            .collect(),
+    };
+
+    println!(
+        "{:<30} {:>10} {:>14} {:>14} {:>8}",
+        "payload", "size", "value + reparse", "envelope", "speedup"
+    );
+
+    for (name, payload) in payloads {
+        assert!(matches!(old_pipeline(&payload), Event::GuildCreate(_)));
+        assert!(matches!(new_pipeline(&payload), Event::GuildCreate(_)));
+
+        let old = time(|| old_pipeline(&payload));
+        let new = time(|| new_pipeline(&payload));
+
+        println!(
+            "{:<30} {:>9}K {:>14?} {:>14?} {:>7.2}x",
+            name,
+            payload.len() / 1024,
+            old,
+            new,
+            old.as_secs_f64() / new.as_secs_f64()
+        );
+    }
+}
+
+fn old_pipeline(payload: &str) -> Event {
+    let value: Value = serde_json::from_str(payload).unwrap();
+    black_box(value.get("op"));
+    black_box(value.get("s"));
+
+    let raw: Box<RawValue> = to_raw_value(&value).unwrap();
+    serde_json::from_str(raw.get()).unwrap()
+}
+
+fn new_pipeline(payload: &str) -> Event {
+    let payload: Payload = serde_json::from_str(payload).unwrap();
+    Event::parse(payload.event_name.unwrap(), payload.data).unwrap()
+}
+
+// mean time per iteration, after running for at least MIN_DURATION
+fn time<T>(f: impl Fn() -> T) -> Duration {
+    black_box(f());
+
+    let started = Instant::now();
+    let mut iterations = 0;
+    while started.elapsed() < MIN_DURATION {
+        black_box(f());
+        iterations += 1;
+    }
+
+    started.elapsed() / iterations
+}
+
+fn load_recordings(paths: &str) -> Vec<(String, String)> {
+    let mut payloads = Vec::new();
+
+    for path in paths.split(',') {
+        let recording = std::fs::read_to_string(path).expect("Failed to read recording");
+
+        for line in recording.lines() {
+            let recorded: RecordedPayload = match serde_json::from_str(line) {
+                Ok(recorded) => recorded,
+                Err(_) => continue,
+            };
+
+            let payload: Payload = match serde_json::from_str(recorded.payload.get()) {
+                Ok(payload) => payload,
+                Err(_) => continue,
+            };
+
+            if payload.event_name == Some("GUILD_CREATE") {
+                let name = format!("{}:{}", recorded.bot_id, recorded.shard_id);
+                payloads.push((name, recorded.payload.get().to_owned()));
+            }
+        }
+    }
+
+    // the largest guilds are the interesting ones
+    payloads.sort_by_key(|(_, payload)| std::cmp::Reverse(payload.len()));
+    payloads.truncate(10);
+    payloads
+}
+
+fn guild_create(member_count: u64) -> String {
+    let roles: Vec<Value> = (0..50)
+        .map(|i| {
+            json!({
+                "id": (1000 + i).to_string(),
+                "name": format!("Role {}", i),
+                "color": 3447003,
+                "hoist": false,
+                "position": i,
+                "permissions": "104324673",
+                "managed": false,
+                "mentionable": true,
+            })
+        })
+        .collect();
+
+    let channels: Vec<Value> = (0..200)
+        .map(|i| {
+            json!({
+                "id": (2000 + i).to_string(),
+                "type": 0,
+                "position": i,
+                "name": format!("channel-{}", i),
+                "topic": "A channel for talking about things",
+                "nsfw": false,
+                "rate_limit_per_user": 0,
+                "parent_id": null,
+                "permission_overwrites": [],
+            })
+        })
+        .collect();
+
+    let members: Vec<Value> = (0..member_count)
+        .map(|i| {
+            json!({
+                "user": {
+                    "id": (100_000 + i).to_string(),
+                    "username": format!("user{}", i),
+                    "discriminator": "0001",
+                    "avatar": "a1b2c3d4e5f6a1b2c3d4e5f6a1b2c3d4",
+                },
+                "nick": null,
+                "roles": ["1001", "1002"],
+                "joined_at": "2021-01-01T00:00:00.000000+00:00",
+                "premium_since": null,
+                "deaf": false,
+                "mute": false,
+            })
+        })
+        .collect();
+
+    json!({
+        "op": 0,
+        "s": 2,
+        "t": "GUILD_CREATE",
+        "d": {
+            "id": "10",
+            "name": "Benchmark Guild",
+            "icon": null,
+            "owner_id": "100000",
+            "region": "europe",
+            "afk_channel_id": null,
+            "afk_timeout": 300,
+            "verification_level": 1,
+            "default_message_notifications": 1,
+            "explicit_content_filter": 2,
+            "roles": roles,
+            "emojis": [],
+            "features": ["COMMUNITY", "NEWS"],
+            "mfa_level": 0,
+            "application_id": null,
+            "system_channel_id": "2000",
+            "system_channel_flags": 0,
+            "rules_channel_id": null,
+            "joined_at": "2021-01-01T00:00:00.000000+00:00",
+            "large": true,
+            "unavailable": false,
+            "member_count": member_count,
+            "voice_states": [],
+            "members": members,
+            "channels": channels,
+            "threads": [],
+            "presences": [],
+            "max_members": 250000,
+            "vanity_url_code": null,
+            "description": null,
+            "banner": null,
+            "premium_tier": 1,
+            "premium_subscription_count": 4,
+            "preferred_locale": "en-US",
+            "public_updates_channel_id": null,
+            "nsfw_level": 0,
+            "stage_instances": [],
+            "stickers": [],
+        },
+    })
+    .to_string()
+}
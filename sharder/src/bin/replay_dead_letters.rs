@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use common::event_forwarding::{DeadLetter, DEAD_LETTER_KEY, DEAD_LETTER_PROCESSING_KEY};
+use deadpool_redis::{cmd, Connection, Pool};
+use log::{error, info};
+use sharder::event_forwarding::{EventForwarder, HttpEventForwarder, RedisStreamEventForwarder};
+use sharder::{build_redis, Config, ForwardingMode, GatewayError};
+
+/// Replays events from the dead letter queue through the configured event forwarder, oldest
+/// first. Takes an optional argument limiting how many events are replayed. Each event is moved to
+/// a processing list while it's replayed, and only removed once it has been forwarded, so that
+/// it's kept if the replay dies. Requires Redis 6.2, for LMOVE.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::from_envvar();
+    env_logger::init();
+
+    let limit = std::env::args()
+        .nth(1)
+        .map(|limit| limit.parse::<usize>().expect("Limit must be a number"));
+
+    let redis = Arc::new(build_redis(&config));
+
+    match config.forwarding_mode {
+        ForwardingMode::Http => {
+            let event_forwarder =
+                HttpEventForwarder::from_config(&config, HttpEventForwarder::build_http_client());
+
+            Ok(replay(&config, &redis, event_forwarder, limit).await?)
+        }
+        ForwardingMode::RedisStream => {
+            let event_forwarder =
+                RedisStreamEventForwarder::from_config(&config, Arc::clone(&redis));
+
+            Ok(replay(&config, &redis, event_forwarder, limit).await?)
+        }
+    }
+}
+
+async fn replay<T: EventForwarder>(
+    config: &Config,
+    redis: &Pool,
+    event_forwarder: T,
+    limit: Option<usize>,
+) -> Result<(), GatewayError> {
+    let mut replayed = 0;
+
+    let mut conn = redis.get().await?;
+    let recovered = restore_processing(&mut conn).await?;
+    if recovered > 0 {
+        info!("Recovered {} events left by an earlier replay", recovered);
+    }
+
+    while limit != Some(replayed) {
+        // events are LPUSHed, so the oldest is at the tail
+        let encoded: Option<String> = cmd("LMOVE")
+            .arg(DEAD_LETTER_KEY)
+            .arg(DEAD_LETTER_PROCESSING_KEY)
+            .arg("RIGHT")
+            .arg("LEFT")
+            .query_async(&mut conn)
+            .await?;
+
+        let encoded = match encoded {
+            Some(encoded) => encoded,
+            None => break,
+        };
+
+        let dead_letter: DeadLetter = match serde_json::from_str(&encoded) {
+            Ok(dead_letter) => dead_letter,
+            Err(e) => {
+                error!("Discarding malformed dead letter ({}): {}", e, encoded);
+                remove_processed(&mut conn, &encoded).await?;
+                continue;
+            }
+        };
+
+        if let Err(e) = event_forwarder
+            .forward_event(config, dead_letter.event, dead_letter.guild_id)
+            .await
+        {
+            // put the event back where we found it, so that ordering is preserved
+            restore_processing(&mut conn).await?;
+
+            error!("Stopping after replaying {} events", replayed);
+            return Err(e);
+        }
+
+        remove_processed(&mut conn, &encoded).await?;
+        replayed += 1;
+    }
+
+    info!("Replayed {} events", replayed);
+    Ok(())
+}
+
+/// Moves events from the processing list back to the tail of the dead letter queue, in the order
+/// they were taken from it. Returns how many were moved.
+async fn restore_processing(conn: &mut Connection) -> Result<usize, GatewayError> {
+    let mut restored = 0;
+
+    loop {
+        let moved: Option<String> = cmd("LMOVE")
+            .arg(DEAD_LETTER_PROCESSING_KEY)
+            .arg(DEAD_LETTER_KEY)
+            .arg("LEFT")
+            .arg("RIGHT")
+            .query_async(conn)
+            .await?;
+
+        match moved {
+            Some(_) => restored += 1,
+            None => return Ok(restored),
+        }
+    }
+}
+
+async fn remove_processed(conn: &mut Connection, encoded: &str) -> Result<(), GatewayError> {
+    let _: i64 = cmd("LREM")
+        .arg(DEAD_LETTER_PROCESSING_KEY)
+        .arg(1)
+        .arg(encoded)
+        .query_async(conn)
+        .await?;
+
+    Ok(())
+}
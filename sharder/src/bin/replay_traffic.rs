@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use cache::{Cache, NoOpCache};
+use common::event_forwarding;
+use deadpool_redis::Pool;
+use log::{error, info};
+use model::Snowflake;
+use sharder::event_forwarding::{EventForwarder, HttpEventForwarder, RedisStreamEventForwarder};
+use sharder::{
+    build_cache, build_redis, CacheBackend, Config, ForwardingGate, ForwardingMode, GatewayError,
+    Identify, RecordedPayload, SessionStartLimit, SessionStartLimiter, Shard, ShardInfo, ShardMode,
+    ShardServices, SharderMode,
+};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Feeds recordings written by the traffic recorder through a shard, caching events in the
+/// configured cache and forwarding them with the configured event forwarder, as though they had
+/// been received from the gateway. Takes the recording files to replay, in order. Events are
+/// discarded rather than forwarded if `--no-forward` is passed.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::from_envvar();
+    env_logger::init();
+
+    let mut forward = true;
+    let mut paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        if arg == "--no-forward" {
+            forward = false;
+        } else {
+            paths.push(arg);
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("Usage: replay_traffic [--no-forward] <recording>...".into());
+    }
+
+    let redis = Arc::new(build_redis(&config));
+
+    match config.cache_backend {
+        CacheBackend::Postgres => {
+            let cache = Arc::new(build_cache(&config).await);
+            start_forwarding(config, cache, redis, forward, paths).await
+        }
+        CacheBackend::Disabled => {
+            start_forwarding(config, Arc::new(NoOpCache), redis, forward, paths).await
+        }
+    }
+}
+
+async fn start_forwarding<C: Cache>(
+    config: Config,
+    cache: Arc<C>,
+    redis: Arc<Pool>,
+    forward: bool,
+    paths: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !forward {
+        return replay(config, cache, redis, DiscardingEventForwarder, paths).await;
+    }
+
+    match config.forwarding_mode {
+        ForwardingMode::Http => {
+            let event_forwarder =
+                HttpEventForwarder::from_config(&config, HttpEventForwarder::build_http_client());
+
+            replay(config, cache, redis, event_forwarder, paths).await
+        }
+        ForwardingMode::RedisStream => {
+            let event_forwarder =
+                RedisStreamEventForwarder::from_config(&config, Arc::clone(&redis));
+
+            replay(config, cache, redis, event_forwarder, paths).await
+        }
+    }
+}
+
+async fn replay<T: EventForwarder, C: Cache>(
+    config: Config,
+    cache: Arc<C>,
+    redis: Arc<Pool>,
+    event_forwarder: T,
+    paths: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Arc::new(config);
+    let event_forwarder = Arc::new(event_forwarder);
+    let started = Instant::now();
+
+    let mut shards: HashMap<(Snowflake, u16), Arc<ReplayShard<T, C>>> = HashMap::new();
+    let mut replayed = 0;
+
+    for path in paths {
+        info!("Replaying {}", path);
+        let mut lines = BufReader::new(File::open(&path).await?).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            let recorded: RecordedPayload = match serde_json::from_str(&line) {
+                Ok(recorded) => recorded,
+                Err(e) => {
+                    error!("Skipping malformed line in {} ({}): {}", path, e, line);
+                    continue;
+                }
+            };
+
+            let shard = shards
+                .entry((recorded.bot_id, recorded.shard_id))
+                .or_insert_with(|| {
+                    build_shard(
+                        &config,
+                        &cache,
+                        &redis,
+                        &event_forwarder,
+                        recorded.bot_id,
+                        ShardInfo::new(recorded.shard_id, recorded.shard_total),
+                    )
+                });
+
+            if let Err(e) = Arc::clone(shard)
+                .replay(recorded.payload.get().as_bytes())
+                .await
+            {
+                shard.log_err("Error replaying payload", &e);
+            }
+
+            replayed += 1;
+        }
+    }
+
+    for shard in shards.values() {
+        shard.wait_for_in_flight().await;
+    }
+
+    info!(
+        "Replayed {} payloads across {} shards in {:?}",
+        replayed,
+        shards.len(),
+        started.elapsed()
+    );
+
+    Ok(())
+}
+
+fn build_shard<T: EventForwarder, C: Cache>(
+    config: &Arc<Config>,
+    cache: &Arc<C>,
+    redis: &Arc<Pool>,
+    event_forwarder: &Arc<T>,
+    bot_id: Snowflake,
+    shard_info: ShardInfo,
+) -> Arc<ReplayShard<T, C>> {
+    // recordings don't contain tokens, so the configured token is forwarded with every event
+    let token = config.sharder_token.clone().unwrap_or_default();
+    let total = shard_info.num_shards;
+
+    // the shard never connects, so the session start limit is never used
+    let session_start_limiter = Arc::new(SessionStartLimiter::new(
+        reqwest::Client::new(),
+        config,
+        token.clone(),
+        Some(SessionStartLimit {
+            total: 0,
+            remaining: 0,
+            reset_after: 0,
+            max_concurrency: 1,
+        }),
+    ));
+
+    let services = ShardServices {
+        cache: Arc::clone(cache),
+        redis: Arc::clone(redis),
+        event_forwarder: Arc::clone(event_forwarder),
+        event_whitelist: Arc::new(config.forward_events.clone()),
+        session_start_limiter,
+        forwarding_gate: Arc::new(ForwardingGate::new(total)),
+        recorder: None,
+    };
+
+    Shard::new(
+        Arc::clone(config),
+        Identify::new(token, None, shard_info, None, config.intents.bits()),
+        1,
+        bot_id,
+        services,
+        ReplayMode {
+            whitelabel: config.sharder_mode == SharderMode::Whitelabel,
+        },
+    )
+}
+
+type ReplayShard<T, C> = Shard<T, ReplayMode, C>;
+
+/// Keeps replayed sessions apart from those of the running sharders
+struct ReplayMode {
+    whitelabel: bool,
+}
+
+impl ShardMode for ReplayMode {
+    fn is_whitelabel(&self) -> bool {
+        self.whitelabel
+    }
+
+    fn identify_ratelimit_key(&self, bot_id: Snowflake, shard_info: &ShardInfo, _: u16) -> String {
+        format!("replay:identify:{}:{}", bot_id, shard_info.shard_id)
+    }
+
+    fn resume_key(&self, bot_id: Snowflake, shard_info: &ShardInfo) -> String {
+        format!("replay:resume:{}:{}", bot_id, shard_info.shard_id)
+    }
+
+    fn seq_key(&self, bot_id: Snowflake, shard_info: &ShardInfo) -> String {
+        format!("replay:seq:{}:{}", bot_id, shard_info.shard_id)
+    }
+
+    fn guild_bots_key(&self, guild_id: Snowflake) -> String {
+        format!("replay:guild_bots:{}", guild_id)
+    }
+
+    fn log_prefix(&self, bot_id: Snowflake, shard_info: &ShardInfo) -> String {
+        format!("[replay:{}:{}]", bot_id, shard_info.shard_id)
+    }
+}
+
+struct DiscardingEventForwarder;
+
+#[async_trait]
+impl EventForwarder for DiscardingEventForwarder {
+    async fn forward_event(
+        &self,
+        _: &Config,
+        _: event_forwarding::Event<'_>,
+        _: Option<Snowflake>,
+    ) -> Result<(), GatewayError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use jemallocator::Jemalloc;
+use sharder::{Config, SharderMode};
+
+mod public;
+mod whitelabel;
+
+#[global_allocator]
+static GLOBAL: Jemalloc = Jemalloc;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::from_envvar();
+    //let _guard = setup_sentry(&config);
+    env_logger::init();
+
+    match config.sharder_mode {
+        SharderMode::Public => {
+            public::start(config).await;
+            Ok(())
+        }
+        SharderMode::Whitelabel => whitelabel::start(config).await,
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use sharder::{
+    get_gateway_bot, CacheBackend, Config, ForwardingMode, GatewayBot, Options, Presence,
+    PublicShardManager, SessionStartLimiter, ShardCount, ShardManager,
+};
+
+use sharder::{build_cache, build_redis, http, shutdown_on_signal};
+
+use cache::{Cache, NoOpCache};
+use deadpool_redis::{cmd, Pool};
+use log::warn;
+use sharder::event_forwarding::{
+    EventForwarder, HttpEventForwarder, RedisStreamEventForwarder, RetryEventForwarder,
+};
+
+pub async fn start(config: Config) {
+    // init sharder options
+    let token = config
+        .sharder_token
+        .clone()
+        .expect("SHARDER_TOKEN is required in public mode");
+    let bot_id = config.bot_id.expect("BOT_ID is required in public mode");
+
+    let http_client = reqwest::Client::new();
+    let gateway_bot = get_gateway_bot(&http_client, &config.discord_api_url, &token)
+        .await
+        .expect("Failed to fetch /gateway/bot");
+
+    let shard_count = get_shard_count(&config, &gateway_bot);
+    let session_start_limiter = Arc::new(SessionStartLimiter::new(
+        http_client,
+        &config,
+        token.clone(),
+        Some(gateway_bot.session_start_limit),
+    ));
+
+    let options = Options {
+        token: Box::from(token),
+        shard_count,
+        presence: Presence::from_config(&config),
+        large_sharding_buckets: gateway_bot.session_start_limit.max_concurrency,
+        user_id: bot_id,
+        session_start_limiter,
+    };
+
+    // init redis
+    let redis = Arc::new(build_redis(&config));
+
+    // test redis connection
+    let mut conn = redis.get().await.expect("Failed to get redis conn");
+
+    let res: String = cmd("PING")
+        .query_async(&mut conn)
+        .await
+        .expect("Redis PING failed");
+
+    assert_eq!(res, "PONG");
+
+    // init cache
+    match config.cache_backend {
+        CacheBackend::Postgres => {
+            let cache = Arc::new(build_cache(&config).await);
+            //cache.create_schema().await.unwrap();
+
+            start_forwarding(config, options, cache, redis).await
+        }
+        CacheBackend::Disabled => {
+            start_forwarding(config, options, Arc::new(NoOpCache), redis).await
+        }
+    }
+}
+
+async fn start_forwarding<C: Cache>(
+    config: Config,
+    options: Options,
+    cache: Arc<C>,
+    redis: Arc<Pool>,
+) {
+    match config.forwarding_mode {
+        ForwardingMode::Http => {
+            let event_forwarder =
+                HttpEventForwarder::from_config(&config, HttpEventForwarder::build_http_client());
+
+            run(config, options, cache, redis, event_forwarder).await
+        }
+        ForwardingMode::RedisStream => {
+            let event_forwarder =
+                RedisStreamEventForwarder::from_config(&config, Arc::clone(&redis));
+
+            run(config, options, cache, redis, event_forwarder).await
+        }
+    }
+}
+
+async fn run<T: EventForwarder, C: Cache>(
+    config: Config,
+    options: Options,
+    cache: Arc<C>,
+    redis: Arc<Pool>,
+    event_forwarder: T,
+) {
+    let event_forwarder = Arc::new(RetryEventForwarder::from_config(
+        &config,
+        event_forwarder,
+        Arc::clone(&redis),
+    ));
+
+    let shutdown_timeout = Duration::from_millis(config.shutdown_timeout);
+    let admin_api = config
+        .admin_api_addr
+        .clone()
+        .map(|addr| (addr, config.admin_api_token.clone()));
+
+    let sm =
+        Arc::new(PublicShardManager::new(config, options, cache, redis, event_forwarder).await);
+
+    // started before connecting, so that shards stuck during startup can be seen
+    if let Some((addr, token)) = admin_api {
+        http::Server::new(addr, token, Arc::clone(&sm)).spawn();
+    }
+    Arc::clone(&sm).connect().await;
+
+    Arc::clone(&sm)
+        .listen_request_guild_members()
+        .await
+        .unwrap();
+
+    shutdown_on_signal(sm, shutdown_timeout).await;
+}
+
+fn get_shard_count(config: &Config, gateway_bot: &GatewayBot) -> ShardCount {
+    let cluster_size = config.sharder_cluster_size.unwrap_or_else(|| {
+        // large bots must run a multiple of max_concurrency shards
+        let step = config.sharder_total * gateway_bot.session_start_limit.max_concurrency;
+        let total = gateway_bot.shards.div_ceil(step) * step;
+        total / config.sharder_total
+    });
+
+    let total = cluster_size * config.sharder_total;
+    if total < gateway_bot.shards {
+        warn!(
+            "Running {} shards, fewer than the {} recommended by Discord",
+            total, gateway_bot.shards
+        );
+    }
+
+    ShardCount {
+        total,
+        lowest: cluster_size * config.sharder_id,
+        highest: cluster_size * (config.sharder_id + 1),
+    }
+}
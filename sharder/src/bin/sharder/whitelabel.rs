@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use sharder::{
+    build_redis, CacheBackend, Config, ForwardingMode, ShardManager, WhitelabelShardManager,
+};
+
+use cache::{Cache, NoOpCache};
+use database::{sqlx::postgres::PgPoolOptions, Database};
+use deadpool_redis::Pool;
+use sharder::{build_cache, http, shutdown_on_signal};
+
+use sharder::event_forwarding::{
+    EventForwarder, HttpEventForwarder, RedisStreamEventForwarder, RetryEventForwarder,
+};
+
+pub async fn start(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    // init db
+    let database_uri = config
+        .database_uri
+        .as_deref()
+        .expect("DATABASE_URI is required in whitelabel mode");
+    let database_threads = config
+        .database_threads
+        .expect("DATABASE_THREADS is required in whitelabel mode");
+
+    let db_opts = PgPoolOptions::new()
+        .min_connections(1)
+        .max_connections(database_threads);
+    let database = Arc::new(Database::connect(database_uri, db_opts).await?);
+
+    // init redis
+    let redis = Arc::new(build_redis(&config));
+
+    // init cache
+    match config.cache_backend {
+        CacheBackend::Postgres => {
+            let cache = Arc::new(build_cache(&config).await);
+            //cache.create_schema().await.unwrap();
+
+            start_forwarding(config, database, cache, redis).await
+        }
+        CacheBackend::Disabled => {
+            start_forwarding(config, database, Arc::new(NoOpCache), redis).await
+        }
+    }
+}
+
+async fn start_forwarding<C: Cache>(
+    config: Config,
+    database: Arc<Database>,
+    cache: Arc<C>,
+    redis: Arc<Pool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match config.forwarding_mode {
+        ForwardingMode::Http => {
+            let event_forwarder =
+                HttpEventForwarder::from_config(&config, HttpEventForwarder::build_http_client());
+
+            run(config, database, cache, redis, event_forwarder).await
+        }
+        ForwardingMode::RedisStream => {
+            let event_forwarder =
+                RedisStreamEventForwarder::from_config(&config, Arc::clone(&redis));
+
+            run(config, database, cache, redis, event_forwarder).await
+        }
+    }
+}
+
+async fn run<T: EventForwarder, C: Cache>(
+    config: Config,
+    database: Arc<Database>,
+    cache: Arc<C>,
+    redis: Arc<Pool>,
+    event_forwarder: T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let event_forwarder = Arc::new(RetryEventForwarder::from_config(
+        &config,
+        event_forwarder,
+        Arc::clone(&redis),
+    ));
+
+    let shutdown_timeout = Duration::from_millis(config.shutdown_timeout);
+    let admin_api = config
+        .admin_api_addr
+        .clone()
+        .map(|addr| (addr, config.admin_api_token.clone()));
+
+    let sm = Arc::new(WhitelabelShardManager::new(
+        config,
+        database,
+        cache,
+        redis,
+        event_forwarder,
+    ));
+
+    // started before connecting, so that shards stuck during startup can be seen
+    if let Some((addr, token)) = admin_api {
+        http::Server::new(addr, token, Arc::clone(&sm)).spawn();
+    }
+
+    Arc::clone(&sm).connect().await;
+
+    Arc::clone(&sm).listen_control().await.unwrap();
+    Arc::clone(&sm)
+        .listen_request_guild_members()
+        .await
+        .unwrap();
+
+    shutdown_on_signal(sm, shutdown_timeout).await;
+    Ok(())
+}
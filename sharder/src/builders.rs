@@ -5,7 +5,21 @@ use deadpool_redis::{Config as RedisConfig, Pool};
 
 /// panics on err
 pub async fn build_cache(config: &Config) -> PostgresCache {
-    let cache_opts = Options {
+    let uri = config
+        .cache_uri
+        .clone()
+        .expect("CACHE_URI is required with the postgres cache backend");
+    let threads = config
+        .cache_threads
+        .expect("CACHE_THREADS is required with the postgres cache backend");
+
+    PostgresCache::connect(uri, cache_options(), threads)
+        .await
+        .unwrap()
+}
+
+pub(crate) fn cache_options() -> Options {
+    Options {
         users: true,
         guilds: true,
         members: true,
@@ -13,11 +27,7 @@ pub async fn build_cache(config: &Config) -> PostgresCache {
         roles: true,
         emojis: false,
         voice_states: false,
-    };
-
-    PostgresCache::connect(config.cache_uri.clone(), cache_opts, config.cache_threads)
-        .await
-        .unwrap()
+    }
 }
 
 /// panics on err
@@ -1,34 +1,223 @@
-use serde::Deserialize;
-
-#[cfg(not(feature = "whitelabel"))]
+use crate::gateway::event_forwarding::EventWhitelist;
+use crate::gateway::IntentSet;
+use model::user::{ActivityType, StatusType};
 use model::Snowflake;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     // Required
     pub sharder_id: u16,
     pub sharder_total: u16,
-    pub cache_uri: String,
-    pub cache_threads: usize,
     pub redis_addr: String,
     pub redis_password: Option<String>,
     pub redis_threads: usize,
-    pub worker_svc_uri: String,
+    // comma separated
+    pub worker_svc_uri: Vec<String>,
     pub sentry_dsn: String,
 
-    // Public Sharder
-    #[cfg(not(feature = "whitelabel"))]
-    pub sharder_token: String,
-    #[cfg(not(feature = "whitelabel"))]
-    pub sharder_cluster_size: u16,
-    #[cfg(not(feature = "whitelabel"))]
-    pub bot_id: Snowflake,
+    // Optional
+    #[serde(default)]
+    pub sharder_mode: SharderMode,
+    #[serde(default)]
+    pub cache_backend: CacheBackend,
+    // required with the postgres cache backend
+    pub cache_uri: Option<String>,
+    pub cache_threads: Option<usize>,
+    #[serde(default = "default_gateway_url")]
+    pub gateway_url: String,
+    #[serde(default = "default_discord_api_url")]
+    pub discord_api_url: String,
+    #[serde(default = "default_session_start_reserve")]
+    pub session_start_reserve: u32,
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    #[serde(default)]
+    pub forwarding_mode: ForwardingMode,
+    #[serde(default = "default_event_stream_max_len")]
+    pub event_stream_max_len: usize,
+    pub event_stream_partitions: Option<u16>,
+    #[serde(default = "default_worker_failure_threshold")]
+    pub worker_failure_threshold: u32,
+    #[serde(default = "default_worker_unhealthy_cooldown")]
+    pub worker_unhealthy_cooldown: u64,
+    #[serde(default = "default_forward_retry_initial_interval")]
+    pub forward_retry_initial_interval: u64,
+    #[serde(default = "default_forward_retry_max_interval")]
+    pub forward_retry_max_interval: u64,
+    #[serde(default = "default_forward_retry_max_elapsed")]
+    pub forward_retry_max_elapsed: u64,
+    #[serde(default = "default_dead_letter_enabled")]
+    pub dead_letter_enabled: bool,
+    // comma separated event names, e.g. MESSAGE_CREATE,GUILD_CREATE
+    #[serde(default)]
+    pub forward_events: EventWhitelist,
+    // forward events that the sharder doesn't recognise, e.g. those added by Discord since
+    #[serde(default)]
+    pub forward_unknown_events: bool,
+    // comma separated intent names or bitmasks, e.g. GUILDS,GUILD_MEMBERS or 515
+    #[serde(default)]
+    pub intents: IntentSet,
+    // e.g. 0.0.0.0:8080, the admin API isn't started if unset
+    pub admin_api_addr: Option<String>,
+    // commands sent to the admin API are rejected if unset
+    pub admin_api_token: Option<String>,
+    // directory to record inbound gateway payloads to, traffic isn't recorded if unset
+    pub record_traffic_dir: Option<String>,
+    #[serde(default = "default_record_traffic_max_file_size")]
+    pub record_traffic_max_file_size: u64,
+    #[serde(default = "default_record_traffic_max_files")]
+    pub record_traffic_max_files: usize,
+    // e.g. listening or watching
+    #[serde(
+        default = "default_presence_activity_type",
+        deserialize_with = "deserialize_activity_type"
+    )]
+    pub presence_activity_type: ActivityType,
+    #[serde(default = "default_presence_status")]
+    pub presence_status: StatusType,
+    // semicolon separated, rotated through in order
+    #[serde(
+        default = "default_presence_messages",
+        deserialize_with = "deserialize_presence_messages"
+    )]
+    pub presence_messages: Vec<String>,
+    #[serde(default = "default_presence_rotate_interval")]
+    pub presence_rotate_interval: u64,
+
+    // Public Sharder, required in public mode
+    pub sharder_token: Option<String>,
+    // defaults to the number of shards recommended by Discord, split between sharders
+    pub sharder_cluster_size: Option<u16>,
+    pub bot_id: Option<Snowflake>,
+
+    // Whitelabel Sharder, required in whitelabel mode
+    pub database_uri: Option<String>,
+    pub database_threads: Option<u32>,
+    // the most bots the sharder will hold leases for, unlimited if unset
+    pub whitelabel_capacity: Option<usize>,
+    // ms, bots are claimed by other sharders once a sharder has stopped renewing its leases. A
+    // sharder that can't renew its leases stops its bots 15s before they expire, so this should be
+    // well above that.
+    #[serde(default = "default_whitelabel_lease_ttl")]
+    pub whitelabel_lease_ttl: u64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SharderMode {
+    #[default]
+    Public,
+    Whitelabel,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheBackend {
+    #[default]
+    Postgres,
+    // events are only forwarded
+    Disabled,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardingMode {
+    #[default]
+    Http,
+    RedisStream,
+}
+
+fn default_gateway_url() -> String {
+    "wss://gateway.discord.gg".to_owned()
+}
+
+fn default_discord_api_url() -> String {
+    "https://discord.com/api/v9".to_owned()
+}
+
+fn default_session_start_reserve() -> u32 {
+    10
+}
+
+fn default_shutdown_timeout() -> u64 {
+    10_000
+}
+
+fn default_event_stream_max_len() -> usize {
+    100_000
+}
+
+fn default_worker_failure_threshold() -> u32 {
+    3
+}
+
+fn default_worker_unhealthy_cooldown() -> u64 {
+    10_000
+}
+
+fn default_forward_retry_initial_interval() -> u64 {
+    100
+}
+
+fn default_forward_retry_max_interval() -> u64 {
+    5_000
+}
+
+fn default_forward_retry_max_elapsed() -> u64 {
+    30_000
+}
+
+fn default_dead_letter_enabled() -> bool {
+    true
+}
+
+fn default_record_traffic_max_file_size() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_record_traffic_max_files() -> usize {
+    10
+}
+
+fn default_presence_activity_type() -> ActivityType {
+    ActivityType::Listening
+}
 
-    // Whitelabel Sharder
-    #[cfg(feature = "whitelabel")]
-    pub database_uri: String,
-    #[cfg(feature = "whitelabel")]
-    pub database_threads: u32,
+fn default_presence_status() -> StatusType {
+    StatusType::Online
+}
+
+fn default_presence_messages() -> Vec<String> {
+    vec!["/help | /setup".to_owned()]
+}
+
+fn default_presence_rotate_interval() -> u64 {
+    60_000
+}
+
+fn default_whitelabel_lease_ttl() -> u64 {
+    30_000
+}
+
+fn deserialize_activity_type<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ActivityType, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(D::Error::custom)
+}
+
+fn deserialize_presence_messages<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    Ok(String::deserialize(deserializer)?
+        .split(';')
+        .map(str::trim)
+        .filter(|message| !message.is_empty())
+        .map(str::to_owned)
+        .collect())
 }
 
 impl Config {
@@ -36,9 +225,20 @@ impl Config {
         envy::from_env::<Config>().expect("Parsing config failed")
     }
 
-    pub fn get_worker_svc_uri(&self) -> String {
-        format!("http://{}/event", self.worker_svc_uri)
+    pub fn get_worker_svc_uris(&self) -> Vec<String> {
+        self.worker_svc_uri
+            .iter()
+            .map(|uri| format!("http://{}/event", uri))
+            .collect()
+    }
+    pub fn get_gateway_uri(&self, version: u8) -> String {
+        format!(
+            "{}/?v={}&encoding=json",
+            self.gateway_url.trim_end_matches('/'),
+            version
+        )
     }
+
     pub fn get_redis_uri(&self) -> String {
         match &self.redis_password {
             Some(pwd) => format!("redis://:{}@{}/", pwd, self.redis_addr),
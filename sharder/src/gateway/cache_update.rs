@@ -0,0 +1,89 @@
+use cache::Cache;
+use model::guild::{Guild, MemberUpdate};
+
+use crate::gateway::payloads::event::Event;
+
+/// Writes the changes described by an event to the cache. Events that aren't cached are ignored.
+/// GUILD_DELETE is left to the shard, which only evicts the guild once no bot remains in it.
+pub async fn update_cache<C: Cache>(cache: &C, event: Event) -> cache::Result<()> {
+    match event {
+        Event::ChannelCreate(channel) => cache.store_channel(channel).await,
+        Event::ChannelUpdate(channel) => cache.store_channel(channel).await,
+        Event::ChannelDelete(channel) => cache.delete_channel(channel.id).await,
+        Event::ThreadCreate(thread) => cache.store_channel(thread).await,
+        Event::ThreadUpdate(thread) => cache.store_channel(thread).await,
+        Event::ThreadDelete(thread) => cache.delete_channel(thread.id).await,
+        Event::GuildCreate(mut guild) => {
+            apply_guild_id_to_channels(&mut guild);
+            cache.store_guild(guild).await
+        }
+        Event::GuildUpdate(guild) => cache.update_guild(guild).await,
+        Event::GuildBanAdd(ev) => cache.delete_member(ev.user.id, ev.guild_id).await,
+        Event::GuildEmojisUpdate(ev) => cache.store_emojis(ev.emojis, ev.guild_id).await,
+        Event::GuildMemberAdd(ev) => cache.store_member(ev.member, ev.guild_id).await,
+        Event::GuildMemberRemove(ev) => cache.delete_member(ev.user.id, ev.guild_id).await,
+        Event::GuildMemberUpdate(ev) => {
+            cache
+                .update_member(
+                    MemberUpdate {
+                        user: ev.user,
+                        nick: ev.nick,
+                        roles: ev.roles,
+                        joined_at: ev.joined_at,
+                        premium_since: ev.premium_since,
+                        deaf: ev.deaf,
+                        mute: ev.mute,
+                    },
+                    ev.guild_id,
+                )
+                .await
+        }
+        Event::GuildMembersChunk(ev) => cache.store_members(ev.members, ev.guild_id).await,
+        Event::GuildRoleCreate(ev) => cache.store_role(ev.role, ev.guild_id).await,
+        Event::GuildRoleUpdate(ev) => cache.store_role(ev.role, ev.guild_id).await,
+        Event::GuildRoleDelete(ev) => cache.delete_role(ev.role_id).await,
+        Event::UserUpdate(user) => cache.store_user(user).await,
+        _ => Ok(()),
+    }
+}
+
+/// Whether update_cache writes events with the given name to the cache, so that events which are
+/// neither cached nor forwarded can be skipped without being deserialized. Keep in sync with the
+/// match in update_cache.
+pub fn is_cached(name: &str) -> bool {
+    matches!(
+        name,
+        "CHANNEL_CREATE"
+            | "CHANNEL_UPDATE"
+            | "CHANNEL_DELETE"
+            | "THREAD_CREATE"
+            | "THREAD_UPDATE"
+            | "THREAD_DELETE"
+            | "GUILD_CREATE"
+            | "GUILD_UPDATE"
+            | "GUILD_BAN_ADD"
+            | "GUILD_EMOJIS_UPDATE"
+            | "GUILD_MEMBER_ADD"
+            | "GUILD_MEMBER_REMOVE"
+            | "GUILD_MEMBER_UPDATE"
+            | "GUILD_MEMBERS_CHUNK"
+            | "GUILD_ROLE_CREATE"
+            | "GUILD_ROLE_UPDATE"
+            | "GUILD_ROLE_DELETE"
+            | "USER_UPDATE"
+    )
+}
+
+fn apply_guild_id_to_channels(guild: &mut Guild) {
+    if let Some(channels) = &mut guild.channels {
+        for channel in channels {
+            channel.guild_id = Some(guild.id)
+        }
+    }
+
+    if let Some(threads) = &mut guild.threads {
+        for thread in threads {
+            thread.guild_id = Some(guild.id)
+        }
+    }
+}
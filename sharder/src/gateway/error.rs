@@ -1,4 +1,5 @@
 use crate::gateway::outbound_message::OutboundMessage;
+use model::Snowflake;
 use std::fmt::Display;
 use thiserror::Error;
 use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
@@ -20,9 +21,6 @@ pub enum GatewayError {
     #[error("error while operating on json (serde): {0}")]
     JsonError(#[from] serde_json::Error),
 
-    #[error("error while operating on json (simd): {0}")]
-    SimdJsonError(#[from] simd_json::Error),
-
     #[error("error while operating on Redis: {0}")]
     RedisError(#[from] redis::RedisError),
 
@@ -85,6 +83,33 @@ pub enum GatewayError {
 
     #[error("Received error response from worker: {0}")]
     WorkerError(String),
+
+    #[error("gave up forwarding event as the sharder is shutting down: {0}")]
+    ForwardCancelledError(String),
+
+    #[error("error occurred while calling the Discord API: {0}")]
+    DiscordApiError(reqwest::Error),
+
+    #[error("shard is not connected to the gateway")]
+    NotConnectedError,
+
+    #[error("timed out waiting for member chunks for guild {0}")]
+    MemberChunkTimeoutError(Snowflake),
+
+    #[error("resharding is not supported by this sharder")]
+    ReshardingUnsupportedError,
+
+    #[error("resharding is already in progress")]
+    AlreadyReshardingError,
+
+    #[error("shard total {0} is not a new multiple of the number of sharders")]
+    InvalidShardTotalError(u16),
+
+    #[error("error occurred while parsing address: {0}")]
+    AddrParseError(#[from] std::net::AddrParseError),
+
+    #[error("error occurred in hyper: {0}")]
+    HyperError(#[from] hyper::Error),
 }
 
 impl GatewayError {
@@ -11,4 +11,7 @@ pub trait EventForwarder: Sync + Send + 'static {
         event: event_forwarding::Event<'_>,
         guild_id: Option<Snowflake>,
     ) -> Result<(), GatewayError>;
+
+    /// Gives up on forwards that are still being retried, for when the process is about to exit
+    fn stop_retrying(&self) {}
 }
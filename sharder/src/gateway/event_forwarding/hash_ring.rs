@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+
+const VIRTUAL_NODES: usize = 128;
+
+/// Consistent hash ring mapping keys onto node indices. Each node is placed on the ring at
+/// VIRTUAL_NODES points derived from its name, so adding or removing a node only moves the keys
+/// that hash onto that node's points.
+pub struct HashRing {
+    points: BTreeMap<u64, usize>,
+}
+
+impl HashRing {
+    /// nodes is a list of (index, name) pairs. The name, rather than the index, determines where
+    /// the node sits on the ring, so that every sharder builds the same ring from the same names.
+    pub fn new<'a>(nodes: impl IntoIterator<Item = (usize, &'a str)>) -> HashRing {
+        let mut points = BTreeMap::new();
+
+        for (index, name) in nodes {
+            for i in 0..VIRTUAL_NODES {
+                points.insert(hash(format!("{}-{}", name, i).as_bytes()), index);
+            }
+        }
+
+        HashRing { points }
+    }
+
+    pub fn get(&self, key: u64) -> Option<usize> {
+        let hash = hash(&key.to_le_bytes());
+
+        self.points
+            .range(hash..)
+            .next()
+            .or_else(|| self.points.iter().next()) // wrap around
+            .map(|(_, &index)| index)
+    }
+}
+
+// FNV-1a followed by the murmur3 finaliser to spread out similar inputs. We can't use the std
+// hasher, as it isn't guaranteed to be stable between releases.
+fn hash(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut hash = data.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    });
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^= hash >> 33;
+
+    hash
+}
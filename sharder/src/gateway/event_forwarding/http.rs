@@ -1,18 +1,79 @@
+use crate::event_forwarding::hash_ring::HashRing;
 use crate::event_forwarding::EventForwarder;
 use crate::gateway::worker_response::WorkerResponse;
+use crate::metrics::{ShardLabels, METRICS};
 use crate::{Config, GatewayError};
 use async_trait::async_trait;
 use common::event_forwarding;
+use log::warn;
 use model::Snowflake;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
+/// Forwards events to one of several worker endpoints, picked by consistent hashing on the guild
+/// ID so that all events for a guild reach the same worker, in order: shards forward a guild's
+/// events one at a time, retrying each before sending the next. Endpoints that fail repeatedly
+/// are taken out of the ring for a cooldown period.
 pub struct HttpEventForwarder {
     client: reqwest::Client,
+    endpoints: Vec<Endpoint>,
+    ring: RwLock<Arc<Ring>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+struct Endpoint {
+    uri: String,
+    failures: AtomicU32,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+struct Ring {
+    ring: HashRing,
+    // when the next unhealthy endpoint is due to be added back
+    expires_at: Option<Instant>,
 }
 
 impl HttpEventForwarder {
-    pub fn new(client: reqwest::Client) -> HttpEventForwarder {
-        HttpEventForwarder { client }
+    /// panics if endpoints is empty
+    pub fn new(
+        client: reqwest::Client,
+        endpoints: Vec<String>,
+        failure_threshold: u32,
+        cooldown: Duration,
+    ) -> HttpEventForwarder {
+        if endpoints.is_empty() {
+            panic!("At least one worker endpoint must be provided");
+        }
+
+        let endpoints = endpoints
+            .into_iter()
+            .map(|uri| Endpoint {
+                uri,
+                failures: AtomicU32::new(0),
+                unhealthy_until: Mutex::new(None),
+            })
+            .collect::<Vec<_>>();
+
+        let ring = Self::build_ring(&endpoints);
+
+        HttpEventForwarder {
+            client,
+            endpoints,
+            ring: RwLock::new(Arc::new(ring)),
+            failure_threshold,
+            cooldown,
+        }
+    }
+
+    pub fn from_config(config: &Config, client: reqwest::Client) -> HttpEventForwarder {
+        Self::new(
+            client,
+            config.get_worker_svc_uris(),
+            config.worker_failure_threshold,
+            Duration::from_millis(config.worker_unhealthy_cooldown),
+        )
     }
 
     pub fn build_http_client() -> reqwest::Client {
@@ -27,24 +88,96 @@ impl HttpEventForwarder {
 
         builder.build().expect("build_http_client")
     }
-}
 
-#[async_trait]
-impl EventForwarder for HttpEventForwarder {
-    async fn forward_event(
+    fn build_ring(endpoints: &[Endpoint]) -> Ring {
+        let now = Instant::now();
+        let mut expires_at: Option<Instant> = None;
+
+        let mut healthy = Vec::with_capacity(endpoints.len());
+        for (index, endpoint) in endpoints.iter().enumerate() {
+            match *endpoint.unhealthy_until.lock().unwrap() {
+                Some(until) if until > now => {
+                    expires_at = Some(expires_at.map_or(until, |at| at.min(until)));
+                }
+                _ => healthy.push((index, &endpoint.uri[..])),
+            }
+        }
+
+        // if every worker is down, keep trying all of them rather than dropping events
+        if healthy.is_empty() {
+            healthy = endpoints
+                .iter()
+                .enumerate()
+                .map(|(index, endpoint)| (index, &endpoint.uri[..]))
+                .collect();
+        }
+
+        Ring {
+            ring: HashRing::new(healthy),
+            expires_at,
+        }
+    }
+
+    fn rebuild_ring(&self) -> Arc<Ring> {
+        let ring = Arc::new(Self::build_ring(&self.endpoints));
+        *self.ring.write().unwrap() = Arc::clone(&ring);
+        ring
+    }
+
+    fn get_endpoint(&self, key: u64) -> &Endpoint {
+        let mut ring = Arc::clone(&self.ring.read().unwrap());
+        if matches!(ring.expires_at, Some(at) if at <= Instant::now()) {
+            ring = self.rebuild_ring();
+        }
+
+        // the ring always contains at least one endpoint
+        &self.endpoints[ring.ring.get(key).unwrap_or(0)]
+    }
+
+    fn record_failure(&self, endpoint: &Endpoint) {
+        let failures = endpoint.failures.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if failures >= self.failure_threshold {
+            endpoint.failures.store(0, Ordering::Relaxed);
+            *endpoint.unhealthy_until.lock().unwrap() = Some(Instant::now() + self.cooldown);
+
+            warn!(
+                "Worker {} failed {} requests in a row, removing it for {:?}",
+                endpoint.uri, failures, self.cooldown
+            );
+
+            self.rebuild_ring();
+        }
+    }
+
+    async fn send_event(
         &self,
-        config: &Config,
         event: event_forwarding::Event<'_>,
-        _guild_id: Option<Snowflake>,
+        guild_id: Option<Snowflake>,
     ) -> Result<(), GatewayError> {
-        let uri = config.get_worker_svc_uri();
+        // events outside of a guild still need a stable worker, so route them by bot instead
+        let endpoint = self.get_endpoint(guild_id.map(|id| id.0).unwrap_or(event.bot_id));
 
         // reqwest::Client uses Arcs internally, meaning this method clones the same client but
         // allows us to make use of connection pooling
-        let req = self.client.clone().post(uri).json(&event);
+        let req = self.client.clone().post(&endpoint.uri[..]).json(&event);
+
+        // only count transport errors against the worker: an error response means it's still up
+        let bytes = match req.send().await {
+            Ok(res) => res.bytes().await,
+            Err(e) => Err(e),
+        };
 
-        let res = req.send().await?;
-        let bytes = res.bytes().await?;
+        let bytes = match bytes {
+            Ok(bytes) => {
+                endpoint.failures.store(0, Ordering::Relaxed);
+                bytes
+            }
+            Err(e) => {
+                self.record_failure(endpoint);
+                return Err(e.into());
+            }
+        };
 
         let res = serde_json::from_slice::<WorkerResponse>(&bytes);
         let res = match res {
@@ -66,3 +199,22 @@ impl EventForwarder for HttpEventForwarder {
         Ok(())
     }
 }
+
+#[async_trait]
+impl EventForwarder for HttpEventForwarder {
+    async fn forward_event(
+        &self,
+        _config: &Config,
+        event: event_forwarding::Event<'_>,
+        guild_id: Option<Snowflake>,
+    ) -> Result<(), GatewayError> {
+        let labels = ShardLabels::new(Snowflake(event.bot_id), event.shard_id);
+
+        let res = self.send_event(event, guild_id).await;
+        if res.is_err() {
+            METRICS.worker_request_failures.inc(labels);
+        }
+
+        res
+    }
+}
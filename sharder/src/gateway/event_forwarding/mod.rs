@@ -1,8 +1,20 @@
 mod event_forwarder;
 pub use event_forwarder::EventForwarder;
 
+mod hash_ring;
+pub use hash_ring::HashRing;
+
 mod http;
 pub use http::HttpEventForwarder;
 
+mod redis_stream;
+pub use redis_stream::RedisStreamEventForwarder;
+
+mod retry;
+pub use retry::RetryEventForwarder;
+
 mod util;
-pub use util::{get_guild_id, is_whitelisted};
+pub use util::get_guild_id;
+
+mod whitelist;
+pub use whitelist::{EventWhitelist, UnknownEventError};
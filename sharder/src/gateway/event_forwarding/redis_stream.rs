@@ -0,0 +1,75 @@
+use crate::event_forwarding::EventForwarder;
+use crate::{Config, GatewayError};
+use async_trait::async_trait;
+use common::event_forwarding;
+use deadpool_redis::{cmd, Pool};
+use model::Snowflake;
+use std::sync::Arc;
+
+pub struct RedisStreamEventForwarder {
+    redis: Arc<Pool>,
+    max_len: usize,
+    partitions: Option<u16>,
+}
+
+impl RedisStreamEventForwarder {
+    /// If partitions is Some, events are spread across one stream per partition, keyed by a hash
+    /// of the guild ID, so that all events for a guild land on the same stream
+    pub fn new(redis: Arc<Pool>, max_len: usize, partitions: Option<u16>) -> Self {
+        RedisStreamEventForwarder {
+            redis,
+            max_len,
+            partitions: partitions.filter(|&partitions| partitions > 1),
+        }
+    }
+
+    pub fn from_config(config: &Config, redis: Arc<Pool>) -> Self {
+        Self::new(
+            redis,
+            config.event_stream_max_len,
+            config.event_stream_partitions,
+        )
+    }
+
+    fn get_key(&self, guild_id: Option<Snowflake>) -> String {
+        match self.partitions {
+            Some(partitions) => {
+                // same formula Discord uses to pick a guild's shard
+                let partition = guild_id
+                    .map(|id| (id.0 >> 22) % partitions as u64)
+                    .unwrap_or(0);
+                event_forwarding::partitioned_key(partition as u16)
+            }
+            None => event_forwarding::EVENT_KEY.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl EventForwarder for RedisStreamEventForwarder {
+    async fn forward_event(
+        &self,
+        _config: &Config,
+        event: event_forwarding::Event<'_>,
+        guild_id: Option<Snowflake>,
+    ) -> Result<(), GatewayError> {
+        let encoded = serde_json::to_string(&event)?;
+        let key = self.get_key(guild_id);
+
+        let mut conn = self.redis.get().await?;
+
+        // approximate trimming (~) lets Redis evict whole radix tree nodes, which is much cheaper
+        let _: redis::Value = cmd("XADD")
+            .arg(&key[..])
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(self.max_len)
+            .arg("*")
+            .arg(event_forwarding::EVENT_FIELD)
+            .arg(encoded)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use crate::event_forwarding::EventForwarder;
+use crate::{Config, GatewayError};
+use async_trait::async_trait;
+use backoff::ExponentialBackoff;
+use common::event_forwarding;
+use deadpool_redis::{cmd, Pool};
+use log::{debug, error};
+use model::Snowflake;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Wraps another EventForwarder, retrying failed forwards with exponential backoff and jitter.
+/// Events that still can't be delivered are pushed to a dead letter list in Redis, from which
+/// they can be replayed with the replay_dead_letters binary. Once stop_retrying has been called,
+/// forwards in progress are abandoned and any others are dead-lettered straight away, rather than
+/// being lost when the process exits.
+pub struct RetryEventForwarder<T: EventForwarder> {
+    inner: T,
+    redis: Arc<Pool>,
+    initial_interval: Duration,
+    max_interval: Duration,
+    max_elapsed_time: Duration,
+    dead_letter: bool,
+    stop_tx: watch::Sender<bool>,
+    stop_rx: watch::Receiver<bool>,
+}
+
+impl<T: EventForwarder> RetryEventForwarder<T> {
+    pub fn new(
+        inner: T,
+        redis: Arc<Pool>,
+        initial_interval: Duration,
+        max_interval: Duration,
+        max_elapsed_time: Duration,
+        dead_letter: bool,
+    ) -> Self {
+        let (stop_tx, stop_rx) = watch::channel(false);
+
+        RetryEventForwarder {
+            inner,
+            redis,
+            initial_interval,
+            max_interval,
+            max_elapsed_time,
+            dead_letter,
+            stop_tx,
+            stop_rx,
+        }
+    }
+
+    pub fn from_config(config: &Config, inner: T, redis: Arc<Pool>) -> Self {
+        Self::new(
+            inner,
+            redis,
+            Duration::from_millis(config.forward_retry_initial_interval),
+            Duration::from_millis(config.forward_retry_max_interval),
+            Duration::from_millis(config.forward_retry_max_elapsed),
+            config.dead_letter_enabled,
+        )
+    }
+
+    fn build_backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoff {
+            current_interval: self.initial_interval,
+            initial_interval: self.initial_interval,
+            max_interval: self.max_interval,
+            max_elapsed_time: Some(self.max_elapsed_time),
+            ..Default::default()
+        }
+    }
+
+    async fn push_dead_letter(
+        &self,
+        event: event_forwarding::Event<'_>,
+        guild_id: Option<Snowflake>,
+        err: &GatewayError,
+    ) -> Result<(), GatewayError> {
+        let dead_letter = event_forwarding::DeadLetter {
+            event,
+            guild_id,
+            error: err.to_string(),
+        };
+
+        let encoded = serde_json::to_string(&dead_letter)?;
+
+        let mut conn = self.redis.get().await?;
+        let _: redis::Value = cmd("LPUSH")
+            .arg(event_forwarding::DEAD_LETTER_KEY)
+            .arg(encoded)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: EventForwarder> EventForwarder for RetryEventForwarder<T> {
+    async fn forward_event(
+        &self,
+        config: &Config,
+        event: event_forwarding::Event<'_>,
+        guild_id: Option<Snowflake>,
+    ) -> Result<(), GatewayError> {
+        let mut stopped = self.stop_rx.clone();
+        let mut last_error = None;
+
+        let res = if *stopped.borrow() {
+            None
+        } else {
+            let retry = backoff::future::retry_notify(
+                self.build_backoff(),
+                || async {
+                    self.inner
+                        .forward_event(config, event.clone(), guild_id)
+                        .await
+                        .map_err(|e| match e {
+                            // the payload will never serialize, no point retrying
+                            GatewayError::JsonError(_) => backoff::Error::Permanent(e),
+                            _ => backoff::Error::Transient(e),
+                        })
+                },
+                |e: GatewayError, delay| {
+                    debug!("Failed to forward event, retrying in {:?}: {}", delay, e);
+                    last_error = Some(e.to_string());
+                },
+            );
+
+            // the worker may never respond, so the attempt in progress is abandoned too
+            tokio::select! {
+                res = retry => Some(res),
+                _ = stopped.changed() => None,
+            }
+        };
+
+        let err = match res {
+            Some(Ok(())) => return Ok(()),
+            Some(Err(e)) => e,
+            None => GatewayError::ForwardCancelledError(
+                last_error.unwrap_or_else(|| "no attempt had completed".to_owned()),
+            ),
+        };
+
+        if self.dead_letter {
+            if let Err(e) = self.push_dead_letter(event, guild_id, &err).await {
+                error!("Error pushing event to dead letter queue: {}", e);
+            }
+        }
+
+        Err(err)
+    }
+
+    fn stop_retrying(&self) {
+        let _ = self.stop_tx.send(true);
+    }
+}
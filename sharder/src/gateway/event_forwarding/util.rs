@@ -1,5 +1,6 @@
 use crate::gateway::payloads::event::Event;
 use model::Snowflake;
+use serde::Deserialize;
 
 pub fn get_guild_id(event: &Event) -> Option<Snowflake> {
     match event {
@@ -41,20 +42,15 @@ pub fn get_guild_id(event: &Event) -> Option<Snowflake> {
         Event::VoiceStateUpdate(data) => data.guild_id,
         Event::VoiceServerUpdate(data) => Some(data.guild_id),
         Event::WebhookUpdate(data) => Some(data.guild_id),
+        Event::Unknown { d, .. } => serde_json::from_str::<GuildIdOnly>(d.get())
+            .ok()
+            .and_then(|data| data.guild_id),
         _ => None,
     }
 }
 
-// TODO: Don't hardcode, use feature flags or something
-pub fn is_whitelisted(event: &Event) -> bool {
-    matches!(
-        event,
-        Event::ChannelDelete(_)
-            | Event::GuildCreate(_)
-            | Event::GuildDelete(_)
-            | Event::GuildMemberUpdate(_)
-            | Event::GuildMemberRemove(_)
-            | Event::MessageCreate(_)
-            | Event::GuildRoleDelete(_)
-    )
+// most guild events carry a top level guild_id, so it can be read without knowing the event
+#[derive(Deserialize)]
+struct GuildIdOnly {
+    guild_id: Option<Snowflake>,
 }
@@ -0,0 +1,69 @@
+use crate::gateway::payloads::event::Event;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use thiserror::Error;
+
+const DEFAULT_EVENTS: &[&str] = &[
+    "CHANNEL_DELETE",
+    "GUILD_CREATE",
+    "GUILD_DELETE",
+    "GUILD_MEMBER_UPDATE",
+    "GUILD_MEMBER_REMOVE",
+    "MESSAGE_CREATE",
+    "GUILD_ROLE_DELETE",
+];
+
+/// The set of events that are forwarded to the workers, by name (e.g. MESSAGE_CREATE). Names are
+/// validated against the Event enum on construction, so a typo is caught at startup rather than
+/// silently dropping events.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "Vec<String>")]
+pub struct EventWhitelist {
+    events: HashSet<&'static str>,
+}
+
+#[derive(Error, Debug)]
+#[error("unknown event name: {0}")]
+pub struct UnknownEventError(pub String);
+
+impl EventWhitelist {
+    pub fn new<S: AsRef<str>>(names: &[S]) -> Result<EventWhitelist, UnknownEventError> {
+        let events = names
+            .iter()
+            .map(|name| {
+                let name = name.as_ref().trim();
+
+                Event::NAMES
+                    .iter()
+                    .find(|known| known.eq_ignore_ascii_case(name))
+                    .copied()
+                    .ok_or_else(|| UnknownEventError(name.to_owned()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(EventWhitelist { events })
+    }
+
+    pub fn is_whitelisted(&self, name: &str) -> bool {
+        self.events.contains(name)
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.events.iter().copied()
+    }
+}
+
+impl Default for EventWhitelist {
+    fn default() -> Self {
+        EventWhitelist::new(DEFAULT_EVENTS).expect("default event whitelist is invalid")
+    }
+}
+
+impl TryFrom<Vec<String>> for EventWhitelist {
+    type Error = UnknownEventError;
+
+    fn try_from(names: Vec<String>) -> Result<Self, Self::Error> {
+        EventWhitelist::new(&names)
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// how far the old shard set may lag behind the new one in reading an event after the switch
+const SUPPRESSED_WINDOW: Duration = Duration::from_secs(30);
+
+/// Decides which set of shards forwards events while the bot is being resharded. Only shards
+/// identified with the active shard total forward events. Both sets receive every event while they
+/// overlap, each at a slightly different time, so the events the new set holds back before the
+/// switch are recorded for a short while. If the old set reads one of them only after the switch,
+/// it forwards it rather than leaving it to the new set, which has already passed it by. An event
+/// around the switch may still be forwarded by both sets, and the gate is local to each sharder,
+/// which switches once its own new shards are ready, so workers must tolerate duplicated events
+/// for the duration of a reshard.
+#[derive(Debug)]
+pub struct ForwardingGate {
+    active_total: AtomicU16,
+    suppressed: Mutex<Suppressed>,
+}
+
+#[derive(Debug, Default)]
+struct Suppressed {
+    // the total that was active before the last switch
+    previous_total: Option<u16>,
+    // when each event held back by an inactive set was last seen, by hash, in order of expiry
+    seen: HashMap<u64, Instant>,
+    expiries: VecDeque<(Instant, u64)>,
+}
+
+impl ForwardingGate {
+    pub fn new(active_total: u16) -> ForwardingGate {
+        ForwardingGate {
+            active_total: AtomicU16::new(active_total),
+            suppressed: Mutex::new(Suppressed::default()),
+        }
+    }
+
+    pub fn is_open(&self, shard_total: u16) -> bool {
+        self.active_total.load(Ordering::Relaxed) == shard_total
+    }
+
+    /// Whether a shard identified with shard_total should forward the event, called as the event
+    /// is read from the gateway
+    pub fn admit(&self, shard_total: u16, event_name: &str, data: &str) -> bool {
+        if self.is_open(shard_total) {
+            return true;
+        }
+
+        let hash = hash_event(event_name, data);
+        let now = Instant::now();
+
+        let mut suppressed = self.suppressed.lock().unwrap();
+        suppressed.expire(now);
+
+        if suppressed.previous_total == Some(shard_total) {
+            // the new set held the event back before the switch, so only we can forward it
+            suppressed.seen.remove(&hash).is_some()
+        } else {
+            suppressed.seen.insert(hash, now);
+            suppressed
+                .expiries
+                .push_back((now + SUPPRESSED_WINDOW, hash));
+            false
+        }
+    }
+
+    pub fn active_total(&self) -> u16 {
+        self.active_total.load(Ordering::Relaxed)
+    }
+
+    pub fn switch_to(&self, shard_total: u16) {
+        let mut suppressed = self.suppressed.lock().unwrap();
+        let previous = self.active_total.swap(shard_total, Ordering::Relaxed);
+        suppressed.previous_total = Some(previous);
+    }
+}
+
+impl Suppressed {
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(expires_at, hash)) = self.expiries.front() {
+            if expires_at > now {
+                break;
+            }
+
+            self.expiries.pop_front();
+
+            // the event may have been seen again since, in which case it's kept
+            if matches!(self.seen.get(&hash), Some(seen) if *seen + SUPPRESSED_WINDOW <= now) {
+                self.seen.remove(&hash);
+            }
+        }
+    }
+}
+
+// the same event carries a different sequence number on each set's connection, so only its name
+// and data are compared
+fn hash_event(event_name: &str, data: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    event_name.hash(&mut hasher);
+    data.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,60 @@
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use log::error;
+use model::Snowflake;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+
+type Queues = Arc<Mutex<HashMap<Option<Snowflake>, VecDeque<BoxFuture<'static, ()>>>>>;
+
+/// Runs the jobs pushed for each guild one at a time, in the order they were pushed, so that a
+/// guild's events are cached and forwarded in the order they were received, with a failed forward
+/// retried before the next is sent. Guilds are run concurrently, each on a task that exits once
+/// its queue is empty. Jobs outside of a guild share one queue.
+pub(crate) struct GuildQueue {
+    queues: Queues,
+}
+
+impl GuildQueue {
+    pub fn new() -> GuildQueue {
+        GuildQueue {
+            queues: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn push<F>(&self, guild_id: Option<Snowflake>, job: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut queues = self.queues.lock().unwrap();
+
+        match queues.get_mut(&guild_id) {
+            // the guild's task runs the job once it has finished those before it
+            Some(queue) => queue.push_back(Box::pin(job)),
+            None => {
+                queues.insert(guild_id, VecDeque::new());
+                tokio::spawn(run(Arc::clone(&self.queues), guild_id, Box::pin(job)));
+            }
+        }
+    }
+}
+
+async fn run(queues: Queues, guild_id: Option<Snowflake>, mut job: BoxFuture<'static, ()>) {
+    loop {
+        // a panicking job mustn't leave the guild's queue without a task to run it
+        if AssertUnwindSafe(job).catch_unwind().await.is_err() {
+            error!("Event handler for guild {:?} panicked", guild_id);
+        }
+
+        let mut pending = queues.lock().unwrap();
+        job = match pending.get_mut(&guild_id).and_then(VecDeque::pop_front) {
+            Some(next) => next,
+            None => {
+                pending.remove(&guild_id);
+                return;
+            }
+        };
+    }
+}
@@ -1,4 +1,10 @@
-#[derive(Copy, Clone)]
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::ops::BitOr;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Copy, Clone, Debug)]
 #[allow(dead_code)]
 pub enum Intents {
     Guilds = 1 << 0,
@@ -24,4 +30,138 @@ impl Intents {
         intents.into_iter().for_each(|i| sum |= i as u64);
         sum
     }
+
+    /// The intent that must be enabled to receive the given event in guilds, if any
+    pub fn required_for(event_name: &str) -> Option<Intents> {
+        match event_name {
+            "GUILD_CREATE"
+            | "GUILD_UPDATE"
+            | "GUILD_DELETE"
+            | "GUILD_ROLE_CREATE"
+            | "GUILD_ROLE_UPDATE"
+            | "GUILD_ROLE_DELETE"
+            | "CHANNEL_CREATE"
+            | "CHANNEL_UPDATE"
+            | "CHANNEL_DELETE"
+            | "CHANNEL_PINS_UPDATE"
+            | "THREAD_CREATE"
+            | "THREAD_UPDATE"
+            | "THREAD_DELETE"
+            | "THREAD_LIST_SYNC"
+            | "THREAD_MEMBER_UPDATE"
+            | "STAGE_INSTANCE_CREATE"
+            | "STAGE_INSTANCE_UPDATE"
+            | "STAGE_INSTANCE_DELETE" => Some(Intents::Guilds),
+            "GUILD_MEMBER_ADD"
+            | "GUILD_MEMBER_UPDATE"
+            | "GUILD_MEMBER_REMOVE"
+            | "THREAD_MEMBERS_UPDATE" => Some(Intents::GuildMembers),
+            "GUILD_BAN_ADD" | "GUILD_BAN_REMOVE" => Some(Intents::GuildBans),
+            "GUILD_EMOJIS_UPDATE" => Some(Intents::GuildEmojis),
+            "GUILD_INTEGRATIONS_UPDATE" => Some(Intents::GuildIntegrations),
+            "WEBHOOK_UPDATE" => Some(Intents::GuildWebhooks),
+            "INVITE_CREATE" | "INVITE_DELETE" => Some(Intents::GuildInvites),
+            "VOICE_STATE_UPDATE" => Some(Intents::GuildVoiceStates),
+            "PRESENCE_UPDATE" => Some(Intents::GuildPresences),
+            "MESSAGE_CREATE" | "MESSAGE_UPDATE" | "MESSAGE_DELETE" | "MESSAGE_DELETE_BULK" => {
+                Some(Intents::GuildMessages)
+            }
+            "MESSAGE_REACTION_ADD"
+            | "MESSAGE_REACTION_REMOVE"
+            | "MESSAGE_REACTION_REMOVE_ALL"
+            | "MESSAGE_REACTION_REMOVE_EMOJI" => Some(Intents::GuildMessageReactions),
+            "TYPING_START" => Some(Intents::GuildMessageTyping),
+            _ => None,
+        }
+    }
+}
+
+impl BitOr for Intents {
+    type Output = u64;
+
+    fn bitor(self, rhs: Intents) -> u64 {
+        self as u64 | rhs as u64
+    }
+}
+
+impl BitOr<Intents> for u64 {
+    type Output = u64;
+
+    fn bitor(self, rhs: Intents) -> u64 {
+        self | rhs as u64
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("unknown intent: {0}")]
+pub struct UnknownIntentError(pub String);
+
+impl FromStr for Intents {
+    type Err = UnknownIntentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let intent = match &s.trim().to_ascii_uppercase()[..] {
+            "GUILDS" => Intents::Guilds,
+            "GUILD_MEMBERS" => Intents::GuildMembers,
+            "GUILD_BANS" => Intents::GuildBans,
+            "GUILD_EMOJIS" => Intents::GuildEmojis,
+            "GUILD_INTEGRATIONS" => Intents::GuildIntegrations,
+            "GUILD_WEBHOOKS" => Intents::GuildWebhooks,
+            "GUILD_INVITES" => Intents::GuildInvites,
+            "GUILD_VOICE_STATES" => Intents::GuildVoiceStates,
+            "GUILD_PRESENCES" => Intents::GuildPresences,
+            "GUILD_MESSAGES" => Intents::GuildMessages,
+            "GUILD_MESSAGE_REACTIONS" => Intents::GuildMessageReactions,
+            "GUILD_MESSAGE_TYPING" => Intents::GuildMessageTyping,
+            "DIRECT_MESSAGES" => Intents::DirectMessages,
+            "DIRECT_MESSAGE_REACTIONS" => Intents::DirectMessageReaction,
+            "DIRECT_MESSAGE_TYPING" => Intents::DirectMessageTyping,
+            _ => return Err(UnknownIntentError(s.to_owned())),
+        };
+
+        Ok(intent)
+    }
+}
+
+/// A set of intents, as sent in identify. Deserializes from a list where each entry is either an
+/// intent name (e.g. GUILD_MEMBERS) or a raw bitmask, which are OR'd together.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(try_from = "Vec<String>")]
+pub struct IntentSet(u64);
+
+impl IntentSet {
+    pub fn new(bits: u64) -> IntentSet {
+        IntentSet(bits)
+    }
+
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub fn contains(self, intent: Intents) -> bool {
+        self.0 & intent as u64 != 0
+    }
+}
+
+impl Default for IntentSet {
+    fn default() -> Self {
+        IntentSet(Intents::Guilds | Intents::GuildMembers | Intents::GuildMessages)
+    }
+}
+
+impl TryFrom<Vec<String>> for IntentSet {
+    type Error = UnknownIntentError;
+
+    fn try_from(values: Vec<String>) -> Result<Self, Self::Error> {
+        let mut bits = 0;
+
+        for value in values {
+            match value.trim().parse::<u64>() {
+                Ok(mask) => bits |= mask,
+                Err(_) => bits = bits | value.parse::<Intents>()?,
+            }
+        }
+
+        Ok(IntentSet(bits))
+    }
 }
@@ -0,0 +1,96 @@
+use super::payloads::event::GuildMembersChunk;
+use model::guild::Member;
+use model::Snowflake;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{oneshot, Mutex};
+
+/// The members returned for a Request Guild Members payload, collected from every chunk
+#[derive(Debug)]
+pub struct GuildMembers {
+    pub guild_id: Snowflake,
+    pub members: Vec<Member>,
+    pub not_found: Vec<Snowflake>,
+}
+
+/// Tracks outstanding Request Guild Members payloads by nonce, collecting chunks until all
+/// chunk_count of them have been received.
+pub(crate) struct MemberChunkCollector {
+    next_nonce: AtomicU64,
+    pending: Mutex<HashMap<String, PendingRequest>>,
+}
+
+struct PendingRequest {
+    response: GuildMembers,
+    received: u32,
+    tx: oneshot::Sender<GuildMembers>,
+}
+
+impl MemberChunkCollector {
+    pub fn new() -> MemberChunkCollector {
+        MemberChunkCollector {
+            next_nonce: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Generates a nonce (unique to this shard) and returns a receiver that will resolve once all
+    /// chunks for it have been received
+    pub async fn register(&self, guild_id: Snowflake) -> (String, oneshot::Receiver<GuildMembers>) {
+        let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed).to_string();
+        let (tx, rx) = oneshot::channel();
+
+        let request = PendingRequest {
+            response: GuildMembers {
+                guild_id,
+                members: Vec::new(),
+                not_found: Vec::new(),
+            },
+            received: 0,
+            tx,
+        };
+
+        self.pending.lock().await.insert(nonce.clone(), request);
+        (nonce, rx)
+    }
+
+    pub async fn cancel(&self, nonce: &str) {
+        self.pending.lock().await.remove(nonce);
+    }
+
+    /// Cancels all outstanding requests, e.g. when the shard disconnects and the remaining chunks
+    /// will never arrive
+    pub async fn clear(&self) {
+        self.pending.lock().await.clear();
+    }
+
+    pub async fn handle_chunk(&self, chunk: &GuildMembersChunk) {
+        let nonce = match &chunk.nonce {
+            Some(nonce) => nonce,
+            None => return,
+        };
+
+        let mut pending = self.pending.lock().await;
+
+        let request = match pending.get_mut(nonce) {
+            Some(request) => request,
+            None => return,
+        };
+
+        request
+            .response
+            .members
+            .extend(chunk.members.iter().cloned());
+        if let Some(not_found) = &chunk.not_found {
+            request.response.not_found.extend_from_slice(not_found);
+        }
+
+        request.received += 1;
+        if request.received >= chunk.chunk_count {
+            if let Some(request) = pending.remove(nonce) {
+                // the requester may have timed out already
+                let _ = request.tx.send(request.response);
+            }
+        }
+    }
+}
@@ -1,8 +1,33 @@
 mod shard;
-pub use shard::Shard;
+pub use shard::{Shard, ShardServices};
+
+mod shard_mode;
+pub use shard_mode::{PublicMode, ShardMode, WhitelabelMode};
+
+mod traffic_recorder;
+pub use traffic_recorder::{RecordedPayload, TrafficRecorder};
+
+mod presence;
+pub use presence::{rotate_presence, Presence, TemplateVars};
+
+mod shard_status;
+pub use shard_status::{ConnectionState, ShardStatus};
+
+mod forwarding_gate;
+pub use forwarding_gate::ForwardingGate;
+
+mod guild_queue;
+use guild_queue::GuildQueue;
 
 mod payloads;
-pub use payloads::Identify;
+pub use payloads::event::Event;
+pub use payloads::{GuildMembersFilter, Identify, Payload};
+
+mod cache_update;
+pub use cache_update::{is_cached, update_cache};
+
+mod member_chunks;
+pub use member_chunks::GuildMembers;
 
 mod error;
 pub use error::GatewayError;
@@ -10,14 +35,20 @@ pub use error::GatewayError;
 mod outbound_message;
 use outbound_message::OutboundMessage;
 
+mod send_ratelimiter;
+pub use send_ratelimiter::SendRateLimiter;
+
 mod shardinfo;
 pub use shardinfo::ShardInfo;
 
+mod session_start_limit;
+pub use session_start_limit::{
+    get_gateway_bot, GatewayBot, SessionStartLimit, SessionStartLimiter,
+};
+
 mod intents;
-pub use intents::Intents;
+pub use intents::{IntentSet, Intents, UnknownIntentError};
 
 mod worker_response;
 
-mod whitelabel_utils;
-
 pub mod event_forwarding;
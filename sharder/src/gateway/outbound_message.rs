@@ -1,26 +1,62 @@
 use futures::channel::mpsc::SendError;
 use serde::Serialize;
+use std::borrow::Cow;
 use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
 
 #[derive(Debug)]
 pub struct OutboundMessage {
-    pub message: String,
+    pub message: Message,
+    pub kind: MessageKind,
     pub tx: oneshot::Sender<Result<(), SendError>>,
 }
 
+/// Determines how the writer schedules a message against the gateway send limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageKind {
+    /// Sent ahead of any queued commands, and may use the capacity reserved for heartbeats
+    Heartbeat,
+    /// Only the most recent pending presence update is sent
+    PresenceUpdate,
+    Command,
+    /// Not a gateway command, so isn't counted against the limit
+    Close,
+}
+
 impl OutboundMessage {
     pub fn new<T: Serialize>(
         msg: T,
+        kind: MessageKind,
         tx: oneshot::Sender<Result<(), SendError>>,
     ) -> Result<OutboundMessage, serde_json::Error> {
         let serialized = serde_json::to_string(&msg)?;
 
         Ok(OutboundMessage {
-            message: serialized,
+            message: Message::text(serialized),
+            kind,
             tx,
         })
     }
 
+    pub fn close(
+        code: u16,
+        reason: &'static str,
+        tx: oneshot::Sender<Result<(), SendError>>,
+    ) -> OutboundMessage {
+        let frame = CloseFrame {
+            code: CloseCode::from(code),
+            reason: Cow::Borrowed(reason),
+        };
+
+        OutboundMessage {
+            message: Message::Close(Some(frame)),
+            kind: MessageKind::Close,
+            tx,
+        }
+    }
+
     pub async fn send(
         self,
         tx: mpsc::Sender<OutboundMessage>,
@@ -101,6 +101,8 @@ pub struct GuildMemberUpdate {
     pub nick: Option<String>,
     pub joined_at: DateTime<Utc>,
     pub premium_since: Option<DateTime<Utc>>,
+    pub deaf: Option<bool>,
+    pub mute: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -1,4 +1,6 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
 
 use model::channel::message::Message;
 use model::channel::{Channel, ThreadMember};
@@ -7,57 +9,104 @@ use model::interaction::ApplicationCommand;
 use model::stage::StageInstance;
 use model::user::{PresenceUpdate, User};
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(tag = "t", content = "d")]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum Event {
-    Ready(super::Ready),
-    Resumed(serde_json::Value),
-    ApplicationCommandCreate(ApplicationCommand),
-    ApplicationCommandUpdate(ApplicationCommand),
-    ApplicationCommandDelete(ApplicationCommand),
-    ChannelCreate(Channel),
-    ChannelUpdate(Channel),
-    ChannelDelete(Channel),
-    ChannelPinsUpdate(super::ChannelPinsUpdate),
-    ThreadCreate(Channel),
-    ThreadUpdate(Channel),
-    ThreadDelete(super::ThreadDelete),
-    ThreadListSync(super::ThreadListSync),
-    ThreadMemberUpdate(ThreadMember),
-    ThreadMembersUpdate(super::ThreadMembersUpdate),
-    GuildCreate(Guild),
-    GuildUpdate(Guild),
-    GuildDelete(UnavailableGuild),
-    GuildBanAdd(super::GuildBanAdd),
-    GuildBanRemove(super::GuildBanRemove),
-    GuildEmojisUpdate(super::GuildEmojisUpdate),
-    GuildIntegrationsUpdate(super::GuildIntegrationsUpdate),
-    GuildJoinRequestDelete(super::GuildJoinRequestDelete),
-    GuildMemberAdd(super::GuildMemberAdd),
-    GuildMemberRemove(super::GuildMemberRemove),
-    GuildMemberUpdate(super::GuildMemberUpdate),
-    GuildMembersChunk(super::GuildMembersChunk),
-    GuildRoleCreate(super::GuildRoleCreate),
-    GuildRoleUpdate(super::GuildRoleUpdate),
-    GuildRoleDelete(super::GuildRoleDelete),
-    InviteCreate(super::InviteCreate),
-    InviteDelete(super::InviteDelete),
-    MessageCreate(Message),
-    MessageUpdate(Message),
-    MessageDelete(super::MessageDelete),
-    MessageDeleteBulk(super::MessageDeleteBulk),
-    MessageReactionAdd(super::MessageReactionAdd),
-    MessageReactionRemove(super::MessageReactionRemove),
-    MessageReactionRemoveAll(super::MessageReactionRemoveAll),
-    MessageReactionRemoveEmoji(super::MessageReactionRemoveEmoji),
-    PresenceUpdate(PresenceUpdate),
-    StageInstanceCreate(StageInstance),
-    StageInstanceUpdate(StageInstance),
-    StageInstanceDelete(StageInstance),
-    TypingStart(super::TypingStart),
-    UserUpdate(User),
-    VoiceStateUpdate(VoiceState),
-    VoiceServerUpdate(super::VoiceServerUpdate),
-    WebhookUpdate(super::WebhooksUpdate),
+// Generates the Event enum, along with the list of event names, from a single table so that the
+// two can't get out of sync
+macro_rules! events {
+    ($($variant:ident($data:ty) => $name:literal,)*) => {
+        #[derive(Serialize, Deserialize, Debug)]
+        #[serde(tag = "t", content = "d")]
+        pub enum Event {
+            $(
+                #[serde(rename = $name)]
+                $variant($data),
+            )*
+
+            /// A dispatch that isn't modelled yet, e.g. an event added by Discord since. Never
+            /// produced by deserializing an Event directly: see Dispatch::parse.
+            #[serde(skip)]
+            Unknown { t: String, d: Box<RawValue> },
+        }
+
+        impl Event {
+            /// The names of all events, as sent by Discord in the t field of a dispatch
+            pub const NAMES: &'static [&'static str] = &[$($name),*];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Event::$variant(_) => $name,)*
+                    Event::Unknown { .. } => "UNKNOWN",
+                }
+            }
+
+            /// Deserializes the data of a dispatch, given the event name from its t field. Names
+            /// that aren't in the table produce Event::Unknown, with the data kept as it is.
+            pub fn parse(name: &str, data: &RawValue) -> serde_json::Result<Event> {
+                match name {
+                    $($name => parse_data(data).map(Event::$variant),)*
+                    _ => Ok(Event::Unknown {
+                        t: name.to_owned(),
+                        d: data.to_owned(),
+                    }),
+                }
+            }
+        }
+    };
+}
+
+// read in place from the frame, but the models own their fields, so strings are still copied out
+// of it as they're parsed
+fn parse_data<T: DeserializeOwned>(data: &RawValue) -> serde_json::Result<T> {
+    serde_json::from_str(data.get())
+}
+
+events! {
+    Ready(super::Ready) => "READY",
+    Resumed(serde_json::Value) => "RESUMED",
+    ApplicationCommandCreate(ApplicationCommand) => "APPLICATION_COMMAND_CREATE",
+    ApplicationCommandUpdate(ApplicationCommand) => "APPLICATION_COMMAND_UPDATE",
+    ApplicationCommandDelete(ApplicationCommand) => "APPLICATION_COMMAND_DELETE",
+    ChannelCreate(Channel) => "CHANNEL_CREATE",
+    ChannelUpdate(Channel) => "CHANNEL_UPDATE",
+    ChannelDelete(Channel) => "CHANNEL_DELETE",
+    ChannelPinsUpdate(super::ChannelPinsUpdate) => "CHANNEL_PINS_UPDATE",
+    ThreadCreate(Channel) => "THREAD_CREATE",
+    ThreadUpdate(Channel) => "THREAD_UPDATE",
+    ThreadDelete(super::ThreadDelete) => "THREAD_DELETE",
+    ThreadListSync(super::ThreadListSync) => "THREAD_LIST_SYNC",
+    ThreadMemberUpdate(ThreadMember) => "THREAD_MEMBER_UPDATE",
+    ThreadMembersUpdate(super::ThreadMembersUpdate) => "THREAD_MEMBERS_UPDATE",
+    GuildCreate(Guild) => "GUILD_CREATE",
+    GuildUpdate(Guild) => "GUILD_UPDATE",
+    GuildDelete(UnavailableGuild) => "GUILD_DELETE",
+    GuildBanAdd(super::GuildBanAdd) => "GUILD_BAN_ADD",
+    GuildBanRemove(super::GuildBanRemove) => "GUILD_BAN_REMOVE",
+    GuildEmojisUpdate(super::GuildEmojisUpdate) => "GUILD_EMOJIS_UPDATE",
+    GuildIntegrationsUpdate(super::GuildIntegrationsUpdate) => "GUILD_INTEGRATIONS_UPDATE",
+    GuildJoinRequestDelete(super::GuildJoinRequestDelete) => "GUILD_JOIN_REQUEST_DELETE",
+    GuildMemberAdd(super::GuildMemberAdd) => "GUILD_MEMBER_ADD",
+    GuildMemberRemove(super::GuildMemberRemove) => "GUILD_MEMBER_REMOVE",
+    GuildMemberUpdate(super::GuildMemberUpdate) => "GUILD_MEMBER_UPDATE",
+    GuildMembersChunk(super::GuildMembersChunk) => "GUILD_MEMBERS_CHUNK",
+    GuildRoleCreate(super::GuildRoleCreate) => "GUILD_ROLE_CREATE",
+    GuildRoleUpdate(super::GuildRoleUpdate) => "GUILD_ROLE_UPDATE",
+    GuildRoleDelete(super::GuildRoleDelete) => "GUILD_ROLE_DELETE",
+    InviteCreate(super::InviteCreate) => "INVITE_CREATE",
+    InviteDelete(super::InviteDelete) => "INVITE_DELETE",
+    MessageCreate(Message) => "MESSAGE_CREATE",
+    MessageUpdate(Message) => "MESSAGE_UPDATE",
+    MessageDelete(super::MessageDelete) => "MESSAGE_DELETE",
+    MessageDeleteBulk(super::MessageDeleteBulk) => "MESSAGE_DELETE_BULK",
+    MessageReactionAdd(super::MessageReactionAdd) => "MESSAGE_REACTION_ADD",
+    MessageReactionRemove(super::MessageReactionRemove) => "MESSAGE_REACTION_REMOVE",
+    MessageReactionRemoveAll(super::MessageReactionRemoveAll) => "MESSAGE_REACTION_REMOVE_ALL",
+    MessageReactionRemoveEmoji(super::MessageReactionRemoveEmoji) => "MESSAGE_REACTION_REMOVE_EMOJI",
+    PresenceUpdate(PresenceUpdate) => "PRESENCE_UPDATE",
+    StageInstanceCreate(StageInstance) => "STAGE_INSTANCE_CREATE",
+    StageInstanceUpdate(StageInstance) => "STAGE_INSTANCE_UPDATE",
+    StageInstanceDelete(StageInstance) => "STAGE_INSTANCE_DELETE",
+    TypingStart(super::TypingStart) => "TYPING_START",
+    UserUpdate(User) => "USER_UPDATE",
+    VoiceStateUpdate(VoiceState) => "VOICE_STATE_UPDATE",
+    VoiceServerUpdate(super::VoiceServerUpdate) => "VOICE_SERVER_UPDATE",
+    WebhookUpdate(super::WebhooksUpdate) => "WEBHOOK_UPDATE",
 }
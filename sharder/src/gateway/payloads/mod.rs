@@ -5,8 +5,6 @@ pub use payload::Payload;
 
 mod opcode;
 pub use opcode::Opcode;
-mod dispatch;
-pub use dispatch::Dispatch;
 
 mod heartbeat;
 pub use heartbeat::Heartbeat;
@@ -17,6 +15,9 @@ pub use identify::{Identify, IdentifyData};
 mod presence_update;
 pub use presence_update::PresenceUpdate;
 
+mod request_guild_members;
+pub use request_guild_members::{GuildMembersFilter, RequestGuildMembers};
+
 mod resume;
 pub use resume::Resume;
 
@@ -1,12 +1,27 @@
 use super::Opcode;
 
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use serde_json::value::RawValue;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Payload {
+/// The envelope of a payload received from the gateway, borrowed from the frame. The data is
+/// left unparsed, so that only the events we act on have to be deserialized.
+#[derive(Deserialize, Debug)]
+pub struct Payload<'a> {
     #[serde(rename = "op")]
     pub opcode: Opcode,
 
     #[serde(rename = "s")]
     pub seq: Option<usize>,
+
+    // event names never contain escapes, so can always be borrowed
+    #[serde(rename = "t")]
+    pub event_name: Option<&'a str>,
+
+    // null if the gateway leaves d out
+    #[serde(rename = "d", borrow, default = "null")]
+    pub data: &'a RawValue,
+}
+
+fn null() -> &'static RawValue {
+    serde_json::from_str("null").unwrap()
 }
@@ -0,0 +1,61 @@
+use super::Opcode;
+use model::Snowflake;
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub struct RequestGuildMembers {
+    #[serde(rename = "op")]
+    opcode: Opcode,
+
+    #[serde(rename = "d")]
+    data: RequestGuildMembersData,
+}
+
+impl RequestGuildMembers {
+    pub fn new(
+        guild_id: Snowflake,
+        filter: GuildMembersFilter,
+        presences: bool,
+        nonce: String,
+    ) -> RequestGuildMembers {
+        let (query, limit, user_ids) = match filter {
+            GuildMembersFilter::Query { query, limit } => (Some(query), Some(limit), None),
+            GuildMembersFilter::UserIds(user_ids) => (None, None, Some(user_ids)),
+        };
+
+        RequestGuildMembers {
+            opcode: Opcode::RequestGuildMembers,
+            data: RequestGuildMembersData {
+                guild_id,
+                query,
+                limit,
+                presences,
+                user_ids,
+                nonce,
+            },
+        }
+    }
+}
+
+/// Which members to request. Discord requires exactly one of query and user_ids to be sent.
+#[derive(Debug, Clone)]
+pub enum GuildMembersFilter {
+    /// Members whose username starts with query. An empty query with a limit of 0 requests all
+    /// members, which requires the GUILD_MEMBERS intent.
+    Query { query: String, limit: u16 },
+    /// Up to 100 specific members
+    UserIds(Vec<Snowflake>),
+}
+
+#[derive(Serialize, Debug)]
+struct RequestGuildMembersData {
+    guild_id: Snowflake,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<u16>,
+    presences: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_ids: Option<Vec<Snowflake>>,
+    nonce: String,
+}
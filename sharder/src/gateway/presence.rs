@@ -0,0 +1,141 @@
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use cache::Cache;
+use model::user::{ActivityType, StatusType, StatusUpdate};
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+use crate::config::Config;
+use crate::gateway::event_forwarding::EventForwarder;
+use crate::gateway::{Shard, ShardInfo, ShardMode};
+
+/// How a bot is shown in the member list. The messages are rotated through on an interval, and
+/// may contain the template variables `{guilds}` (guilds on the shard), `{shard}` (the shard ID)
+/// and `{shards}` (the shard total).
+#[derive(Debug, Clone)]
+pub struct Presence {
+    pub activity_type: ActivityType,
+    pub status_type: StatusType,
+    pub messages: Vec<String>,
+    pub rotate_interval: Duration,
+}
+
+impl Presence {
+    pub fn from_config(config: &Config) -> Presence {
+        Presence {
+            activity_type: config.presence_activity_type,
+            status_type: config.presence_status,
+            messages: config.presence_messages.clone(),
+            rotate_interval: Duration::from_millis(config.presence_rotate_interval),
+        }
+    }
+
+    /// The message at the given position in the rotation, wrapping around, with the template
+    /// variables filled in. None if there are no messages.
+    pub fn render(&self, index: usize, vars: &TemplateVars) -> Option<String> {
+        if self.messages.is_empty() {
+            return None;
+        }
+
+        let message = &self.messages[index % self.messages.len()];
+        Some(
+            message
+                .replace("{guilds}", &vars.guilds.to_string())
+                .replace("{shard}", &vars.shard_id.to_string())
+                .replace("{shards}", &vars.shard_total.to_string()),
+        )
+    }
+
+    pub fn status_update(&self, message: String) -> StatusUpdate {
+        StatusUpdate::new(self.activity_type, message, self.status_type)
+    }
+
+    /// The presence sent with the identify. The shard doesn't know which guilds it's in yet, so
+    /// this is the first message that doesn't show the guild count, if there is one.
+    pub fn initial(&self, shard_info: &ShardInfo) -> Option<StatusUpdate> {
+        let vars = TemplateVars {
+            guilds: 0,
+            shard_id: shard_info.shard_id,
+            shard_total: shard_info.num_shards,
+        };
+
+        let index = self
+            .messages
+            .iter()
+            .position(|message| !message.contains("{guilds}"))?;
+
+        self.render(index, &vars)
+            .map(|message| self.status_update(message))
+    }
+}
+
+pub struct TemplateVars {
+    pub guilds: usize,
+    pub shard_id: u16,
+    pub shard_total: u16,
+}
+
+/// Pushes the shard's presence whenever the rendered message changes: each time the rotation
+/// moves on, as the guild count changes, and straight away when a new presence is sent on the
+/// channel. Updates are only written once the shard's session is ready, so one pushed while the
+/// shard is reconnecting waits for the identify or resume to complete. Exits once the shard has
+/// been dropped or shut down, or the sender has been dropped.
+pub async fn rotate_presence<T: EventForwarder, M: ShardMode, C: Cache>(
+    shard: Weak<Shard<T, M, C>>,
+    mut presence: watch::Receiver<Arc<Presence>>,
+) {
+    let mut index = 0;
+    // the identify carried a message, but never one with the guild count
+    let mut last_sent = None;
+
+    loop {
+        let rotate_interval = presence.borrow().rotate_interval;
+
+        tokio::select! {
+            _ = sleep(rotate_interval) => index += 1,
+            res = presence.changed() => {
+                if res.is_err() {
+                    return;
+                }
+
+                index = 0;
+                last_sent = None;
+            }
+        }
+
+        let shard = match shard.upgrade() {
+            Some(shard) => shard,
+            None => return,
+        };
+
+        if shard.is_shutting_down() {
+            return;
+        }
+
+        let current = Arc::clone(&presence.borrow());
+        let vars = TemplateVars {
+            guilds: shard.guild_count().await,
+            shard_id: shard.get_shard_id(),
+            shard_total: shard.get_shard_total(),
+        };
+
+        let message = match current.render(index, &vars) {
+            Some(message) => message,
+            None => continue,
+        };
+
+        if last_sent.as_ref() == Some(&message) {
+            continue;
+        }
+
+        // if an update is already waiting to be sent, this one is retried on the next rotation
+        if shard
+            .status_update_tx
+            .try_send(current.status_update(message.clone()))
+            .is_ok()
+        {
+            last_sent = Some(message);
+        }
+    }
+}
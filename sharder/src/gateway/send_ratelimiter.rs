@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Discord disconnects shards that send more than 120 commands in 60 seconds. The limit is
+/// enforced over a sliding window, by remembering when each message in the last period was sent,
+/// so that no period of that length ever contains more than the limit. A number of tokens are held
+/// back that only priority messages (heartbeats) may use, so that a flood of other commands can't
+/// cause us to miss a heartbeat.
+pub struct SendRateLimiter {
+    limit: u32,
+    period: Duration,
+    reserved: u32,
+    // when each message in the current window was sent, oldest first
+    sent: VecDeque<Instant>,
+}
+
+impl SendRateLimiter {
+    pub fn new(limit: u32, period: Duration, reserved: u32) -> SendRateLimiter {
+        SendRateLimiter {
+            limit,
+            period,
+            reserved,
+            sent: VecDeque::with_capacity(limit as usize),
+        }
+    }
+
+    /// Takes a token, returning false if none are available to a message of this priority
+    pub fn try_acquire(&mut self, priority: bool) -> bool {
+        self.try_acquire_at(priority, Instant::now())
+    }
+
+    pub fn try_acquire_at(&mut self, priority: bool, now: Instant) -> bool {
+        self.expire(now);
+
+        if self.sent.len() < self.capacity(priority) {
+            self.sent.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// When a message of this priority may next be sent
+    pub fn available_at(&mut self, priority: bool) -> Instant {
+        self.available_at_from(priority, Instant::now())
+    }
+
+    pub fn available_at_from(&mut self, priority: bool, now: Instant) -> Instant {
+        self.expire(now);
+
+        let capacity = self.capacity(priority);
+        if self.sent.len() < capacity {
+            return now;
+        }
+
+        // once enough of the oldest messages have left the window to bring us under capacity
+        match self.sent.get(self.sent.len() - capacity) {
+            Some(sent_at) => *sent_at + self.period,
+            None => now + self.period, // no capacity at all, e.g. everything is reserved
+        }
+    }
+
+    fn capacity(&self, priority: bool) -> usize {
+        if priority {
+            self.limit as usize
+        } else {
+            self.limit.saturating_sub(self.reserved) as usize
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(sent_at) = self.sent.front() {
+            if now.saturating_duration_since(*sent_at) < self.period {
+                break;
+            }
+
+            self.sent.pop_front();
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{Config, GatewayError};
+use log::{error, warn};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+#[derive(Deserialize, Debug)]
+pub struct GatewayBot {
+    pub url: String,
+    pub shards: u16,
+    pub session_start_limit: SessionStartLimit,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct SessionStartLimit {
+    pub total: u32,
+    pub remaining: u32,
+    // ms
+    pub reset_after: u64,
+    pub max_concurrency: u16,
+}
+
+pub async fn get_gateway_bot(
+    client: &reqwest::Client,
+    api_url: &str,
+    token: &str,
+) -> Result<GatewayBot, GatewayError> {
+    let uri = format!("{}/gateway/bot", api_url.trim_end_matches('/'));
+
+    let res = client
+        .get(&uri)
+        .header("Authorization", format!("Bot {}", token))
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(GatewayError::DiscordApiError)?;
+
+    res.json().await.map_err(GatewayError::DiscordApiError)
+}
+
+/// Tracks how many sessions a bot may still start before Discord's daily limit resets, and holds
+/// back identifies once only the reserve is left, so that a reconnect loop can't burn through the
+/// remaining budget and get the token reset.
+pub struct SessionStartLimiter {
+    client: reqwest::Client,
+    api_url: String,
+    token: String,
+    reserve: u32,
+    // None until first fetched
+    limit: Mutex<Option<SessionStartLimit>>,
+}
+
+impl SessionStartLimiter {
+    pub fn new(
+        client: reqwest::Client,
+        config: &Config,
+        token: String,
+        limit: Option<SessionStartLimit>,
+    ) -> SessionStartLimiter {
+        SessionStartLimiter {
+            client,
+            api_url: config.discord_api_url.clone(),
+            token,
+            reserve: config.session_start_reserve,
+            limit: Mutex::new(limit),
+        }
+    }
+
+    /// Waits until a session may be started, and counts it against the budget. The lock is held
+    /// while waiting, so every shard sharing this limiter waits too.
+    pub async fn acquire(&self) {
+        let mut limit = self.limit.lock().await;
+
+        loop {
+            // our count is only an estimate, as other sharders identify with the same token, so
+            // check with Discord before concluding that we're out of budget
+            if !matches!(*limit, Some(current) if current.remaining > self.reserve) {
+                match get_gateway_bot(&self.client, &self.api_url, &self.token).await {
+                    Ok(gateway_bot) => *limit = Some(gateway_bot.session_start_limit),
+                    Err(e) => {
+                        error!(
+                            "Error fetching session start limit, identifying anyway: {}",
+                            e
+                        );
+                        return;
+                    }
+                }
+            }
+
+            if let Some(current) = limit.as_mut() {
+                if current.remaining > self.reserve {
+                    current.remaining -= 1;
+                    return;
+                }
+
+                let reset_after = Duration::from_millis(current.reset_after);
+                warn!(
+                    "Only {} of {} session starts remain, waiting {:?} for the limit to reset",
+                    current.remaining, current.total, reset_after
+                );
+
+                sleep(reset_after).await;
+                *limit = None;
+            }
+        }
+    }
+}
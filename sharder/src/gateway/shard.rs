@@ -1,11 +1,13 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Display;
 use std::str;
-use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 
-use deadpool_redis::{cmd, Pool};
+use deadpool_redis::{cmd, pipe, Pool};
 #[cfg(feature = "compression")]
 use flate2::{Decompress, FlushDecompress, Status};
 use futures::StreamExt;
@@ -14,30 +16,33 @@ use log::{debug, error, info};
 use serde::Serialize;
 use serde_json::value::RawValue;
 use tokio::sync::{mpsc, oneshot};
-use tokio::sync::{Mutex, RwLock};
-use tokio::time::sleep;
+use tokio::sync::{Mutex, Notify, RwLock};
+use tokio::time::{interval_at, sleep, timeout};
 use url::Url;
 
-use cache::{Cache, PostgresCache};
+use cache::Cache;
 use common::event_forwarding;
-#[cfg(feature = "whitelabel")]
-use database::Database;
-use model::guild::{Guild, Member};
+use common::event_forwarding::{GuildAvailability, GUILD_AVAILABLE, GUILD_UNAVAILABLE};
 use model::user::StatusUpdate;
 use model::Snowflake;
 
 use crate::config::Config;
-use crate::gateway::payloads::PresenceUpdate;
-use crate::gateway::whitelabel_utils::is_whitelabel;
-use crate::gateway::GatewayError;
+use crate::gateway::member_chunks::{GuildMembers, MemberChunkCollector};
+use crate::gateway::payloads::{GuildMembersFilter, PresenceUpdate, RequestGuildMembers};
+use crate::gateway::shard_status::{ConnectionState, ShardStatus};
+use crate::gateway::{
+    is_cached, update_cache, ForwardingGate, GatewayError, GuildQueue, SessionStartLimiter,
+    ShardMode, TrafficRecorder,
+};
+use crate::metrics::{ShardLabels, METRICS};
 
+use super::outbound_message::MessageKind;
 use super::payloads;
 use super::payloads::event::Event;
-use super::payloads::{Dispatch, Opcode, Payload};
+use super::payloads::{Opcode, Payload};
 use super::OutboundMessage;
-use crate::gateway::event_forwarding::{is_whitelisted, EventForwarder};
-use serde_json::error::Category;
-use serde_json::Value;
+use super::SendRateLimiter;
+use crate::gateway::event_forwarding::{EventForwarder, EventWhitelist};
 use std::error::Error;
 use tokio_tungstenite::{
     connect_async, tungstenite,
@@ -46,12 +51,62 @@ use tokio_tungstenite::{
 
 const GATEWAY_VERSION: u8 = 9;
 const SEQ_SAVE_DELAY: Duration = Duration::from_secs(5);
+const MEMBER_CHUNK_TIMEOUT: Duration = Duration::from_secs(30);
+// Discord invalidates the session if we close with 1000 or 1001
+const RESUMABLE_CLOSE_CODE: u16 = 4000;
+const SEND_LIMIT: u32 = 120;
+const SEND_LIMIT_PERIOD: Duration = Duration::from_secs(60);
+// a heartbeat roughly every 41s, plus any requested by the gateway
+const HEARTBEAT_RESERVE: u32 = 5;
+// the sets of bots in each guild are refreshed on every (re)connection and periodically while
+// connected, so only expire if no bot in the guild has been connected for some time
+const GUILD_BOTS_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const GUILD_BOTS_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Why a guild the shard is in is unavailable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unavailability {
+    /// Listed in READY, but its GUILD_CREATE hasn't been received yet
+    Pending,
+    /// Reported unavailable by GUILD_DELETE, during a Discord outage
+    Outage,
+}
+
+/// An event generated by the shard, forwarded in the same form as a gateway dispatch
+#[derive(Serialize)]
+struct SyntheticDispatch<T> {
+    #[serde(rename = "op")]
+    opcode: Opcode,
+    #[serde(rename = "t")]
+    event_name: &'static str,
+    #[serde(rename = "d")]
+    data: T,
+}
+
+// dispatches that the shard acts on itself, whether or not they're cached or forwarded
+const SHARD_EVENTS: &[&str] = &[
+    "READY",
+    "RESUMED",
+    "GUILD_CREATE",
+    "GUILD_DELETE",
+    "GUILD_MEMBERS_CHUNK",
+];
+
+/// The shared handles a shard caches, forwards and records events through
+pub struct ShardServices<T, C> {
+    pub cache: Arc<C>,
+    pub redis: Arc<Pool>,
+    pub event_forwarder: Arc<T>,
+    pub event_whitelist: Arc<EventWhitelist>,
+    pub session_start_limiter: Arc<SessionStartLimiter>,
+    pub forwarding_gate: Arc<ForwardingGate>,
+    pub recorder: Option<Arc<TrafficRecorder>>,
+}
 
-pub struct Shard<T: EventForwarder> {
+pub struct Shard<T: EventForwarder, M: ShardMode, C: Cache> {
     pub(crate) config: Arc<Config>,
     pub(crate) identify: payloads::Identify,
     large_sharding_buckets: u16,
-    cache: Arc<PostgresCache>,
+    cache: Arc<C>,
     redis: Arc<Pool>,
     pub status_update_tx: mpsc::Sender<StatusUpdate>,
     status_update_rx: Mutex<mpsc::Receiver<StatusUpdate>>,
@@ -61,36 +116,62 @@ pub struct Shard<T: EventForwarder> {
     last_seq_update: Mutex<Instant>,
     session_id: RwLock<Option<String>>,
     writer: RwLock<Option<mpsc::Sender<OutboundMessage>>>,
+    outbound_queue_depth: Arc<AtomicUsize>,
     kill_heartbeat: Mutex<Option<oneshot::Sender<()>>>,
     pub kill_shard_tx: Mutex<Option<oneshot::Sender<()>>>,
     kill_shard_rx: Mutex<oneshot::Receiver<()>>,
     last_ack: RwLock<Instant>,
     last_heartbeat: RwLock<Instant>,
+    // round trip time of the last acknowledged heartbeat
+    latency: RwLock<Option<Duration>>,
     connect_time: RwLock<Instant>,
+    state: RwLock<ConnectionState>,
+    guilds: RwLock<HashSet<Snowflake>>,
+    unavailable_guilds: RwLock<HashMap<Snowflake, Unavailability>>,
     ready_tx: Mutex<Option<oneshot::Sender<()>>>,
     ready_guild_count: AtomicU16,
     received_count: AtomicU16,
     is_ready: AtomicBool,
     pub(crate) event_forwarder: Arc<T>,
-
-    #[cfg(feature = "whitelabel")]
-    pub(crate) database: Arc<Database>,
+    event_whitelist: Arc<EventWhitelist>,
+    forwarding_gate: Arc<ForwardingGate>,
+    // each guild's events are cached & forwarded in order, one at a time
+    event_queue: GuildQueue,
+    member_chunks: MemberChunkCollector,
+    session_start_limiter: Arc<SessionStartLimiter>,
+    // held for reading by each event being cached & forwarded, so that shutdown can wait for them
+    in_flight: Arc<RwLock<()>>,
+    shutting_down: AtomicBool,
+    shutdown_requested: Notify,
+    shutdown_complete: Notify,
+    stopped: AtomicBool,
+    start_requested: Notify,
+    mode: M,
+    recorder: Option<Arc<TrafficRecorder>>,
 }
 
 #[cfg(feature = "compression")]
 const CHUNK_SIZE: usize = 16 * 1024; // 16KiB
 
-impl<T: EventForwarder> Shard<T> {
+impl<T: EventForwarder, M: ShardMode, C: Cache> Shard<T, M, C> {
     pub fn new(
         config: Arc<Config>,
         identify: payloads::Identify,
         large_sharding_buckets: u16,
-        cache: Arc<PostgresCache>,
-        redis: Arc<Pool>,
         user_id: Snowflake,
-        event_forwarder: Arc<T>,
-        #[cfg(feature = "whitelabel")] database: Arc<Database>,
-    ) -> Arc<Shard<T>> {
+        services: ShardServices<T, C>,
+        mode: M,
+    ) -> Arc<Shard<T, M, C>> {
+        let ShardServices {
+            cache,
+            redis,
+            event_forwarder,
+            event_whitelist,
+            session_start_limiter,
+            forwarding_gate,
+            recorder,
+        } = services;
+
         let (kill_shard_tx, kill_shard_rx) = oneshot::channel();
         let (status_update_tx, status_update_rx) = mpsc::channel(1);
 
@@ -108,25 +189,65 @@ impl<T: EventForwarder> Shard<T> {
             last_seq_update: Mutex::new(Instant::now()),
             session_id: RwLock::new(None),
             writer: RwLock::new(None),
+            outbound_queue_depth: Arc::new(AtomicUsize::new(0)),
             kill_heartbeat: Mutex::new(None),
             kill_shard_tx: Mutex::new(Some(kill_shard_tx)),
             kill_shard_rx: Mutex::new(kill_shard_rx),
             last_ack: RwLock::new(Instant::now()),
             last_heartbeat: RwLock::new(Instant::now()),
+            latency: RwLock::new(None),
             connect_time: RwLock::new(Instant::now()), // will be overwritten
+            state: RwLock::new(ConnectionState::Disconnected),
+            guilds: RwLock::new(HashSet::new()),
+            unavailable_guilds: RwLock::new(HashMap::new()),
             ready_tx: Mutex::new(None),
             ready_guild_count: AtomicU16::new(0),
             received_count: AtomicU16::new(0),
             is_ready: AtomicBool::new(false),
             event_forwarder,
-            #[cfg(feature = "whitelabel")]
-            database,
+            event_whitelist,
+            forwarding_gate,
+            event_queue: GuildQueue::new(),
+            member_chunks: MemberChunkCollector::new(),
+            session_start_limiter,
+            in_flight: Arc::new(RwLock::new(())),
+            shutting_down: AtomicBool::new(false),
+            shutdown_requested: Notify::new(),
+            shutdown_complete: Notify::new(),
+            stopped: AtomicBool::new(false),
+            start_requested: Notify::new(),
+            mode,
+            recorder,
         })
     }
 
     pub async fn connect(
         self: Arc<Self>,
         ready_tx: Option<oneshot::Sender<()>>,
+    ) -> Result<(), GatewayError> {
+        let res = if self.is_shutting_down() {
+            Ok(())
+        } else if self.stopped.load(Ordering::Relaxed) {
+            self.wait_until_started().await;
+            Ok(())
+        } else {
+            Arc::clone(&self).connect_and_listen(ready_tx).await
+        };
+
+        *self.state.write().await = ConnectionState::Disconnected;
+
+        // if we weren't connected, or the connection dropped before the shutdown was handled,
+        // there's nothing left to do
+        if self.is_shutting_down() {
+            self.shutdown_complete.notify_one();
+        }
+
+        res
+    }
+
+    async fn connect_and_listen(
+        self: Arc<Self>,
+        ready_tx: Option<oneshot::Sender<()>>,
     ) -> Result<(), GatewayError> {
         //rst
         *self.ready_tx.lock().await = ready_tx;
@@ -140,14 +261,15 @@ impl<T: EventForwarder> Shard<T> {
         self.received_count.store(0, Ordering::Relaxed);
         self.is_ready.store(false, Ordering::Relaxed);
 
+        // chunks for requests made on the previous connection will never arrive
+        self.member_chunks.clear().await;
+
         *self.last_heartbeat.write().await = Instant::now();
         *self.last_ack.write().await = Instant::now();
+        *self.latency.write().await = None;
         // rst
 
-        let mut uri = format!(
-            "wss://gateway.discord.gg/?v={}&encoding=json",
-            GATEWAY_VERSION
-        );
+        let mut uri = self.config.get_gateway_uri(GATEWAY_VERSION);
         if cfg!(feature = "compression") {
             uri.push_str("&compress=zlib-stream");
         }
@@ -157,12 +279,17 @@ impl<T: EventForwarder> Shard<T> {
         let (wss, _) = connect_async(uri).await?;
         let (ws_tx, ws_rx) = wss.split();
         *self.connect_time.write().await = Instant::now();
+        *self.state.write().await = ConnectionState::Connecting;
 
         // start writer
         let (recv_broker_tx, recv_broker_rx) = futures::channel::mpsc::unbounded();
         let (send_broker_tx, send_broker_rx) = futures::channel::mpsc::unbounded();
         let (internal_tx, internal_rx) = mpsc::channel(1);
-        tokio::spawn(handle_writes(send_broker_tx, internal_rx));
+        tokio::spawn(handle_writes(
+            send_broker_tx,
+            internal_rx,
+            Arc::clone(&self.outbound_queue_depth),
+        ));
 
         let forward_outbound = send_broker_rx.map(Ok).forward(ws_tx);
         let forward_inbound = ws_rx.map(Ok).forward(recv_broker_tx);
@@ -185,15 +312,187 @@ impl<T: EventForwarder> Shard<T> {
     async fn write<U: Serialize>(
         &self,
         msg: U,
+        kind: MessageKind,
         tx: oneshot::Sender<Result<(), futures::channel::mpsc::SendError>>,
     ) -> Result<(), GatewayError> {
-        OutboundMessage::new(msg, tx)?
+        OutboundMessage::new(msg, kind, tx)?
             .send(self.writer.read().await.clone().unwrap())
             .await?;
 
         Ok(())
     }
 
+    /// Sends a Request Guild Members payload, and waits for all of the resulting chunks. The
+    /// members are also written to the cache as the chunks are received.
+    pub async fn request_guild_members(
+        &self,
+        guild_id: Snowflake,
+        filter: GuildMembersFilter,
+        presences: bool,
+    ) -> Result<GuildMembers, GatewayError> {
+        if self.writer.read().await.is_none() {
+            return GatewayError::NotConnectedError.into();
+        }
+
+        let (nonce, members_rx) = self.member_chunks.register(guild_id).await;
+        let payload = RequestGuildMembers::new(guild_id, filter, presences, nonce.clone());
+
+        if let Err(e) = self.do_request_guild_members(payload).await {
+            self.member_chunks.cancel(&nonce).await;
+            return Err(e);
+        }
+
+        match tokio::time::timeout(MEMBER_CHUNK_TIMEOUT, members_rx).await {
+            Ok(members) => Ok(members?),
+            Err(_) => {
+                self.member_chunks.cancel(&nonce).await;
+                GatewayError::MemberChunkTimeoutError(guild_id).into()
+            }
+        }
+    }
+
+    /// Stops reading events, waits for those already read to be cached and forwarded for up to
+    /// half of the shutdown timeout, saves the session and closes the connection such that the
+    /// session can be resumed by the next process. The shard will not reconnect afterwards.
+    pub async fn shutdown(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+        self.shutdown_requested.notify_one();
+        self.shutdown_complete.notified().await;
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages waiting for capacity under the gateway send limit
+    pub fn outbound_queue_depth(&self) -> usize {
+        self.outbound_queue_depth.load(Ordering::Relaxed)
+    }
+
+    /// Drops the connection, after which the shard reconnects and resumes the session if it can.
+    /// A stopped shard is started again instead.
+    pub fn reconnect(self: Arc<Self>) {
+        if self.stopped.swap(false, Ordering::Relaxed) {
+            self.log("Starting");
+            self.start_requested.notify_one();
+        } else {
+            self.log("Reconnecting");
+            self.record_reconnect("admin");
+            self.kill();
+        }
+    }
+
+    /// Drops the connection, and doesn't reconnect until `reconnect` is called
+    pub fn stop(self: Arc<Self>) {
+        self.log("Stopping");
+        self.stopped.store(true, Ordering::Relaxed);
+        self.record_reconnect("admin");
+        self.kill();
+    }
+
+    /// Discards the session and drops the connection, so that the shard identifies rather than
+    /// resuming when it reconnects
+    pub async fn reidentify(self: Arc<Self>) {
+        self.log("Discarding session to re-identify");
+        self.invalidate_session().await;
+        self.record_reconnect("admin");
+        self.kill();
+    }
+
+    pub async fn status(&self) -> ShardStatus {
+        let state = *self.state.read().await;
+
+        let connected_for = match state {
+            ConnectionState::Connecting | ConnectionState::Connected => {
+                Some(self.connect_time.read().await.elapsed().as_millis() as u64)
+            }
+            ConnectionState::Disconnected | ConnectionState::Stopped => None,
+        };
+
+        ShardStatus {
+            bot_id: self.user_id,
+            shard_id: self.get_shard_id(),
+            shard_total: self.get_shard_total(),
+            state,
+            has_session: self.session_id.read().await.is_some(),
+            seq: *self.seq.read().await,
+            heartbeat_latency: self
+                .latency()
+                .await
+                .map(|latency| latency.as_millis() as u64),
+            guild_count: self.guild_count().await,
+            pending_guild_count: self.count_unavailable(Unavailability::Pending).await,
+            unavailable_guild_count: self.count_unavailable(Unavailability::Outage).await,
+            connected_for,
+            outbound_queue_depth: self.outbound_queue_depth(),
+        }
+    }
+
+    async fn count_unavailable(&self, reason: Unavailability) -> usize {
+        self.unavailable_guilds
+            .read()
+            .await
+            .values()
+            .filter(|r| **r == reason)
+            .count()
+    }
+
+    /// Guilds the shard is in, including those that are unavailable
+    pub async fn guild_count(&self) -> usize {
+        self.guilds.read().await.len()
+    }
+
+    /// The round trip time of the most recently acknowledged heartbeat, None until the first ACK
+    /// is received
+    pub async fn latency(&self) -> Option<Duration> {
+        *self.latency.read().await
+    }
+
+    async fn wait_until_started(&self) {
+        self.log("Stopped, waiting to be started");
+        *self.state.write().await = ConnectionState::Stopped;
+
+        tokio::select! {
+            _ = self.start_requested.notified() => {}
+            _ = self.shutdown_requested.notified() => {}
+        }
+    }
+
+    async fn close_resumable(&self) {
+        if let Some(kill_heartbeat_tx) = self.kill_heartbeat.lock().await.take() {
+            let _ = kill_heartbeat_tx.send(());
+        }
+
+        // wait for in-flight events to finish, for up to half of the shutdown timeout, leaving the
+        // rest for saving the session, closing and dead-lettering events still being retried
+        let limit = Duration::from_millis(self.config.shutdown_timeout / 2);
+        if timeout(limit, self.wait_for_in_flight()).await.is_err() {
+            self.log(format!(
+                "Events still in flight after {:?}, closing anyway",
+                limit
+            ));
+        }
+
+        if let Err(e) = self.save_seq().await {
+            self.log_err("Error saving sequence number", &e);
+        }
+
+        if let Err(e) = self.save_session_id().await {
+            self.log_err("Error saving session ID to Redis", &e);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let msg = OutboundMessage::close(RESUMABLE_CLOSE_CODE, "shutting down", tx);
+
+        if let Some(writer) = self.writer.read().await.clone() {
+            if let Err(e) = msg.send(writer).await {
+                self.log_err("Error sending close to writer", &GatewayError::from(e));
+            } else if let Ok(Err(e)) = rx.await {
+                self.log_err("Error writing close", &GatewayError::WebsocketSendError(e));
+            }
+        }
+    }
+
     // helper function
     pub fn kill(self: Arc<Self>) {
         // BIG problem
@@ -238,11 +537,20 @@ impl<T: EventForwarder> Shard<T> {
         #[cfg(feature = "compression")]
         let mut decoder = Decompress::new(true);
 
+        let mut refresh_guild_bots = interval_at(
+            tokio::time::Instant::now() + GUILD_BOTS_REFRESH_INTERVAL,
+            GUILD_BOTS_REFRESH_INTERVAL,
+        );
+
         loop {
             let shard = Arc::clone(&self);
             let kill_rx = &mut *shard.kill_shard_rx.lock().await;
             let status_update_rx = &mut shard.status_update_rx.lock().await;
 
+            // Discord closes the connection if a presence update is sent before the identify or
+            // resume has completed
+            let connected = *shard.state.read().await == ConnectionState::Connected;
+
             tokio::select! {
                 // handle kill
                 _ = kill_rx => {
@@ -250,29 +558,48 @@ impl<T: EventForwarder> Shard<T> {
                     break;
                 }
 
+                // handle graceful shutdown
+                _ = shard.shutdown_requested.notified() => {
+                    self.log("Shutting down");
+                    self.close_resumable().await;
+                    break;
+                }
+
                 // handle incoming payload
                 payload = rx.next() => {
                     match payload {
                         None => {
                             self.log("Payload was None, killing");
+                            self.record_reconnect("connection_closed");
                             self.kill();
                             break;
                         }
 
                         Some(Err(e)) => {
                             self.log_err("Error reading data from websocket, killing", &GatewayError::WebsocketError(e));
+                            self.record_reconnect("websocket_error");
                             self.kill();
                             break;
                         }
 
                         Some(Ok(Message::Close(frame))) => {
                             self.log(format!("Got close from gateway: {:?}", frame));
+                            match &frame {
+                                Some(frame) => self.record_reconnect(format!("close_{}", u16::from(frame.code))),
+                                None => self.record_reconnect("close"),
+                            }
                             Arc::clone(&self).kill();
 
                             if let Some(frame) = frame {
                                 if let CloseCode::Library(code) = frame.code {
                                     let fatal_codes: [u16; 2] = [4004, 4014];
                                     if fatal_codes.contains(&code) {
+                                        // we won't receive a GUILD_DELETE for any of our guilds
+                                        let guilds: Vec<Snowflake> = self.guilds.read().await.iter().copied().collect();
+                                        if let Err(e) = self.forget_guild_bots(&guilds).await {
+                                            self.log_err("Error removing guilds from Redis", &e);
+                                        }
+
                                         return GatewayError::AuthenticationError {
                                             bot_token: self.identify.data.token.clone(),
                                             error_code: frame.code,
@@ -286,17 +613,9 @@ impl<T: EventForwarder> Shard<T> {
                         }
 
                         Some(Ok(Message::Text(data))) => {
-                            let value: Value = serde_json::from_slice(data.as_bytes())?;
-
-                            let payload = match Arc::clone(&self).read_payload(&value).await {
-                                Ok(payload) => payload,
-                                Err(e) => {
-                                    self.log_err("Error while deserializing payload", &e);
-                                    continue;
-                                }
-                            };
+                            self.record(data.as_bytes());
 
-                            if let Err(e) = Arc::clone(&self).process_payload(payload, value).await {
+                            if let Err(e) = Arc::clone(&self).process_frame(&data).await {
                                 self.log_err("An error occurred while processing a payload", &e);
                             }
                         }
@@ -307,21 +626,19 @@ impl<T: EventForwarder> Shard<T> {
                                 Ok(data) => data,
                                 Err(e) => {
                                     self.log_err("Error while decompressing payload", &e);
+                                    METRICS.decompression_errors.inc(self.metric_labels());
                                     continue;
                                 }
                             };
 
-                            let value: Value = serde_json::from_slice(data.as_bytes())?;
+                            self.record(&data);
 
-                            let payload = match Arc::clone(&self).read_payload(&value).await {
-                                Ok(payload) => payload,
-                                Err(e) => {
-                                    self.log_err("Error while deserializing payload", &e);
-                                    continue;
-                                }
+                            let res = match str::from_utf8(&data) {
+                                Ok(raw) => Arc::clone(&self).process_frame(raw).await,
+                                Err(e) => Err(e.into()),
                             };
 
-                            if let Err(e) = Arc::clone(&self).process_payload(payload, value).await {
+                            if let Err(e) = res {
                                 self.log_err("An error occurred while processing a payload", &e);
                             }
                         }
@@ -330,15 +647,25 @@ impl<T: EventForwarder> Shard<T> {
                     }
                 }
 
+                _ = refresh_guild_bots.tick() => {
+                    let shard = Arc::clone(&self);
+                    tokio::spawn(async move {
+                        let guilds: Vec<Snowflake> = shard.guilds.read().await.iter().copied().collect();
+                        if let Err(e) = shard.add_guild_bots(&guilds).await {
+                            shard.log_err("Error refreshing guilds in Redis", &e);
+                        }
+                    });
+                }
+
                 // handle status update
-                presence = status_update_rx.recv() => {
+                presence = status_update_rx.recv(), if connected => {
                     if let Some(presence) = presence {
                         let (tx, rx) = oneshot::channel();
 
                         let shard = Arc::clone(&self);
                         tokio::spawn(async move {
                             let payload = PresenceUpdate::new(presence);
-                            if let Err(e) = shard.write(payload, tx).await {
+                            if let Err(e) = shard.write(payload, MessageKind::PresenceUpdate, tx).await {
                                 shard.log_err("Error sending presence update payload to writer", &e);
                             }
 
@@ -396,26 +723,49 @@ impl<T: EventForwarder> Shard<T> {
         return Ok(output);
     }
 
-    // Manually deserialize since we only need 2 values
-    async fn read_payload(self: Arc<Self>, data: &Value) -> Result<Payload, GatewayError> {
-        let opcode = serde_json::from_value(
-            data.get("op")
-                .ok_or_else(|| GatewayError::MissingFieldError("op".to_owned()))?
-                .clone(),
-        )?;
-
-        let seq = match data.get("s") {
-            None => None,
-            Some(s) => serde_json::from_value(s.clone())?,
-        };
+    fn record(&self, data: &[u8]) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(
+                self.user_id,
+                self.get_shard_id(),
+                self.get_shard_total(),
+                data,
+            );
+        }
+    }
+
+    /// Processes a payload captured by a [`TrafficRecorder`] as though it had just been received,
+    /// caching and forwarding it. Only dispatches are replayed, as the other opcodes drive a
+    /// connection that doesn't exist.
+    pub async fn replay(self: Arc<Self>, data: &[u8]) -> Result<(), GatewayError> {
+        let raw: &RawValue = serde_json::from_str(str::from_utf8(data)?)?;
+        let payload: Payload = serde_json::from_str(raw.get())?;
+
+        if payload.opcode == Opcode::Dispatch {
+            self.process_payload(payload, raw).await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Waits for every event read so far to be cached and forwarded
+    pub async fn wait_for_in_flight(&self) {
+        drop(self.in_flight.write().await);
+    }
 
-        Ok(Payload { opcode, seq })
+    // Only the envelope is parsed here, the data is deserialized once we know what it is. The
+    // frame is borrowed as a RawValue, so that it can be forwarded as received without parsing it
+    // again.
+    async fn process_frame(self: Arc<Self>, raw: &str) -> Result<(), GatewayError> {
+        let raw: &RawValue = serde_json::from_str(raw)?;
+        let payload: Payload = serde_json::from_str(raw.get())?;
+        self.process_payload(payload, raw).await
     }
 
     async fn process_payload(
         self: Arc<Self>,
-        payload: Payload,
-        raw: Value,
+        payload: Payload<'_>,
+        raw: &RawValue,
     ) -> Result<(), GatewayError> {
         if let Some(seq) = payload.seq {
             *self.seq.write().await = Some(seq);
@@ -433,46 +783,26 @@ impl<T: EventForwarder> Shard<T> {
 
         match payload.opcode {
             Opcode::Dispatch => {
-                let payload = serde_json::from_value(raw)?;
-
-                if let Err(e) = Arc::clone(&self).handle_event(payload).await {
-                    if let GatewayError::JsonError(ref err) = e {
-                        // Ignore unknown payloads
-                        if err.classify() != Category::Data {
-                            self.log_err("Error processing dispatch", &e);
-                        }
-                    } else {
-                        self.log_err("Error processing dispatch", &e);
-                    }
+                if let Err(e) = Arc::clone(&self).handle_event(payload, raw).await {
+                    self.log_err("Error processing dispatch", &e);
                 }
             }
 
             Opcode::Reconnect => {
                 self.log("Received reconnect payload from Discord");
+                self.record_reconnect("reconnect_requested");
                 self.kill();
             }
 
             Opcode::InvalidSession => {
                 self.log("Received invalid session payload from Discord");
-
-                *self.session_id.write().await = None;
-                *self.seq.write().await = None;
-
-                // delete session ID from Redis
-                if let Err(e) = self.delete_session_id().await {
-                    self.log_err("Error deleting session_id from Redis", &e);
-                }
-
-                // delete seq from Redis
-                if let Err(e) = self.delete_seq().await {
-                    self.log_err("Error deleting seq from Redis", &e);
-                }
-
+                self.invalidate_session().await;
+                self.record_reconnect("invalid_session");
                 self.kill();
             }
 
             Opcode::Hello => {
-                let hello: payloads::Hello = serde_json::from_value(raw)?;
+                let hello: payloads::Hello = serde_json::from_str(raw.get())?;
                 let interval = Duration::from_millis(hello.data.heartbeat_interval as u64);
 
                 let mut should_identify = true;
@@ -503,6 +833,7 @@ impl<T: EventForwarder> Shard<T> {
                             self.log(
                                 "Connected over 45s ago, Discord will kick us off. Reconnecting.",
                             );
+                            self.record_reconnect("identify_timeout");
                             Arc::clone(&self).kill();
                             return Ok(());
                         } else {
@@ -519,12 +850,14 @@ impl<T: EventForwarder> Shard<T> {
 
                     if self.connect_time.read().await.elapsed() > interval {
                         self.log("Connected over 45s ago, Discord will kick us off. Reconnecting.");
+                        self.record_reconnect("identify_timeout");
                         self.kill();
                         return Ok(());
                     }
 
                     if let Err(e) = Arc::clone(&self).do_identify().await {
                         self.log_err("Error identifying, killing", &e);
+                        self.record_reconnect("identify_error");
                         self.kill();
                         return e.into();
                     }
@@ -536,8 +869,21 @@ impl<T: EventForwarder> Shard<T> {
                 *self.kill_heartbeat.lock().await = Some(kill_tx)
             }
 
+            // the gateway wants a heartbeat now, rather than at the next interval
+            Opcode::Heartbeat => {
+                if let Err(e) = Arc::clone(&self).do_heartbeat().await {
+                    self.log_err("Error sending requested heartbeat", &e);
+                }
+            }
+
             Opcode::HeartbeatAck => {
-                *self.last_ack.write().await = Instant::now();
+                let now = Instant::now();
+                *self.last_ack.write().await = now;
+
+                if let Some(rtt) = now.checked_duration_since(*self.last_heartbeat.read().await) {
+                    *self.latency.write().await = Some(rtt);
+                    METRICS.heartbeat_rtt.observe(self.metric_labels(), rtt);
+                }
 
                 // save session ID
                 if let Err(e) = self.save_session_id().await {
@@ -551,11 +897,43 @@ impl<T: EventForwarder> Shard<T> {
         Ok(())
     }
 
-    async fn handle_event(self: Arc<Self>, data: Box<RawValue>) -> Result<(), GatewayError> {
-        let payload: Dispatch = serde_json::from_str(data.get())?;
+    async fn handle_event(
+        self: Arc<Self>,
+        payload: Payload<'_>,
+        raw: &RawValue,
+    ) -> Result<(), GatewayError> {
+        let name = payload.event_name.ok_or(GatewayError::MissingEventType)?;
+
+        let label = match Event::NAMES.iter().find(|known| **known == name) {
+            Some(known) => Cow::Borrowed(*known),
+            None => Cow::Owned(name.to_owned()),
+        };
+        METRICS
+            .events_received
+            .inc_with(self.metric_labels(), label);
+
+        // most dispatches (presences, typing, reactions...) are neither cached nor forwarded, so
+        // skip them before paying for deserialization
+        let forward = self.is_forwarded(name);
+        if !forward && !is_cached(name) && !SHARD_EVENTS.contains(&name) {
+            return Ok(());
+        }
+
+        let event = match Event::parse(name, payload.data) {
+            Ok(event) => event,
+            Err(e) => {
+                // a known event that doesn't match our model, most likely a field that Discord
+                // has changed
+                self.log_debug("Error parsing event", raw.get(), e);
+                return Ok(());
+            }
+        };
+
+        // the synthetic event to forward, if the guild became unavailable or available
+        let mut availability = None;
 
         // Gateway events
-        match &payload.data {
+        match &event {
             Event::Ready(ready) => {
                 *self.session_id.write().await = Some(ready.session_id.clone());
                 if let Err(e) = self.save_session_id().await {
@@ -564,6 +942,27 @@ impl<T: EventForwarder> Shard<T> {
 
                 self.ready_guild_count
                     .store(ready.guilds.len() as u16, Ordering::Relaxed);
+                *self.guilds.write().await = ready.guilds.iter().map(|g| g.id).collect();
+
+                let guilds: Vec<Snowflake> = ready.guilds.iter().map(|g| g.id).collect();
+                if let Err(e) = self.add_guild_bots(&guilds).await {
+                    self.log_err("Error recording guilds in Redis", &e);
+                }
+
+                // every guild is unavailable until its GUILD_CREATE is received, while outages
+                // carry over from the previous session
+                let mut unavailable = self.unavailable_guilds.write().await;
+                *unavailable = ready
+                    .guilds
+                    .iter()
+                    .map(|g| match unavailable.get(&g.id) {
+                        Some(Unavailability::Outage) => (g.id, Unavailability::Outage),
+                        _ => (g.id, Unavailability::Pending),
+                    })
+                    .collect();
+                drop(unavailable);
+
+                *self.state.write().await = ConnectionState::Connected;
 
                 self.log(format!(
                     "Ready on {}#{} ({})",
@@ -574,6 +973,13 @@ impl<T: EventForwarder> Shard<T> {
 
             Event::Resumed(_) => {
                 self.log("Received resumed acknowledgement");
+                *self.state.write().await = ConnectionState::Connected;
+
+                // no GUILD_CREATEs follow a resume
+                let guilds: Vec<Snowflake> = self.guilds.read().await.iter().copied().collect();
+                if let Err(e) = self.add_guild_bots(&guilds).await {
+                    self.log_err("Error recording guilds in Redis", &e);
+                }
 
                 if !self
                     .is_ready
@@ -593,117 +999,223 @@ impl<T: EventForwarder> Shard<T> {
                 return Ok(());
             }
 
-            #[cfg(not(feature = "whitelabel"))]
-            Event::GuildCreate(_) => {
+            Event::GuildCreate(g) => {
+                self.guilds.write().await.insert(g.id);
                 self.update_count().await;
+
+                if let Err(e) = self.mode.on_guild_create(self.user_id, g.id).await {
+                    self.log_err("Error while storing guild data", &e);
+                }
+
+                if let Err(e) = self.add_guild_bots(&[g.id]).await {
+                    self.log_err("Error recording guild in Redis", &e);
+                }
+
+                let previous = self.unavailable_guilds.write().await.remove(&g.id);
+                if previous == Some(Unavailability::Outage) {
+                    availability = Some((GUILD_AVAILABLE, g.id));
+                }
             }
 
-            #[cfg(feature = "whitelabel")]
-            Event::GuildCreate(g) => {
-                self.update_count().await;
+            // we were kicked, rather than the guild becoming unavailable
+            Event::GuildDelete(g) if g.unavailable.is_none() => {
+                self.guilds.write().await.remove(&g.id);
+                self.unavailable_guilds.write().await.remove(&g.id);
+
+                if let Err(e) = self.mode.on_guild_delete(self.user_id, g.id).await {
+                    self.log_err("Error while removing guild data", &e);
+                }
 
-                #[cfg(feature = "whitelabel")]
-                if let Err(e) = self.store_whitelabel_guild(g.id).await {
-                    self.log_err("Error while storing whitelabel guild data", &e);
+                match self.remove_guild_bot(g.id).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        if let Err(e) = self.cache.delete_guild(g.id).await {
+                            self.log_err("Error updating cache", &GatewayError::CacheError(e));
+                            METRICS.cache_errors.inc(self.metric_labels());
+                        }
+                    }
+                    // keep the cached guild rather than evicting it from under another bot
+                    Err(e) => self.log_err("Error removing guild from Redis", &e),
+                }
+            }
+
+            // Discord is having an outage in the guild
+            Event::GuildDelete(g) => {
+                let previous = self
+                    .unavailable_guilds
+                    .write()
+                    .await
+                    .insert(g.id, Unavailability::Outage);
+
+                if previous != Some(Unavailability::Outage) {
+                    availability = Some((GUILD_UNAVAILABLE, g.id));
                 }
             }
 
             _ => {}
         }
 
-        // cache + push to redis
-        tokio::spawn(async move {
-            let guild_id = super::event_forwarding::get_guild_id(&payload.data);
-            let should_forward =
-                is_whitelisted(&payload.data) && self.meets_forward_threshold(&payload.data).await;
+        // the gate is checked as the event is read, rather than once it has been cached, so that
+        // an event read before a reshard's switch is still forwarded after it
+        let shard_total = self.get_shard_total();
+        let forward = forward
+            && self
+                .forwarding_gate
+                .admit(shard_total, name, payload.data.get())
+            && self.meets_forward_threshold(&event).await;
+        let forward_availability = availability.is_some()
+            && self.is_forwarded("GUILD_DELETE")
+            && self.forwarding_gate.is_open(shard_total);
+
+        // the whole payload is forwarded, as received
+        let frame = if forward { Some(raw.to_owned()) } else { None };
+        let guild_id = super::event_forwarding::get_guild_id(&event);
+
+        // cache + push to redis, after the guild's earlier events
+        let in_flight = Arc::clone(&self.in_flight).read_owned().await;
+        let shard = Arc::clone(&self);
+        shard.event_queue.push(guild_id, async move {
+            let _in_flight = in_flight;
 
             // cache
-            let res = match payload.data {
-                Event::ChannelCreate(channel) => self.cache.store_channel(channel).await,
-                Event::ChannelUpdate(channel) => self.cache.store_channel(channel).await,
-                Event::ChannelDelete(channel) => self.cache.delete_channel(channel.id).await,
-                Event::ThreadCreate(thread) => self.cache.store_channel(thread).await,
-                Event::ThreadUpdate(thread) => self.cache.store_channel(thread).await,
-                Event::ThreadDelete(thread) => self.cache.delete_channel(thread.id).await,
-                Event::GuildCreate(mut guild) => {
-                    apply_guild_id_to_channels(&mut guild);
-                    self.cache.store_guild(guild).await
-                }
-                Event::GuildUpdate(mut guild) => {
-                    apply_guild_id_to_channels(&mut guild);
-                    self.cache.store_guild(guild).await
-                }
-                Event::GuildDelete(guild) => {
-                    if guild.unavailable.is_none() {
-                        // we were kicked
-                        // TODO: don't delete if this is main bot & whitelabel bot is in guild
-                        self.cache.delete_guild(guild.id).await
-                    } else {
-                        Ok(())
-                    }
-                }
-                Event::GuildBanAdd(ev) => self.cache.delete_member(ev.user.id, ev.guild_id).await,
-                Event::GuildEmojisUpdate(ev) => {
-                    self.cache.store_emojis(ev.emojis, ev.guild_id).await
-                }
-                Event::GuildMemberAdd(ev) => self.cache.store_member(ev.member, ev.guild_id).await,
-                Event::GuildMemberRemove(ev) => {
-                    self.cache.delete_member(ev.user.id, ev.guild_id).await
-                }
-                Event::GuildMemberUpdate(ev) => {
-                    self.cache
-                        .store_member(
-                            Member {
-                                user: Some(ev.user),
-                                nick: ev.nick,
-                                roles: ev.roles,
-                                joined_at: ev.joined_at,
-                                premium_since: ev.premium_since,
-                                deaf: false, // TODO: Don't update these fields somehow?
-                                mute: false, // TODO: Don't update these fields somehow?
-                            },
-                            ev.guild_id,
-                        )
-                        .await
-                }
-                Event::GuildMembersChunk(ev) => {
-                    self.cache.store_members(ev.members, ev.guild_id).await
-                }
-                Event::GuildRoleCreate(ev) => self.cache.store_role(ev.role, ev.guild_id).await,
-                Event::GuildRoleUpdate(ev) => self.cache.store_role(ev.role, ev.guild_id).await,
-                Event::GuildRoleDelete(ev) => self.cache.delete_role(ev.role_id).await,
-                Event::UserUpdate(user) => self.cache.store_user(user).await,
-                _ => Ok(()),
-            };
+            if let Event::GuildMembersChunk(ev) = &event {
+                self.member_chunks.handle_chunk(ev).await;
+            }
+
+            let res = update_cache(&*self.cache, event).await;
 
             if let Err(e) = res {
                 self.log_err("Error updating cache", &GatewayError::CacheError(e));
+                METRICS.cache_errors.inc(self.metric_labels());
+            }
+
+            if let Some((GUILD_UNAVAILABLE, guild_id)) = availability {
+                if let Err(e) = self.cache.mark_guild_unavailable(guild_id).await {
+                    self.log_err("Error updating cache", &GatewayError::CacheError(e));
+                    METRICS.cache_errors.inc(self.metric_labels());
+                }
             }
 
             // push to workers, even if error occurred
-            if should_forward {
-                // prepare payload
-                let wrapped = event_forwarding::Event {
-                    bot_token: &self.identify.data.token[..],
-                    bot_id: self.user_id.0,
-                    is_whitelabel: is_whitelabel(),
-                    shard_id: self.get_shard_id(),
-                    event: &data,
-                };
+            if let Some(frame) = frame {
+                self.forward(&frame, guild_id).await;
+            }
 
-                if let Err(e) = self
-                    .event_forwarder
-                    .forward_event(&*self.config, wrapped, guild_id)
-                    .await
-                {
-                    self.log_err("Error while executing worker HTTP request", &e);
-                }
+            // forwarded along with GUILD_DELETE, which is how Discord reports outages
+            if let Some((name, guild_id)) = availability.filter(|_| forward_availability) {
+                self.forward_availability(name, guild_id).await;
             }
         });
 
         Ok(())
     }
 
+    async fn forward(&self, frame: &RawValue, guild_id: Option<Snowflake>) {
+        let wrapped = event_forwarding::Event {
+            bot_token: &self.identify.data.token[..],
+            bot_id: self.user_id.0,
+            is_whitelabel: self.mode.is_whitelabel(),
+            shard_id: self.get_shard_id(),
+            event: frame,
+        };
+
+        match self
+            .event_forwarder
+            .forward_event(&*self.config, wrapped, guild_id)
+            .await
+        {
+            Ok(()) => METRICS.events_forwarded.inc(self.metric_labels()),
+            Err(e) => {
+                self.log_err("Error while forwarding event to worker", &e);
+                METRICS.event_forward_failures.inc(self.metric_labels());
+            }
+        }
+    }
+
+    async fn forward_availability(&self, name: &'static str, guild_id: Snowflake) {
+        let frame = SyntheticDispatch {
+            opcode: Opcode::Dispatch,
+            event_name: name,
+            data: GuildAvailability { guild_id },
+        };
+
+        match serde_json::to_string(&frame).and_then(RawValue::from_string) {
+            Ok(frame) => self.forward(&frame, Some(guild_id)).await,
+            Err(e) => self.log_err(
+                "Error serializing guild availability event",
+                &GatewayError::JsonError(e),
+            ),
+        }
+    }
+
+    /// Records that the bot is in each of the guilds, refreshing the expiry of their sets
+    async fn add_guild_bots(&self, guild_ids: &[Snowflake]) -> Result<(), GatewayError> {
+        if guild_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.redis.get().await?;
+
+        let mut pipe = pipe();
+        for guild_id in guild_ids {
+            let key = self.mode.guild_bots_key(*guild_id);
+
+            pipe.cmd("SADD")
+                .arg(&key)
+                .arg(self.user_id.0)
+                .ignore()
+                .cmd("EXPIRE")
+                .arg(&key)
+                .arg(GUILD_BOTS_TTL.as_secs())
+                .ignore();
+        }
+
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Removes the bot from the guilds without evicting them, for when it will never receive a
+    /// GUILD_DELETE for them, e.g. as its token has been revoked
+    async fn forget_guild_bots(&self, guild_ids: &[Snowflake]) -> Result<(), GatewayError> {
+        if guild_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.redis.get().await?;
+
+        let mut pipe = pipe();
+        for guild_id in guild_ids {
+            pipe.cmd("SREM")
+                .arg(self.mode.guild_bots_key(*guild_id))
+                .arg(self.user_id.0)
+                .ignore();
+        }
+
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Returns whether any bots remain in the guild. If the set doesn't exist, e.g. as Redis has
+    /// been flushed, none are assumed to remain: the guild is evicted, and cached again by the
+    /// next GUILD_CREATE from any bot that is still in it, rather than kept forever.
+    async fn remove_guild_bot(&self, guild_id: Snowflake) -> Result<bool, GatewayError> {
+        let mut conn = self.redis.get().await?;
+        let key = self.mode.guild_bots_key(guild_id);
+
+        let (remaining,): (usize,) = pipe()
+            .atomic()
+            .cmd("SREM")
+            .arg(&key)
+            .arg(self.user_id.0)
+            .ignore()
+            .cmd("SCARD")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(remaining > 0)
+    }
+
     async fn update_count(&self) {
         if !self.is_ready.load(Ordering::Relaxed) {
             let received = self.received_count.fetch_add(1, Ordering::Relaxed);
@@ -731,6 +1243,14 @@ impl<T: EventForwarder> Shard<T> {
         }
     }
 
+    fn is_forwarded(&self, name: &str) -> bool {
+        if Event::NAMES.contains(&name) {
+            self.event_whitelist.is_whitelisted(name)
+        } else {
+            self.config.forward_unknown_events
+        }
+    }
+
     async fn meets_forward_threshold(&self, event: &Event) -> bool {
         if cfg!(feature = "skip-initial-guild-creates") {
             if let Event::GuildCreate(_) = event {
@@ -747,7 +1267,9 @@ impl<T: EventForwarder> Shard<T> {
         let (cancel_tx, mut cancel_rx) = oneshot::channel();
 
         tokio::spawn(async move {
-            sleep(interval).await;
+            // Discord asks for the first heartbeat to be jittered, so that shards reconnecting at
+            // once don't all heartbeat together
+            sleep(interval.mul_f64(rand::random::<f64>())).await;
 
             let mut has_done_heartbeat = false;
             while let Err(oneshot::error::TryRecvError::Empty) = cancel_rx.try_recv() {
@@ -762,12 +1284,14 @@ impl<T: EventForwarder> Shard<T> {
 
                 if has_done_heartbeat && (elapsed.is_none() || elapsed.unwrap() > interval) {
                     shard.log("Hasn't received heartbeat ack, killing");
+                    shard.record_reconnect("missed_ack");
                     shard.kill();
                     break;
                 }
 
                 if let Err(e) = Arc::clone(&shard).do_heartbeat().await {
                     shard.log_err("Error sending heartbeat, killing", &e);
+                    shard.record_reconnect("heartbeat_error");
                     shard.kill();
                     break;
                 }
@@ -784,31 +1308,52 @@ impl<T: EventForwarder> Shard<T> {
     async fn do_heartbeat(self: Arc<Self>) -> Result<(), GatewayError> {
         let payload = payloads::Heartbeat::new(*self.seq.read().await);
 
+        // set before writing, so that the ACK can't arrive first
+        *self.last_heartbeat.write().await = Instant::now();
+
         let (tx, rx) = oneshot::channel();
-        self.write(payload, tx).await?;
+        self.write(payload, MessageKind::Heartbeat, tx).await?;
 
+        Ok(rx.await??)
+    }
+
+    async fn do_identify(self: Arc<Self>) -> Result<(), GatewayError> {
+        let (tx, rx) = oneshot::channel();
+        self.write(&self.identify, MessageKind::Command, tx).await?;
         rx.await??;
 
-        *self.last_heartbeat.write().await = Instant::now();
+        METRICS.identifies.inc(self.metric_labels());
         Ok(())
     }
 
-    async fn do_identify(self: Arc<Self>) -> Result<(), GatewayError> {
+    async fn do_request_guild_members(
+        &self,
+        payload: RequestGuildMembers,
+    ) -> Result<(), GatewayError> {
         let (tx, rx) = oneshot::channel();
-        self.write(&self.identify, tx).await?;
+        self.write(payload, MessageKind::Command, tx).await?;
 
         Ok(rx.await??)
     }
 
     async fn wait_for_ratelimit(&self) -> Result<(), GatewayError> {
-        let key = if is_whitelabel() {
-            format!("ratelimiter:whitelabel:identify:{}", self.user_id)
-        } else {
-            format!(
-                "ratelimiter:public:identify:{}",
-                self.get_shard_id() % self.large_sharding_buckets
-            )
-        };
+        let started = Instant::now();
+        let res = self.do_wait_for_ratelimit().await;
+
+        METRICS
+            .identify_ratelimit_wait
+            .observe(self.metric_labels(), started.elapsed());
+        res
+    }
+
+    async fn do_wait_for_ratelimit(&self) -> Result<(), GatewayError> {
+        self.session_start_limiter.acquire().await;
+
+        let key = self.mode.identify_ratelimit_key(
+            self.user_id,
+            &self.identify.data.shard_info,
+            self.large_sharding_buckets,
+        );
 
         let mut res = redis::Value::Nil;
         while res == redis::Value::Nil {
@@ -847,9 +1392,26 @@ impl<T: EventForwarder> Shard<T> {
         let payload = payloads::Resume::new(self.identify.data.token.clone(), session_id, seq);
 
         let (tx, rx) = oneshot::channel();
-        self.write(payload, tx).await?;
+        self.write(payload, MessageKind::Command, tx).await?;
+        rx.await??;
 
-        Ok(rx.await??)
+        METRICS.resumes.inc(self.metric_labels());
+        Ok(())
+    }
+
+    pub(crate) async fn invalidate_session(&self) {
+        *self.session_id.write().await = None;
+        *self.seq.write().await = None;
+
+        // delete session ID from Redis
+        if let Err(e) = self.delete_session_id().await {
+            self.log_err("Error deleting session_id from Redis", &e);
+        }
+
+        // delete seq from Redis
+        if let Err(e) = self.delete_seq().await {
+            self.log_err("Error deleting seq from Redis", &e);
+        }
     }
 
     async fn save_session_id(&self) -> Result<(), GatewayError> {
@@ -864,7 +1426,7 @@ impl<T: EventForwarder> Shard<T> {
 
                 cmd("SET")
                     .arg(&[&key[..], session_id, "EX", "120"]) // expiry of 120s
-                    .query_async(&mut conn)
+                    .query_async::<()>(&mut conn)
                     .await?;
 
                 Ok(())
@@ -904,7 +1466,7 @@ impl<T: EventForwarder> Shard<T> {
 
                 cmd("SET")
                     .arg(&[&key[..], &seq.to_string()[..], "EX", "120"]) // expiry of 120s
-                    .query_async(&mut conn)
+                    .query_async::<()>(&mut conn)
                     .await?;
 
                 Ok(())
@@ -939,7 +1501,10 @@ impl<T: EventForwarder> Shard<T> {
             None => return Ok(()),
         };
 
-        cmd("DEL").arg(&[&key[..]]).query_async(&mut conn).await?;
+        cmd("DEL")
+            .arg(&[&key[..]])
+            .query_async::<()>(&mut conn)
+            .await?;
 
         Ok(())
     }
@@ -952,106 +1517,131 @@ impl<T: EventForwarder> Shard<T> {
             None => return Ok(()),
         };
 
-        cmd("DEL").arg(&[&key[..]]).query_async(&mut conn).await?;
+        cmd("DEL")
+            .arg(&[&key[..]])
+            .query_async::<()>(&mut conn)
+            .await?;
 
         Ok(())
     }
 
     async fn get_resume_key(&self) -> Option<String> {
-        if is_whitelabel() {
-            Some(format!(
-                "tickets:resume:{}:{}",
-                self.user_id,
-                self.get_shard_id()
-            ))
-        } else {
-            Some(format!(
-                "tickets:resume:public:{}-{}",
-                self.get_shard_id(),
-                self.identify.data.shard_info.num_shards
-            ))
-        }
+        Some(
+            self.mode
+                .resume_key(self.user_id, &self.identify.data.shard_info),
+        )
     }
 
     async fn get_seq_key(&self) -> Option<String> {
-        if is_whitelabel() {
-            Some(format!(
-                "tickets:seq:{}:{}",
-                self.user_id,
-                self.get_shard_id()
-            ))
-        } else {
-            Some(format!(
-                "tickets:seq:public:{}-{}",
-                self.get_shard_id(),
-                self.identify.data.shard_info.num_shards
-            ))
-        }
+        Some(
+            self.mode
+                .seq_key(self.user_id, &self.identify.data.shard_info),
+        )
     }
 
     /// helper
+    fn metric_labels(&self) -> ShardLabels {
+        ShardLabels::new(self.user_id, self.get_shard_id())
+    }
+
+    fn record_reconnect(&self, reason: impl Into<Cow<'static, str>>) {
+        METRICS.reconnects.inc_with(self.metric_labels(), reason);
+    }
+
     pub fn get_shard_id(&self) -> u16 {
         self.identify.data.shard_info.shard_id
     }
 
+    pub fn get_shard_total(&self) -> u16 {
+        self.identify.data.shard_info.num_shards
+    }
+
+    fn log_prefix(&self) -> String {
+        self.mode
+            .log_prefix(self.user_id, &self.identify.data.shard_info)
+    }
+
     pub fn log(&self, msg: impl Display) {
-        if is_whitelabel() {
-            info!("[shard:{}] {}", self.user_id, msg);
-        } else {
-            info!("[shard:{:0>2}] {}", self.get_shard_id(), msg);
-        }
+        info!("{} {}", self.log_prefix(), msg);
     }
 
     pub fn log_err(&self, msg: impl Display, err: &GatewayError) {
-        if is_whitelabel() {
-            error!("[shard:{}] {}: {}", self.user_id, msg, err);
-        } else {
-            error!("[shard:{:0>2}] {}: {}", self.get_shard_id(), msg, err);
-        }
+        error!("{} {}: {}", self.log_prefix(), msg, err);
     }
 
     pub fn log_debug(&self, msg: impl Display, raw_payload: &str, err: impl Error) {
-        if is_whitelabel() {
-            debug!(
-                "[shard:{}] {}: {}\nFull payload: {}",
-                self.user_id, msg, err, raw_payload
-            );
-        } else {
-            debug!(
-                "[shard:{:0>2}] {}: {}\nFull payload: {}",
-                self.get_shard_id(),
-                msg,
-                err,
-                raw_payload
-            );
-        }
+        debug!(
+            "{} {}: {}\nFull payload: {}",
+            self.log_prefix(),
+            msg,
+            err,
+            raw_payload
+        );
     }
 }
 
 async fn handle_writes(
     mut tx: futures::channel::mpsc::UnboundedSender<tungstenite::Message>,
     mut rx: mpsc::Receiver<super::OutboundMessage>,
+    queue_depth: Arc<AtomicUsize>,
 ) {
-    while let Some(msg) = rx.recv().await {
-        let payload = Message::text(msg.message);
-        let res = tx.send(payload).await;
+    let mut limiter = SendRateLimiter::new(SEND_LIMIT, SEND_LIMIT_PERIOD, HEARTBEAT_RESERVE);
+    let mut queue: VecDeque<OutboundMessage> = VecDeque::new();
+    let mut presence: Option<OutboundMessage> = None;
+
+    loop {
+        queue_depth.store(queue.len() + presence.is_some() as usize, Ordering::Relaxed);
+
+        let has_pending = !queue.is_empty() || presence.is_some();
+        let next_is_priority =
+            matches!(queue.front(), Some(msg) if msg.kind == MessageKind::Heartbeat);
+        tokio::select! {
+            msg = rx.recv() => {
+                let msg = match msg {
+                    Some(msg) => msg,
+                    None => break,
+                };
 
-        if let Err(e) = msg.tx.send(res) {
-            eprintln!("Error while sending write result back to caller: {:?}", e);
+                match msg.kind {
+                    MessageKind::Close => write_message(&mut tx, msg).await,
+                    MessageKind::Heartbeat => queue.push_front(msg),
+                    MessageKind::Command => queue.push_back(msg),
+                    MessageKind::PresenceUpdate => {
+                        // superseded, so there's no need to send it
+                        if let Some(old) = presence.replace(msg) {
+                            let _ = old.tx.send(Ok(()));
+                        }
+                    }
+                }
+            }
+
+            // wait for a token to become available to the next message
+            _ = tokio::time::sleep_until(limiter.available_at(next_is_priority).into()), if has_pending => {}
         }
-    }
-}
 
-fn apply_guild_id_to_channels(guild: &mut Guild) {
-    if let Some(channels) = &mut guild.channels {
-        for channel in channels {
-            channel.guild_id = Some(guild.id)
+        while let Some(msg) = queue.front() {
+            if !limiter.try_acquire(msg.kind == MessageKind::Heartbeat) {
+                break;
+            }
+
+            let msg = queue.pop_front().unwrap();
+            write_message(&mut tx, msg).await;
         }
-    }
 
-    if let Some(threads) = &mut guild.threads {
-        for thread in threads {
-            thread.guild_id = Some(guild.id)
+        // presence updates are lowest priority
+        if queue.is_empty() && presence.is_some() && limiter.try_acquire(false) {
+            write_message(&mut tx, presence.take().unwrap()).await;
         }
     }
 }
+
+async fn write_message(
+    tx: &mut futures::channel::mpsc::UnboundedSender<tungstenite::Message>,
+    msg: OutboundMessage,
+) {
+    let res = tx.send(msg.message).await;
+
+    if let Err(e) = msg.tx.send(res) {
+        eprintln!("Error while sending write result back to caller: {:?}", e);
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use database::Database;
+use model::Snowflake;
+
+use crate::gateway::{GatewayError, ShardInfo};
+
+/// The behaviour that differs between shards of the public bot and shards of whitelabel bots
+#[async_trait]
+pub trait ShardMode: Send + Sync + 'static {
+    fn is_whitelabel(&self) -> bool;
+
+    /// Redis key used to make sure only one shard per bucket identifies at a time
+    fn identify_ratelimit_key(
+        &self,
+        bot_id: Snowflake,
+        shard_info: &ShardInfo,
+        large_sharding_buckets: u16,
+    ) -> String;
+
+    fn resume_key(&self, bot_id: Snowflake, shard_info: &ShardInfo) -> String;
+
+    fn seq_key(&self, bot_id: Snowflake, shard_info: &ShardInfo) -> String;
+
+    /// Redis set of the IDs of the bots, public and whitelabel, that are in the guild. Shared
+    /// between modes, as a guild is only evicted from the cache once no bot remains in it.
+    fn guild_bots_key(&self, guild_id: Snowflake) -> String {
+        format!("tickets:guild_bots:{}", guild_id)
+    }
+
+    /// Called for every GUILD_CREATE received, after the guild has been recorded by the shard
+    async fn on_guild_create(
+        &self,
+        _bot_id: Snowflake,
+        _guild_id: Snowflake,
+    ) -> Result<(), GatewayError> {
+        Ok(())
+    }
+
+    /// Called for every GUILD_DELETE received as a result of the bot being removed from the guild,
+    /// rather than the guild becoming unavailable
+    async fn on_guild_delete(
+        &self,
+        _bot_id: Snowflake,
+        _guild_id: Snowflake,
+    ) -> Result<(), GatewayError> {
+        Ok(())
+    }
+
+    /// Prepended to every line logged by the shard
+    fn log_prefix(&self, bot_id: Snowflake, shard_info: &ShardInfo) -> String;
+}
+
+/// A shard of the public bot, identified by its shard ID
+pub struct PublicMode;
+
+impl ShardMode for PublicMode {
+    fn is_whitelabel(&self) -> bool {
+        false
+    }
+
+    fn identify_ratelimit_key(
+        &self,
+        _bot_id: Snowflake,
+        shard_info: &ShardInfo,
+        large_sharding_buckets: u16,
+    ) -> String {
+        format!(
+            "ratelimiter:public:identify:{}",
+            shard_info.shard_id % large_sharding_buckets
+        )
+    }
+
+    fn resume_key(&self, _bot_id: Snowflake, shard_info: &ShardInfo) -> String {
+        format!(
+            "tickets:resume:public:{}-{}",
+            shard_info.shard_id, shard_info.num_shards
+        )
+    }
+
+    fn seq_key(&self, _bot_id: Snowflake, shard_info: &ShardInfo) -> String {
+        format!(
+            "tickets:seq:public:{}-{}",
+            shard_info.shard_id, shard_info.num_shards
+        )
+    }
+
+    fn log_prefix(&self, _bot_id: Snowflake, shard_info: &ShardInfo) -> String {
+        format!("[shard:{:0>2}]", shard_info.shard_id)
+    }
+}
+
+/// A shard of a whitelabel bot, identified by the bot's ID. The guilds each bot is in are stored
+/// in the database.
+#[derive(Clone)]
+pub struct WhitelabelMode {
+    database: Arc<Database>,
+}
+
+impl WhitelabelMode {
+    pub fn new(database: Arc<Database>) -> WhitelabelMode {
+        WhitelabelMode { database }
+    }
+}
+
+#[async_trait]
+impl ShardMode for WhitelabelMode {
+    fn is_whitelabel(&self) -> bool {
+        true
+    }
+
+    fn identify_ratelimit_key(
+        &self,
+        bot_id: Snowflake,
+        _shard_info: &ShardInfo,
+        _large_sharding_buckets: u16,
+    ) -> String {
+        format!("ratelimiter:whitelabel:identify:{}", bot_id)
+    }
+
+    fn resume_key(&self, bot_id: Snowflake, shard_info: &ShardInfo) -> String {
+        format!("tickets:resume:{}:{}", bot_id, shard_info.shard_id)
+    }
+
+    fn seq_key(&self, bot_id: Snowflake, shard_info: &ShardInfo) -> String {
+        format!("tickets:seq:{}:{}", bot_id, shard_info.shard_id)
+    }
+
+    async fn on_guild_create(
+        &self,
+        bot_id: Snowflake,
+        guild_id: Snowflake,
+    ) -> Result<(), GatewayError> {
+        self.database
+            .whitelabel_guilds
+            .insert(bot_id, guild_id)
+            .await
+            .map_err(GatewayError::DatabaseError)
+    }
+
+    async fn on_guild_delete(
+        &self,
+        bot_id: Snowflake,
+        guild_id: Snowflake,
+    ) -> Result<(), GatewayError> {
+        self.database
+            .whitelabel_guilds
+            .delete(bot_id, guild_id)
+            .await
+            .map_err(GatewayError::DatabaseError)
+    }
+
+    fn log_prefix(&self, bot_id: Snowflake, _shard_info: &ShardInfo) -> String {
+        format!("[shard:{}]", bot_id)
+    }
+}
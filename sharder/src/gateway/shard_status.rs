@@ -0,0 +1,35 @@
+use model::Snowflake;
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    /// Not connected, and will reconnect shortly
+    Disconnected,
+    /// Connected to the gateway, but hasn't received READY or RESUMED yet
+    Connecting,
+    Connected,
+    /// Stopped through the admin API, and won't reconnect until told to
+    Stopped,
+}
+
+/// A snapshot of a shard's state, as reported by the admin API
+#[derive(Serialize, Debug)]
+pub struct ShardStatus {
+    pub bot_id: Snowflake,
+    pub shard_id: u16,
+    pub shard_total: u16,
+    pub state: ConnectionState,
+    pub has_session: bool,
+    pub seq: Option<usize>,
+    // ms between the last acknowledged heartbeat and its ACK, None until the first ACK
+    pub heartbeat_latency: Option<u64>,
+    pub guild_count: usize,
+    // guilds listed in READY whose GUILD_CREATE hasn't been received yet
+    pub pending_guild_count: usize,
+    // guilds that Discord has reported an outage in
+    pub unavailable_guild_count: usize,
+    // ms since the websocket was opened, None if not connected
+    pub connected_for: Option<u64>,
+    pub outbound_queue_depth: usize,
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::FutureExt;
+use log::{error, info};
+use model::Snowflake;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::metrics::{ShardLabels, METRICS};
+
+// payloads waiting to be written, beyond which new payloads are dropped rather than slowing the
+// shards down
+const QUEUE_SIZE: usize = 10_000;
+
+/// A single line of a recording
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordedPayload<'a> {
+    // ms since the unix epoch
+    pub received_at: u64,
+    pub bot_id: Snowflake,
+    pub shard_id: u16,
+    pub shard_total: u16,
+    #[serde(borrow)]
+    pub payload: &'a RawValue,
+}
+
+/// Writes the decompressed payloads received by every shard in the process to newline delimited
+/// JSON files, which can be fed back through a shard with the `replay_traffic` binary. A new file
+/// is started once the current one reaches the size limit, and the oldest files written by this
+/// process are deleted to stay under the file limit.
+pub struct TrafficRecorder {
+    tx: mpsc::Sender<String>,
+}
+
+impl TrafficRecorder {
+    /// Returns None if recording isn't enabled in the config
+    pub fn from_config(config: &Config) -> Option<TrafficRecorder> {
+        let dir = config.record_traffic_dir.as_ref()?;
+
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let writer = RecordingWriter {
+            dir: PathBuf::from(dir),
+            prefix: format!("traffic-{}", config.sharder_id),
+            max_file_size: config.record_traffic_max_file_size,
+            max_files: config.record_traffic_max_files,
+            files: VecDeque::new(),
+            current: None,
+            current_size: 0,
+        };
+
+        tokio::spawn(writer.run(rx));
+
+        Some(TrafficRecorder { tx })
+    }
+
+    /// Queues a payload to be written. Payloads are dropped if the writer can't keep up.
+    pub fn record(&self, bot_id: Snowflake, shard_id: u16, shard_total: u16, payload: &[u8]) {
+        let payload: &RawValue = match serde_json::from_slice(payload) {
+            Ok(payload) => payload,
+            Err(_) => return, // the shard logs the payload when it fails to parse it too
+        };
+
+        let recorded = RecordedPayload {
+            received_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            bot_id,
+            shard_id,
+            shard_total,
+            payload,
+        };
+
+        let line = match serde_json::to_string(&recorded) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Error serializing recorded payload: {}", e);
+                return;
+            }
+        };
+
+        if self.tx.try_send(line).is_err() {
+            METRICS
+                .recorded_payloads_dropped
+                .inc(ShardLabels::new(bot_id, shard_id));
+        }
+    }
+}
+
+struct RecordingWriter {
+    dir: PathBuf,
+    prefix: String,
+    max_file_size: u64,
+    max_files: usize,
+    // oldest first, including the current file
+    files: VecDeque<PathBuf>,
+    current: Option<BufWriter<File>>,
+    current_size: u64,
+}
+
+impl RecordingWriter {
+    async fn run(mut self, mut rx: mpsc::Receiver<String>) {
+        while let Some(line) = rx.recv().await {
+            self.write(line).await;
+
+            // flush once the queue is drained, rather than after every payload
+            while let Some(Some(line)) = rx.recv().now_or_never() {
+                self.write(line).await;
+            }
+
+            if let Some(file) = self.current.as_mut() {
+                if let Err(e) = file.flush().await {
+                    error!("Error flushing traffic recording: {}", e);
+                    self.current = None;
+                }
+            }
+        }
+    }
+
+    async fn write(&mut self, mut line: String) {
+        if self.current.is_none() || self.current_size >= self.max_file_size {
+            if let Err(e) = self.rotate().await {
+                error!("Error starting new traffic recording: {}", e);
+                return;
+            }
+        }
+
+        line.push('\n');
+
+        if let Some(file) = self.current.as_mut() {
+            match file.write_all(line.as_bytes()).await {
+                Ok(()) => self.current_size += line.len() as u64,
+                Err(e) => {
+                    error!("Error writing traffic recording: {}", e);
+                    // start a new file for the next payload
+                    self.current = None;
+                }
+            }
+        }
+    }
+
+    async fn rotate(&mut self) -> std::io::Result<()> {
+        if let Some(mut file) = self.current.take() {
+            file.flush().await?;
+        }
+
+        fs::create_dir_all(&self.dir).await?;
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let path = self.dir.join(format!("{}-{}.ndjson", self.prefix, millis));
+
+        let file = File::create(&path).await?;
+        info!("Recording gateway traffic to {}", path.display());
+
+        self.current = Some(BufWriter::new(file));
+        self.current_size = 0;
+        self.files.push_back(path);
+
+        while self.files.len() > self.max_files.max(1) {
+            if let Some(oldest) = self.files.pop_front() {
+                if let Err(e) = fs::remove_file(&oldest).await {
+                    error!(
+                        "Error deleting old traffic recording {}: {}",
+                        oldest.display(),
+                        e
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,2 @@
+mod auth_token_extractor;
+pub use auth_token_extractor::AuthTokenExtractor;
@@ -0,0 +1,6 @@
+mod server;
+pub use server::Server;
+
+mod extractors;
+mod response;
+mod routes;
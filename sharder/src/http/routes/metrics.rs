@@ -0,0 +1,14 @@
+use crate::http::Server;
+use crate::metrics::METRICS;
+use crate::ShardManager;
+use axum::extract;
+use std::sync::Arc;
+
+pub async fn metrics_handler<T: ShardManager + Send + Sync + 'static>(
+    server: extract::Extension<Arc<Server<T>>>,
+) -> String {
+    let shards = server.0.shard_manager.shards().await;
+    let statuses = futures::future::join_all(shards.iter().map(|shard| shard.status())).await;
+
+    METRICS.render(&statuses)
+}
@@ -0,0 +1,11 @@
+mod shards;
+pub use shards::shards_handler;
+
+mod shard_command;
+pub use shard_command::shard_command_handler;
+
+mod reshard;
+pub use reshard::reshard_handler;
+
+mod metrics;
+pub use metrics::metrics_handler;
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use axum::extract;
+
+use crate::http::server::Server;
+
+use crate::http::extractors::AuthTokenExtractor;
+use crate::http::response::Response;
+use crate::{GatewayError, ShardManager};
+use axum::response::Json;
+use hyper::StatusCode;
+
+pub async fn reshard_handler<T: ShardManager + Send + Sync + 'static>(
+    auth_token: AuthTokenExtractor,
+    path: extract::Path<u16>,
+    server: extract::Extension<Arc<Server<T>>>,
+) -> (StatusCode, Json<Response>) {
+    let (server, shard_total) = (server.0, path.0);
+
+    if let Err(rejection) = server.authorize(&auth_token.0) {
+        return rejection;
+    }
+
+    // resharding continues in the background once started
+    match Arc::clone(&server.shard_manager).reshard(shard_total).await {
+        Ok(()) => (StatusCode::ACCEPTED, Json(Response::success())),
+        Err(e) => {
+            let status = match e {
+                GatewayError::AlreadyReshardingError => StatusCode::CONFLICT,
+                GatewayError::InvalidShardTotalError(_)
+                | GatewayError::ReshardingUnsupportedError => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+
+            (status, Json(Response::error(&e.to_string())))
+        }
+    }
+}
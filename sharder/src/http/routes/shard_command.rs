@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use axum::extract;
+
+use crate::http::server::Server;
+
+use crate::http::extractors::AuthTokenExtractor;
+use crate::http::response::Response;
+use crate::ShardManager;
+use axum::response::Json;
+use hyper::StatusCode;
+use log::info;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    /// Drop the connection and resume, or start the shard if it was killed
+    Reconnect,
+    /// Drop the connection, and don't reconnect until a reconnect command is received
+    Kill,
+    /// Discard the session, and identify afresh
+    Reidentify,
+}
+
+pub async fn shard_command_handler<T: ShardManager + Send + Sync + 'static>(
+    auth_token: AuthTokenExtractor,
+    path: extract::Path<(u64, u16, Command)>,
+    server: extract::Extension<Arc<Server<T>>>,
+) -> (StatusCode, Json<Response>) {
+    let (server, (bot_id, shard_id, command)) = (server.0, path.0);
+
+    if let Err(rejection) = server.authorize(&auth_token.0) {
+        return rejection;
+    }
+
+    let shard = server
+        .shard_manager
+        .shards()
+        .await
+        .into_iter()
+        .find(|shard| shard.user_id.0 == bot_id && shard.get_shard_id() == shard_id);
+
+    let shard = match shard {
+        Some(shard) => shard,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(Response::error("Shard not found")),
+            )
+        }
+    };
+
+    info!(
+        "Received {:?} command for shard {} of {} from admin API",
+        command, shard_id, bot_id
+    );
+
+    match command {
+        Command::Reconnect => shard.reconnect(),
+        Command::Kill => shard.stop(),
+        Command::Reidentify => shard.reidentify().await,
+    }
+
+    (StatusCode::OK, Json(Response::success()))
+}
@@ -0,0 +1,16 @@
+use crate::http::Server;
+use crate::{ShardManager, ShardStatus};
+use axum::extract;
+use axum::response::Json;
+use std::sync::Arc;
+
+pub async fn shards_handler<T: ShardManager + Send + Sync + 'static>(
+    server: extract::Extension<Arc<Server<T>>>,
+) -> Json<Vec<ShardStatus>> {
+    let shards = server.0.shard_manager.shards().await;
+
+    let mut statuses = futures::future::join_all(shards.iter().map(|shard| shard.status())).await;
+    statuses.sort_by_key(|status| (status.bot_id, status.shard_id));
+
+    Json(statuses)
+}
@@ -0,0 +1,78 @@
+use super::response::Response;
+use super::routes;
+use crate::{GatewayError, ShardManager};
+use axum::handler::{get, post};
+use axum::response::Json;
+use axum::{AddExtensionLayer, Router};
+use hyper::StatusCode;
+use log::error;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+/// Reports the state of every shard and serves Prometheus metrics, and accepts commands to
+/// reconnect, stop or re-identify a single shard, or to reshard
+pub struct Server<T: ShardManager> {
+    pub addr: String,
+    pub token: Option<String>,
+    pub shard_manager: Arc<T>,
+}
+
+impl<T: ShardManager + Send + Sync + 'static> Server<T> {
+    pub fn new(addr: String, token: Option<String>, shard_manager: Arc<T>) -> Server<T> {
+        Server {
+            addr,
+            token,
+            shard_manager,
+        }
+    }
+
+    pub async fn start(self) -> Result<(), GatewayError> {
+        let server = Arc::new(self);
+
+        let app = Router::new()
+            .route("/shards", get(routes::shards_handler::<T>))
+            .layer(AddExtensionLayer::new(server.clone()))
+            .route("/metrics", get(routes::metrics_handler::<T>))
+            .layer(AddExtensionLayer::new(server.clone()))
+            .route(
+                "/shards/:bot_id/:shard_id/:command",
+                post(routes::shard_command_handler::<T>),
+            )
+            .layer(AddExtensionLayer::new(server.clone()))
+            .route("/reshard/:shard_total", post(routes::reshard_handler::<T>))
+            .layer(AddExtensionLayer::new(server.clone()));
+
+        let addr = &server.addr[..].parse()?;
+
+        hyper::Server::bind(addr)
+            .serve(app.into_make_service())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Checks the token sent with a command, which is rejected if no token is configured
+    pub(crate) fn authorize(&self, token: &str) -> Result<(), (StatusCode, Json<Response>)> {
+        match &self.token {
+            // compared in constant time, so that the token can't be guessed from response times
+            Some(expected) if bool::from(token.as_bytes().ct_eq(expected.as_bytes())) => Ok(()),
+            Some(_) => Err((
+                StatusCode::UNAUTHORIZED,
+                Json(Response::error("Invalid token")),
+            )),
+            None => Err((
+                StatusCode::FORBIDDEN,
+                Json(Response::error("Admin API commands are disabled")),
+            )),
+        }
+    }
+
+    /// Runs the server in the background, logging the error if it exits
+    pub fn spawn(self) {
+        tokio::spawn(async move {
+            if let Err(e) = self.start().await {
+                error!("Admin API exited with error: {}", e);
+            }
+        });
+    }
+}
@@ -2,16 +2,18 @@ mod gateway;
 pub use gateway::*;
 
 mod manager;
-pub use manager::{Options, ShardCount, ShardManager};
-
-#[cfg(not(feature = "whitelabel"))]
-pub use manager::PublicShardManager;
-
-#[cfg(feature = "whitelabel")]
-pub use manager::WhitelabelShardManager;
+pub use manager::{lease_target, Options, ShardCount, ShardManager};
+pub use manager::{PublicShardManager, WhitelabelShardManager};
 
 mod builders;
 pub use builders::{build_cache, build_redis, setup_sentry};
 
+pub mod http;
+
+pub mod metrics;
+
+mod shutdown;
+pub use shutdown::{shutdown_on_signal, wait_for_shutdown_signal};
+
 mod config;
-pub use config::Config;
+pub use config::{CacheBackend, Config, ForwardingMode, SharderMode};
@@ -1,24 +1,98 @@
 mod shard_manager;
 pub use shard_manager::ShardManager;
 
-#[cfg(not(feature = "whitelabel"))]
 mod public_shard_manager;
-#[cfg(not(feature = "whitelabel"))]
 pub use public_shard_manager::PublicShardManager;
 
-#[cfg(feature = "whitelabel")]
 mod whitelabel_shard_manager;
-#[cfg(feature = "whitelabel")]
 pub use whitelabel_shard_manager::WhitelabelShardManager;
 
 mod options;
 pub use options::*;
 
-use crate::gateway::Intents;
-fn get_intents() -> u64 {
-    Intents::build(vec![
-        Intents::Guilds,
-        Intents::GuildMembers,
-        Intents::GuildMessages,
-    ])
+use crate::builders::cache_options;
+use crate::gateway::event_forwarding::{EventForwarder, EventWhitelist};
+use crate::gateway::{GuildMembersFilter, IntentSet, Intents, Shard, ShardMode};
+use cache::Cache;
+use common::request_guild_members;
+use log::warn;
+use model::Snowflake;
+use std::sync::Arc;
+
+/// Logs a warning for each forwarded or cached event that the bot won't receive with its intents
+fn check_intents(bot_id: Snowflake, intents: IntentSet, event_whitelist: &EventWhitelist) {
+    for event in event_whitelist.events() {
+        if let Some(intent) = Intents::required_for(event) {
+            if !intents.contains(intent) {
+                warn!(
+                    "[{}] {} is forwarded to workers, but requires the {:?} intent, which is not enabled",
+                    bot_id, event, intent
+                );
+            }
+        }
+    }
+
+    let opts = cache_options();
+    let cached = [
+        (
+            opts.guilds || opts.channels || opts.roles,
+            Intents::Guilds,
+            "guilds",
+        ),
+        (opts.members, Intents::GuildMembers, "members"),
+        (opts.emojis, Intents::GuildEmojis, "emojis"),
+        (opts.voice_states, Intents::GuildVoiceStates, "voice states"),
+    ];
+
+    for &(enabled, intent, name) in cached.iter() {
+        if enabled && !intents.contains(intent) {
+            warn!(
+                "[{}] Caching {} requires the {:?} intent, which is not enabled",
+                bot_id, name, intent
+            );
+        }
+    }
+}
+
+/// The number of bot leases each whitelabel sharder should hold for the bots to be spread evenly
+/// between the live sharders. Rounded up, so that no bot is left unclaimed while every sharder is
+/// under its capacity.
+pub fn lease_target(bot_count: usize, sharder_count: usize, capacity: Option<usize>) -> usize {
+    let even = bot_count.div_ceil(sharder_count.max(1));
+    capacity.map_or(even, |capacity| even.min(capacity))
+}
+
+/// Requests the members described by a payload received over Redis. The members are written to the
+/// cache by the shard as the chunks arrive.
+fn request_guild_members<T: EventForwarder, M: ShardMode, C: Cache>(
+    shard: Arc<Shard<T, M, C>>,
+    payload: request_guild_members::Payload,
+) {
+    let guild_id = payload.guild_id;
+    let presences = payload.presences;
+
+    let filter = match payload.user_ids {
+        Some(user_ids) => GuildMembersFilter::UserIds(user_ids),
+        None => GuildMembersFilter::Query {
+            query: payload.query.unwrap_or_default(),
+            limit: payload.limit.unwrap_or(0),
+        },
+    };
+
+    tokio::spawn(async move {
+        match shard
+            .request_guild_members(guild_id, filter, presences)
+            .await
+        {
+            Ok(members) => shard.log(format!(
+                "Received {} members for guild {}",
+                members.members.len(),
+                guild_id
+            )),
+            Err(e) => shard.log_err(
+                format!("Error requesting members for guild {}", guild_id),
+                &e,
+            ),
+        }
+    });
 }
@@ -1,12 +1,15 @@
-use model::user::StatusUpdate;
 use model::Snowflake;
+use std::sync::Arc;
+
+use crate::gateway::{Presence, SessionStartLimiter};
 
 pub struct Options {
     pub token: Box<str>,
     pub shard_count: ShardCount,
-    pub presence: StatusUpdate,
+    pub presence: Presence,
     pub large_sharding_buckets: u16,
     pub user_id: Snowflake,
+    pub session_start_limiter: Arc<SessionStartLimiter>,
 }
 
 pub struct ShardCount {
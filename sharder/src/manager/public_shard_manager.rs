@@ -1,102 +1,285 @@
 use async_trait::async_trait;
 
-use super::Options;
 use super::ShardManager;
+use super::{Options, ShardCount};
 
-use crate::gateway::{Identify, Shard, ShardInfo};
-
-use model::user::{ActivityType, StatusType, StatusUpdate};
+use crate::gateway::{
+    rotate_presence, ForwardingGate, Identify, Presence, PublicMode, Shard, ShardInfo,
+    ShardServices, TrafficRecorder,
+};
 
 use std::sync::Arc;
 
 use std::collections::HashMap;
 
-use cache::PostgresCache;
+use cache::Cache;
 
 use crate::config::Config;
-use crate::gateway::event_forwarding::EventForwarder;
+use crate::gateway::event_forwarding::{EventForwarder, EventWhitelist};
+use crate::GatewayError;
+use common::request_guild_members;
 use deadpool_redis::Pool;
+use futures::StreamExt;
+use log::{info, warn};
 use std::time::Duration;
 use tokio::fs::File;
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, watch, RwLock};
 use tokio::time::sleep;
 
-pub struct PublicShardManager<T: EventForwarder> {
+pub struct PublicShardManager<T: EventForwarder, C: Cache> {
     config: Arc<Config>,
-    shards: HashMap<u16, Arc<Shard<T>>>,
+    options: Options,
+    cache: Arc<C>,
+    redis: Arc<Pool>,
+    event_forwarder: Arc<T>,
+    event_whitelist: Arc<EventWhitelist>,
+    forwarding_gate: Arc<ForwardingGate>,
+    recorder: Option<Arc<TrafficRecorder>>,
+    // kept to hand out receivers to the presence rotation of each shard
+    presence_tx: watch::Sender<Arc<Presence>>,
+    shards: RwLock<ShardSet<T, C>>,
+    // the new set of shards being brought up while resharding
+    pending_shards: RwLock<Option<ShardSet<T, C>>>,
+}
+
+struct ShardSet<T: EventForwarder, C: Cache> {
+    total: u16,
+    shards: HashMap<u16, Arc<Shard<T, PublicMode, C>>>,
 }
 
-#[cfg(not(feature = "whitelabel"))]
-impl<T: EventForwarder> PublicShardManager<T> {
+impl<T: EventForwarder, C: Cache> PublicShardManager<T, C> {
     pub async fn new(
         config: Config,
         options: Options,
-        cache: Arc<PostgresCache>,
+        cache: Arc<C>,
         redis: Arc<Pool>,
         event_forwarder: Arc<T>,
     ) -> Self {
+        let event_whitelist = Arc::new(config.forward_events.clone());
+        super::check_intents(options.user_id, config.intents, &event_whitelist);
+
+        let (presence_tx, _) = watch::channel(Arc::new(options.presence.clone()));
+
         let mut sm = PublicShardManager {
+            recorder: TrafficRecorder::from_config(&config).map(Arc::new),
+            presence_tx,
             config: Arc::new(config),
-            shards: HashMap::new(),
+            forwarding_gate: Arc::new(ForwardingGate::new(options.shard_count.total)),
+            options,
+            cache,
+            redis,
+            event_forwarder,
+            event_whitelist,
+            shards: RwLock::new(ShardSet {
+                total: 0,
+                shards: HashMap::new(),
+            }),
+            pending_shards: RwLock::new(None),
         };
 
-        for i in options.shard_count.lowest..options.shard_count.highest {
-            let shard_info = ShardInfo::new(i, options.shard_count.total);
-            let status = StatusUpdate::new(
-                ActivityType::Listening,
-                "/help | /setup".to_owned(),
-                StatusType::Online,
-            );
+        let shards = sm.build_shard_set(&sm.options.shard_count);
+        *sm.shards.get_mut() = shards;
+
+        sm
+    }
+
+    fn build_shard_set(&self, shard_count: &ShardCount) -> ShardSet<T, C> {
+        let mut shards = HashMap::new();
+
+        for i in shard_count.lowest..shard_count.highest {
+            let shard_info = ShardInfo::new(i, shard_count.total);
+            let presence = self.options.presence.initial(&shard_info);
             let identify = Identify::new(
-                options.token.clone().into_string(),
+                self.options.token.clone().into_string(),
                 None,
                 shard_info,
-                Some(status),
-                super::get_intents(),
+                presence,
+                self.config.intents.bits(),
             );
 
+            let services = ShardServices {
+                cache: Arc::clone(&self.cache),
+                redis: Arc::clone(&self.redis),
+                event_forwarder: Arc::clone(&self.event_forwarder),
+                event_whitelist: Arc::clone(&self.event_whitelist),
+                session_start_limiter: Arc::clone(&self.options.session_start_limiter),
+                forwarding_gate: Arc::clone(&self.forwarding_gate),
+                recorder: self.recorder.clone(),
+            };
+
             let shard = Shard::new(
-                Arc::clone(&sm.config),
+                Arc::clone(&self.config),
                 identify,
-                options.large_sharding_buckets,
-                Arc::clone(&cache),
-                Arc::clone(&redis),
-                options.user_id,
-                Arc::clone(&event_forwarder),
+                self.options.large_sharding_buckets,
+                self.options.user_id,
+                services,
+                PublicMode,
             );
 
-            sm.shards.insert(i, shard);
+            tokio::spawn(rotate_presence(
+                Arc::downgrade(&shard),
+                self.presence_tx.subscribe(),
+            ));
+
+            shards.insert(i, shard);
         }
 
-        sm
+        ShardSet {
+            total: shard_count.total,
+            shards,
+        }
     }
-}
 
-#[async_trait]
-impl<T: EventForwarder> ShardManager for PublicShardManager<T> {
-    async fn connect(self: Arc<Self>) {
-        for (_, shard) in self.shards.iter() {
-            let shard_id = shard.get_shard_id();
-            let shard = Arc::clone(shard);
-            let (ready_tx, ready_rx) = oneshot::channel::<()>();
+    /// Connects the shard, reconnecting whenever it exits until it's shut down. The returned
+    /// receiver resolves once the shard has loaded most of its guilds.
+    fn run_shard(shard: Arc<Shard<T, PublicMode, C>>) -> oneshot::Receiver<()> {
+        let (ready_tx, ready_rx) = oneshot::channel::<()>();
 
-            tokio::spawn(async move {
-                let mut ready_tx = Some(ready_tx);
+        tokio::spawn(async move {
+            let mut ready_tx = Some(ready_tx);
 
-                loop {
-                    let shard = Arc::clone(&shard);
-                    shard.log("Starting...");
+            loop {
+                let shard = Arc::clone(&shard);
+                shard.log("Starting...");
 
-                    match Arc::clone(&shard).connect(ready_tx.take()).await {
-                        Ok(()) => shard.log("Exited with Ok"),
-                        Err(e) => shard.log_err("Exited with error", &e),
-                    }
+                match Arc::clone(&shard).connect(ready_tx.take()).await {
+                    Ok(()) => shard.log("Exited with Ok"),
+                    Err(e) => shard.log_err("Exited with error", &e),
+                }
 
-                    sleep(Duration::from_millis(500)).await;
+                if shard.is_shutting_down() {
+                    shard.log("Shut down, not restarting");
+                    break;
                 }
-            });
+
+                sleep(Duration::from_millis(500)).await;
+            }
+        });
+
+        ready_rx
+    }
+
+    async fn do_reshard(self: Arc<Self>) {
+        let new_shards = match &*self.pending_shards.read().await {
+            Some(set) => set.shards.values().cloned().collect::<Vec<_>>(),
+            None => return,
+        };
+
+        // new shards are brought up one at a time, as on startup, and don't forward events until
+        // every one of them is ready
+        let mut started = Vec::with_capacity(new_shards.len());
+        let mut all_ready = true;
+        for shard in new_shards {
+            let ready_rx = Self::run_shard(Arc::clone(&shard));
+            started.push(Arc::clone(&shard));
 
             match ready_rx.await {
+                Ok(_) => shard.log("Loaded guilds, ready to take over from the old shard set"),
+                Err(e) => {
+                    shard.log(format!("Error reading ready rx: {}", e));
+                    all_ready = false;
+                    break;
+                }
+            }
+        }
+
+        if !all_ready {
+            warn!("Not every new shard became ready, abandoning resharding");
+
+            futures::future::join_all(started.iter().map(|shard| shard.shutdown())).await;
+            *self.pending_shards.write().await = None;
+            return;
+        }
+
+        let old_shards = {
+            let mut shards = self.shards.write().await;
+            let new_set = match self.pending_shards.write().await.take() {
+                Some(set) => set,
+                None => return,
+            };
+
+            // switch forwarding while holding the lock, so that nothing sees the new set without
+            // it forwarding events
+            self.forwarding_gate.switch_to(new_set.total);
+            std::mem::replace(&mut *shards, new_set)
+        };
+
+        info!(
+            "Switched forwarding to {} shards, shutting down the old set of {}",
+            self.forwarding_gate.active_total(),
+            old_shards.total
+        );
+
+        futures::future::join_all(old_shards.shards.values().map(|shard| shard.shutdown())).await;
+        info!("Resharding complete");
+    }
+
+    pub async fn listen_request_guild_members(self: Arc<Self>) -> Result<(), GatewayError> {
+        let mut conn = redis::Client::open(self.config.get_redis_uri())
+            .unwrap()
+            .get_async_connection()
+            .await?
+            .into_pubsub();
+
+        conn.subscribe(request_guild_members::KEY).await?;
+
+        tokio::spawn(async move {
+            let mut stream = conn.on_message();
+
+            while let Some(m) = stream.next().await {
+                match serde_json::from_slice::<request_guild_members::Payload>(
+                    m.get_payload_bytes(),
+                ) {
+                    Ok(payload) => {
+                        if payload.bot_id != self.options.user_id {
+                            continue;
+                        }
+
+                        let shard = {
+                            let shards = self.shards.read().await;
+                            let shard_id =
+                                ((payload.guild_id.0 >> 22) % shards.total as u64) as u16;
+
+                            shards.shards.get(&shard_id).cloned()
+                        };
+
+                        // shards run by other sharders won't be present
+                        if let Some(shard) = shard {
+                            super::request_guild_members(shard, payload);
+                        }
+                    }
+                    Err(e) => eprintln!(
+                        "An error occurred while decoding request guild members payload: {}",
+                        e
+                    ),
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: EventForwarder, C: Cache> ShardManager for PublicShardManager<T, C> {
+    type Forwarder = T;
+    type Mode = PublicMode;
+    type Cache = C;
+
+    async fn connect(self: Arc<Self>) {
+        let shards = self
+            .shards
+            .read()
+            .await
+            .shards
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for shard in shards {
+            let shard_id = shard.get_shard_id();
+
+            match Self::run_shard(shard).await {
                 Ok(_) => println!("[{:0>2}] Loaded guilds", shard_id),
                 Err(e) => eprintln!("[{:0>2}] Error reading ready rx: {}", shard_id, e),
             }
@@ -105,4 +288,68 @@ impl<T: EventForwarder> ShardManager for PublicShardManager<T> {
         File::create("/tmp/ready").await.unwrap(); // panic if can't create
         println!("Reported readiness to probe");
     }
+
+    async fn shutdown(self: Arc<Self>) {
+        let shards = self.shards().await;
+        futures::future::join_all(shards.iter().map(|shard| shard.shutdown())).await;
+
+        // events still in flight are being retried, so dead-letter them rather than losing them
+        // when the process exits
+        self.event_forwarder.stop_retrying();
+        futures::future::join_all(shards.iter().map(|shard| shard.wait_for_in_flight())).await;
+    }
+
+    async fn shards(&self) -> Vec<Arc<Shard<T, PublicMode, C>>> {
+        let mut shards = self
+            .shards
+            .read()
+            .await
+            .shards
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if let Some(pending) = &*self.pending_shards.read().await {
+            shards.extend(pending.shards.values().cloned());
+        }
+
+        shards
+    }
+
+    async fn reshard(self: Arc<Self>, shard_total: u16) -> Result<(), GatewayError> {
+        let sharder_total = self.config.sharder_total;
+        if shard_total == 0 || !shard_total.is_multiple_of(sharder_total) {
+            return GatewayError::InvalidShardTotalError(shard_total).into();
+        }
+
+        if shard_total == self.shards.read().await.total {
+            return GatewayError::InvalidShardTotalError(shard_total).into();
+        }
+
+        {
+            let mut pending = self.pending_shards.write().await;
+            if pending.is_some() {
+                return GatewayError::AlreadyReshardingError.into();
+            }
+
+            let cluster_size = shard_total / sharder_total;
+            let shard_count = ShardCount {
+                total: shard_total,
+                lowest: cluster_size * self.config.sharder_id,
+                highest: cluster_size * (self.config.sharder_id + 1),
+            };
+
+            info!(
+                "Resharding to {} shards, running shards {} to {}",
+                shard_total,
+                shard_count.lowest,
+                shard_count.highest - 1
+            );
+
+            *pending = Some(self.build_shard_set(&shard_count));
+        }
+
+        tokio::spawn(self.do_reshard());
+        Ok(())
+    }
 }
@@ -1,8 +1,29 @@
 use async_trait::async_trait;
+use cache::Cache;
 
+use crate::gateway::event_forwarding::EventForwarder;
+use crate::gateway::{GatewayError, Shard, ShardMode};
 use std::sync::Arc;
 
 #[async_trait]
-pub trait ShardManager {
+pub trait ShardManager: Send + Sync {
+    type Forwarder: EventForwarder;
+    type Mode: ShardMode;
+    type Cache: Cache;
+
     async fn connect(self: Arc<Self>);
+
+    /// Gracefully shuts down every shard, leaving their sessions resumable, and dead-letters any
+    /// events that are still being retried
+    async fn shutdown(self: Arc<Self>);
+
+    /// Every shard currently run by this manager
+    async fn shards(&self) -> Vec<Arc<Shard<Self::Forwarder, Self::Mode, Self::Cache>>>;
+
+    /// Starts bringing up a new set of shards with the given total alongside the current set.
+    /// Forwarding switches to the new set, and the current set is shut down, once every new shard
+    /// is ready.
+    async fn reshard(self: Arc<Self>, _shard_total: u16) -> Result<(), GatewayError> {
+        GatewayError::ReshardingUnsupportedError.into()
+    }
 }
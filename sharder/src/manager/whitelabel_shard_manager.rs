@@ -2,287 +2,670 @@ use async_trait::async_trait;
 
 use super::ShardManager;
 
-use crate::gateway::{Identify, Shard, ShardInfo};
+use crate::gateway::{
+    rotate_presence, ForwardingGate, Identify, IntentSet, Presence, SessionStartLimiter, Shard,
+    ShardInfo, ShardServices, TrafficRecorder, WhitelabelMode,
+};
 
-use crate::gateway::event_forwarding::EventForwarder;
+use crate::gateway::event_forwarding::{EventForwarder, EventWhitelist};
+use crate::metrics::{ShardLabels, METRICS};
 use crate::{Config, GatewayError};
-use cache::PostgresCache;
-use common::token_change;
+use cache::Cache;
+use common::request_guild_members;
+use common::whitelabel_control::{self, Command};
 use database::{Database, WhitelabelBot};
-use deadpool_redis::Pool;
+use deadpool_redis::{cmd, Pool};
 use futures::StreamExt;
-use model::user::{ActivityType, StatusType, StatusUpdate};
+use log::{error, info, warn};
+use model::user::ActivityType;
 use model::Snowflake;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::iter;
 use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::RwLock;
-use tokio::time::sleep;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
 
-pub struct WhitelabelShardManager<T: EventForwarder> {
+type WhitelabelShard<T, C> = Shard<T, WhitelabelMode, C>;
+
+/// How long a stopped bot is given to disconnect before its run loop is aborted
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// If the leases can't be renewed, the bots are stopped this long, plus STOP_TIMEOUT, before the
+/// leases would expire, allowing for clock drift between the sharder and the database
+const LEASE_EXPIRY_MARGIN: Duration = Duration::from_secs(5);
+
+const NOT_RUNNING: &str = "bot is not running on this sharder";
+
+struct Bot<T: EventForwarder, C: Cache> {
+    shard: Arc<WhitelabelShard<T, C>>,
+    // the presence rotated through by the shard
+    presence_tx: watch::Sender<Arc<Presence>>,
+    // reconnects the shard until it's removed from the map
+    run_loop: JoinHandle<()>,
+}
+
+pub struct WhitelabelShardManager<T: EventForwarder, C: Cache> {
     config: Arc<Config>,
-    shards: RwLock<HashMap<Snowflake, Arc<Shard<T>>>>,
-    // user_id -> bot_id
-    user_ids: RwLock<HashMap<Snowflake, Snowflake>>,
+    bots: RwLock<HashMap<Snowflake, Bot<T, C>>>,
+    // bots this sharder holds the lease for, which may have been stopped through the control
+    // channel. Held while leases are claimed or released.
+    leases: Mutex<HashSet<Snowflake>>,
+    // when the last successful renewal was started, the leases expiring a TTL after
+    renewed_at: RwLock<Instant>,
+    // sharders starting together haven't all heartbeated until a TTL has passed, so until then
+    // the first would count itself as the only live sharder and claim every bot
+    claim_after: Instant,
+    shutting_down: AtomicBool,
     database: Arc<Database>,
-    cache: Arc<PostgresCache>,
+    cache: Arc<C>,
     redis: Arc<Pool>,
     event_forwarder: Arc<T>,
+    http_client: reqwest::Client,
+    recorder: Option<Arc<TrafficRecorder>>,
 }
 
-impl<T: EventForwarder> WhitelabelShardManager<T> {
+impl<T: EventForwarder, C: Cache> WhitelabelShardManager<T, C> {
     pub fn new(
         config: Config,
         database: Arc<Database>,
-        cache: Arc<PostgresCache>,
+        cache: Arc<C>,
         redis: Arc<Pool>,
         event_forwarder: Arc<T>,
     ) -> Self {
+        let claim_after = Instant::now() + Duration::from_millis(config.whitelabel_lease_ttl);
+
         WhitelabelShardManager {
+            recorder: TrafficRecorder::from_config(&config).map(Arc::new),
             config: Arc::new(config),
-            shards: RwLock::new(HashMap::new()),
-            user_ids: RwLock::new(HashMap::new()),
+            bots: RwLock::new(HashMap::new()),
+            leases: Mutex::new(HashSet::new()),
+            renewed_at: RwLock::new(Instant::now()),
+            claim_after,
+            shutting_down: AtomicBool::new(false),
             database,
             cache,
             redis,
             event_forwarder,
+            http_client: reqwest::Client::new(),
         }
     }
 
-    async fn connect_bot(self: Arc<Self>, bot: WhitelabelBot) {
-        self.user_ids
-            .write()
-            .await
-            .insert(Snowflake(bot.user_id as u64), Snowflake(bot.bot_id as u64));
-
+    /// Registers a shard for the bot and connects it in the background, replacing any shard that
+    /// was already running for the bot
+    async fn start_bot(self: &Arc<Self>, bot: WhitelabelBot) {
         let bot_id = Snowflake(bot.bot_id as u64);
+        let presence = self.get_presence(bot_id).await;
 
-        tokio::spawn(async move {
-            // retrieve bot status
-            let status = match self.database.whitelabel_status.get(bot_id).await {
-                Ok(status) => Some(status),
-                Err(database::sqlx::Error::RowNotFound) => None,
+        // retrieve forwarded events, falling back to the sharder-wide set
+        let event_whitelist = match self.database.whitelabel_forwarded_events.get(bot_id).await {
+            Ok(events) if events.is_empty() => None,
+            Ok(events) => match EventWhitelist::new(&events) {
+                Ok(whitelist) => Some(whitelist),
                 Err(e) => {
-                    eprintln!(
-                        "Error occurred while retrieving status for {}: {:?}",
-                        bot.bot_id, e
-                    );
+                    eprintln!("Invalid forwarded events for {}: {}", bot.bot_id, e);
                     None
                 }
+            },
+            Err(e) => {
+                eprintln!(
+                    "Error occurred while retrieving forwarded events for {}: {:?}",
+                    bot.bot_id, e
+                );
+                None
             }
-            .unwrap_or_else(|| "/help".to_owned());
-
-            let shard_info = ShardInfo::new(0, 1);
-            let presence = StatusUpdate::new(ActivityType::Listening, status, StatusType::Online);
-            let identify = Identify::new(
+        }
+        .unwrap_or_else(|| self.config.forward_events.clone());
+
+        // retrieve intents, falling back to the sharder-wide set
+        let intents = match self.database.whitelabel_intents.get(bot_id).await {
+            Ok(intents) => intents.map(IntentSet::new),
+            Err(e) => {
+                eprintln!(
+                    "Error occurred while retrieving intents for {}: {:?}",
+                    bot.bot_id, e
+                );
+                None
+            }
+        }
+        .unwrap_or(self.config.intents);
+
+        super::check_intents(bot_id, intents, &event_whitelist);
+
+        let shard_info = ShardInfo::new(0, 1);
+        let initial_presence = presence.initial(&shard_info);
+        let identify = Identify::new(
+            bot.token.clone(),
+            None,
+            shard_info,
+            initial_presence,
+            intents.bits(),
+        );
+
+        let services = ShardServices {
+            cache: Arc::clone(&self.cache),
+            redis: Arc::clone(&self.redis),
+            event_forwarder: Arc::clone(&self.event_forwarder),
+            event_whitelist: Arc::new(event_whitelist),
+            session_start_limiter: Arc::new(SessionStartLimiter::new(
+                self.http_client.clone(),
+                &self.config,
                 bot.token.clone(),
                 None,
-                shard_info,
-                Some(presence),
-                super::get_intents(),
-            );
+            )),
+            // whitelabel bots are never resharded
+            forwarding_gate: Arc::new(ForwardingGate::new(1)),
+            recorder: self.recorder.clone(),
+        };
+
+        let shard = Shard::new(
+            self.config.clone(),
+            identify,
+            1,
+            bot_id,
+            services,
+            WhitelabelMode::new(Arc::clone(&self.database)),
+        );
+
+        let (presence_tx, presence_rx) = watch::channel(Arc::new(presence));
+        tokio::spawn(rotate_presence(Arc::downgrade(&shard), presence_rx));
+
+        // the run loop checks that its shard is still registered before each connect, so the
+        // lock is held until the shard has been inserted
+        let mut bots = self.bots.write().await;
+        let run_loop = tokio::spawn(Arc::clone(self).run_bot(bot, Arc::clone(&shard)));
+        let replaced = bots.insert(
+            bot_id,
+            Bot {
+                shard,
+                presence_tx,
+                run_loop,
+            },
+        );
+        drop(bots);
+
+        if let Some(replaced) = replaced {
+            replaced.shard.log("Replaced by a new shard, stopping");
+            replaced.shard.kill();
+        }
+    }
 
-            let shard = Shard::new(
-                self.config.clone(),
-                identify,
-                1,
-                Arc::clone(&self.cache),
-                Arc::clone(&self.redis),
-                bot_id,
-                Arc::clone(&self.event_forwarder),
-                #[cfg(feature = "whitelabel")]
-                Arc::clone(&self.database),
-            );
+    async fn run_bot(self: Arc<Self>, bot: WhitelabelBot, shard: Arc<WhitelabelShard<T, C>>) {
+        let bot_id = Snowflake(bot.bot_id as u64);
+
+        loop {
+            // the bot has been stopped, or restarted with a new shard
+            if !self.is_current(bot_id, &shard).await {
+                shard.log("Shard was removed, not restarting");
+                break;
+            }
 
-            self.shards.write().await.insert(bot_id, Arc::clone(&shard));
+            shard.log("Starting...");
+
+            let res = Arc::clone(&shard).connect(None).await;
+            match res {
+                Ok(()) => shard.log("Exited with Ok"),
+                Err(GatewayError::AuthenticationError { error, .. }) => {
+                    shard.log_err(
+                        "Exited with authentication error, removing ",
+                        &GatewayError::custom(&error),
+                    );
 
-            loop {
-                let shard = Arc::clone(&shard);
-                shard.log("Starting...");
+                    self.remove_if_current(bot_id, &shard).await;
 
-                let res = Arc::clone(&shard).connect(None).await;
-                match res {
-                    Ok(()) => shard.log("Exited with Ok"),
-                    Err(GatewayError::AuthenticationError { error, .. }) => {
+                    if let Err(e) = self
+                        .database
+                        .whitelabel_errors
+                        .append(Snowflake(bot.user_id as u64), error)
+                        .await
+                    {
                         shard.log_err(
-                            "Exited with authentication error, removing ",
-                            &GatewayError::custom(&error),
+                            "Error occurred while recording error to database",
+                            &GatewayError::DatabaseError(e),
                         );
+                    }
 
-                        let bot_id = Snowflake(bot.bot_id as u64);
-                        self.shards.write().await.remove(&bot_id);
-
-                        let user_id = Snowflake(bot.user_id as u64);
-                        self.user_ids.write().await.remove(&user_id);
-
-                        if let Err(e) = self
-                            .database
-                            .whitelabel_errors
-                            .append(Snowflake(bot.user_id as u64), error)
-                            .await
-                        {
-                            shard.log_err(
-                                "Error occurred while recording error to database",
-                                &GatewayError::DatabaseError(e),
-                            );
-                        }
+                    self.delete_from_db(&bot.token).await;
+                }
+                Err(e) => shard.log_err("Exited with error", &e),
+            }
 
-                        self.delete_from_db(&bot.token).await;
-                    }
-                    Err(e) => shard.log_err("Exited with error", &e),
+            if shard.is_shutting_down() {
+                shard.log("Shut down, not restarting");
+                break;
+            }
+
+            sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    async fn is_current(&self, bot_id: Snowflake, shard: &Arc<WhitelabelShard<T, C>>) -> bool {
+        match self.bots.read().await.get(&bot_id) {
+            Some(bot) => Arc::ptr_eq(&bot.shard, shard),
+            None => false,
+        }
+    }
+
+    async fn remove_if_current(&self, bot_id: Snowflake, shard: &Arc<WhitelabelShard<T, C>>) {
+        let mut bots = self.bots.write().await;
+        if matches!(bots.get(&bot_id), Some(bot) if Arc::ptr_eq(&bot.shard, shard)) {
+            bots.remove(&bot_id);
+        }
+    }
+
+    /// Disconnects the bot and waits for its run loop to exit. If forget_session is set, the bot
+    /// identifies afresh when it's next started, rather than resuming.
+    async fn stop_bot(&self, bot_id: Snowflake, forget_session: bool) -> Result<(), String> {
+        let Bot {
+            shard,
+            mut run_loop,
+            ..
+        } = self
+            .bots
+            .write()
+            .await
+            .remove(&bot_id)
+            .ok_or_else(|| NOT_RUNNING.to_owned())?;
+
+        shard.log("Stopping");
+        Arc::clone(&shard).kill();
+
+        // the kill is missed if it arrives while the shard is still connecting
+        if timeout(STOP_TIMEOUT, &mut run_loop).await.is_err() {
+            shard.log("Didn't disconnect in time, aborting");
+            run_loop.abort();
+        }
+
+        if forget_session {
+            shard.invalidate_session().await;
+        }
+
+        Ok(())
+    }
+
+    async fn start_from_db(self: &Arc<Self>, bot_id: Snowflake) -> Result<(), String> {
+        match self.database.whitelabel.get_bot_by_id(bot_id).await {
+            Ok(Some(bot)) => {
+                self.start_bot(bot).await;
+                Ok(())
+            }
+            Ok(None) => Err("bot not found in database".to_owned()),
+            Err(e) => Err(format!("error retrieving bot from database: {}", e)),
+        }
+    }
+
+    async fn clear_stopped(&self, bot_id: Snowflake) -> Result<(), String> {
+        self.database
+            .whitelabel_stopped
+            .delete(bot_id)
+            .await
+            .map_err(|e| format!("error clearing stop in database: {}", e))
+    }
+
+    async fn handle_command(
+        self: &Arc<Self>,
+        bot_id: Snowflake,
+        command: Command,
+    ) -> Result<(), String> {
+        let running = self.bots.read().await.contains_key(&bot_id);
+
+        match command {
+            Command::Start if running => Ok(()),
+            Command::Start => {
+                self.clear_stopped(bot_id).await?;
+                self.start_from_db(bot_id).await
+            }
+            Command::Stop => {
+                self.stop_bot(bot_id, true).await?;
+
+                // so that the bot isn't started again after a restart, or by another sharder
+                self.database
+                    .whitelabel_stopped
+                    .insert(bot_id)
+                    .await
+                    .map_err(|e| format!("error recording stop in database: {}", e))
+            }
+            // intents are sent with the identify, so the bot has to reconnect for them to apply
+            Command::Restart | Command::ReloadIntents => {
+                if command == Command::Restart {
+                    self.clear_stopped(bot_id).await?;
                 }
 
-                // we've received delete payload
-                if self.shards.read().await.get(&bot_id).is_none() {
-                    shard.log("Shard was removed from shard vec, not restarting");
-                    break;
-                } else {
-                    shard.log("Shard still exists, restarting");
+                if running {
+                    self.stop_bot(bot_id, true).await?;
+                } else if command == Command::ReloadIntents {
+                    return Err(NOT_RUNNING.to_owned());
                 }
 
-                sleep(Duration::from_millis(500)).await;
+                self.start_from_db(bot_id).await
             }
-        });
+            Command::ReloadStatus => {
+                let presence = self.get_presence(bot_id).await;
+
+                // the shard's presence rotation pushes the new presence straight away
+                match self.bots.read().await.get(&bot_id) {
+                    Some(bot) => bot
+                        .presence_tx
+                        .send(Arc::new(presence))
+                        .map_err(|_| "presence rotation has stopped".to_owned()),
+                    None => Err(NOT_RUNNING.to_owned()),
+                }
+            }
+            Command::Ping if running => Ok(()),
+            Command::Ping => Err(NOT_RUNNING.to_owned()),
+        }
     }
 
-    async fn delete_from_db(&self, token: &str) {
-        if let Err(e) = self.database.whitelabel.delete_by_token(token).await {
-            eprintln!("Error removing bot: {}", e);
+    fn lease_ttl(&self) -> Duration {
+        Duration::from_millis(self.config.whitelabel_lease_ttl)
+    }
+
+    /// Renews the sharder's leases, then claims or releases bots so that they're spread evenly
+    /// between the live sharders. Bots whose leases were lost are stopped, and bots still leased
+    /// from before the sharder restarted are started again, unless they were stopped through the
+    /// control channel. No bots are claimed until a TTL after the sharder started.
+    async fn rebalance(self: &Arc<Self>) -> Result<(), GatewayError> {
+        let leases_table = &self.database.whitelabel_leases;
+        let sharder_id = self.config.sharder_id;
+        let ttl = self.lease_ttl();
+
+        let mut leases = self.leases.lock().await;
+
+        leases_table.heartbeat(sharder_id, ttl).await?;
+
+        // the database sets the expiry after the query is sent, so this is never later than it
+        let renewing_at = Instant::now();
+        let renewed = leases_table.renew(sharder_id, ttl).await?;
+        *self.renewed_at.write().await = renewing_at;
+
+        let held: HashSet<Snowflake> = renewed.iter().map(|lease| lease.bot_id).collect();
+
+        // claimed by another sharder after the lease expired, or the bot was deleted
+        let lost: Vec<Snowflake> = leases.difference(&held).copied().collect();
+        let regained: Vec<Snowflake> = renewed
+            .iter()
+            .filter(|lease| !leases.contains(&lease.bot_id) && !lease.stopped)
+            .map(|lease| lease.bot_id)
+            .collect();
+        *leases = held;
+
+        // the bot may have been stopped already, and the session now belongs to the new holder
+        futures::future::join_all(lost.iter().map(|&bot_id| self.stop_bot(bot_id, false))).await;
+        futures::future::join_all(regained.iter().map(|&bot_id| async move {
+            if let Err(e) = self.start_from_db(bot_id).await {
+                error!("Error starting {} after restart: {}", bot_id, e);
+            }
+        }))
+        .await;
+
+        // stopped bots aren't run by any sharder, so they aren't spread between them
+        let target = super::lease_target(
+            leases_table.count_runnable().await?,
+            leases_table.count_live_sharders().await?,
+            self.config.whitelabel_capacity,
+        );
+
+        if leases.len() > target {
+            let released: Vec<Snowflake> =
+                leases.iter().take(leases.len() - target).copied().collect();
+
+            // stopped before the leases are given up, so that a bot is never connected twice. The
+            // bots are forgotten even if releasing fails: they're restarted if their leases are
+            // renewed.
+            futures::future::join_all(released.iter().map(|&bot_id| self.stop_bot(bot_id, false)))
+                .await;
+            for bot_id in &released {
+                leases.remove(bot_id);
+            }
+
+            leases_table.release(sharder_id, &released).await?;
+            info!("Released {} bots to other sharders", released.len());
+        } else if leases.len() < target && Instant::now() >= self.claim_after {
+            let claimed = leases_table
+                .claim(sharder_id, ttl, target - leases.len())
+                .await?;
+
+            if !claimed.is_empty() {
+                info!("Claimed {} bots", claimed.len());
+            }
+
+            leases.extend(claimed.iter().map(|bot| Snowflake(bot.bot_id as u64)));
+            futures::future::join_all(claimed.into_iter().map(|bot| self.start_bot(bot))).await;
         }
+
+        Ok(())
     }
 
-    pub async fn listen_status_updates(self: Arc<Self>) -> Result<(), GatewayError> {
-        let database = Arc::clone(&self.database);
+    /// Rebalances a few times per lease TTL. If the leases can't be renewed, every bot is stopped
+    /// in time for it to have disconnected before the leases expire, as other sharders may then
+    /// claim them.
+    async fn maintain_leases(self: Arc<Self>) {
+        let ttl = self.lease_ttl();
+        let stop_after = ttl.saturating_sub(STOP_TIMEOUT + LEASE_EXPIRY_MARGIN);
 
-        let mut conn = redis::Client::open(self.config.get_redis_uri())
-            .unwrap()
-            .get_async_connection()
-            .await?
-            .into_pubsub();
+        loop {
+            sleep(self.until_lease_deadline(ttl / 3, stop_after).await).await;
 
-        conn.subscribe(common::status_updates::KEY).await?;
+            if self.shutting_down.load(Ordering::Relaxed) {
+                break;
+            }
 
-        tokio::spawn(async move {
-            let mut stream = conn.on_message();
+            // a query stuck waiting on the database mustn't hold up stopping the bots. The
+            // rebalance is safe to cancel: leases it misses are picked up by the next renewal.
+            let limit = self.until_lease_deadline(ttl / 3, stop_after).await;
+            match timeout(limit, self.rebalance()).await {
+                Ok(Ok(())) => continue,
+                Ok(Err(e)) => error!("Error occurred while renewing whitelabel leases: {}", e),
+                Err(_) => error!("Timed out renewing whitelabel leases"),
+            }
 
-            while let Some(m) = stream.next().await {
-                match m.get_payload::<String>().map(|s| s.parse::<Snowflake>()) {
-                    Ok(Ok(bot_id)) => {
-                        if let Some(shard) = self.shards.read().await.get(&bot_id) {
-                            shard.log("Received status update payload");
-
-                            // retrieve new status
-                            // TODO: New tokio::spawn for this?
-                            match database.whitelabel_status.get(bot_id).await {
-                                Ok(status) => {
-                                    if let Err(e) = shard
-                                        .status_update_tx
-                                        .clone()
-                                        .send(StatusUpdate::new(
-                                            ActivityType::Listening,
-                                            status,
-                                            StatusType::Online,
-                                        ))
-                                        .await
-                                    {
-                                        eprintln!(
-                                            "An error occured while updating status for {}: {}",
-                                            bot_id, e
-                                        );
-                                    }
-                                }
-                                Err(e) => eprintln!("Error retrieving status from db: {}", e),
-                            }
-                        }
-                    }
-                    Ok(Err(e)) => eprintln!("An error occured while reading status updates: {}", e),
-                    Err(e) => eprintln!("An error occured while reading status updates: {}", e),
+            if self.renewed_at.read().await.elapsed() >= stop_after {
+                // anything still held is started again once the leases are renewed
+                let mut leases = self.leases.lock().await;
+                if !leases.is_empty() {
+                    warn!("Leases are expiring, stopping {} bots", leases.len());
+                    futures::future::join_all(
+                        leases.iter().map(|&bot_id| self.stop_bot(bot_id, false)),
+                    )
+                    .await;
+                    leases.clear();
                 }
             }
-        });
+        }
+    }
+
+    /// The given duration, cut short if the bots would have to be stopped before it has elapsed
+    async fn until_lease_deadline(&self, duration: Duration, stop_after: Duration) -> Duration {
+        // once the bots have been stopped, there's no deadline to keep
+        if self.bots.read().await.is_empty() {
+            return duration;
+        }
+
+        let deadline = *self.renewed_at.read().await + stop_after;
+        duration.min(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether the sharder holds the bot's lease. Commands that start the bot claim its lease if
+    /// no live sharder holds it, and the sharder has capacity for it.
+    async fn hold_lease(&self, bot_id: Snowflake, command: Command) -> bool {
+        let mut leases = self.leases.lock().await;
+        if leases.contains(&bot_id) {
+            return true;
+        }
+
+        if !matches!(command, Command::Start | Command::Restart) {
+            return false;
+        }
+
+        if let Some(capacity) = self.config.whitelabel_capacity {
+            if leases.len() >= capacity {
+                return false;
+            }
+        }
+
+        // the leases are locked, so this mustn't hold up stopping the bots if they expire
+        let claim = self.database.whitelabel_leases.claim_bot(
+            self.config.sharder_id,
+            self.lease_ttl(),
+            bot_id,
+        );
+
+        match timeout(self.lease_ttl() / 3, claim).await {
+            Ok(Ok(true)) => {
+                leases.insert(bot_id);
+                true
+            }
+            Ok(Ok(false)) => false,
+            Ok(Err(e)) => {
+                error!("Error occurred while claiming lease for {}: {}", bot_id, e);
+                false
+            }
+            Err(_) => {
+                warn!("Timed out claiming lease for {}", bot_id);
+                false
+            }
+        }
+    }
+
+    async fn publish_ack(&self, ack: &whitelabel_control::Ack) -> Result<(), GatewayError> {
+        let mut conn = self.redis.get().await?;
+
+        cmd("PUBLISH")
+            .arg(whitelabel_control::ACK_KEY)
+            .arg(serde_json::to_string(ack)?)
+            .query_async::<i64>(&mut conn)
+            .await?;
 
         Ok(())
     }
 
-    pub async fn listen_new_tokens(self: Arc<Self>) -> Result<(), GatewayError> {
+    /// Falls back to the sharder-wide presence if the bot hasn't set one
+    async fn get_presence(&self, bot_id: Snowflake) -> Presence {
+        let mut presence = Presence::from_config(&self.config);
+
+        match self.database.whitelabel_status.get_presence(bot_id).await {
+            Ok(Some(row)) => {
+                match ActivityType::try_from(row.activity_type as u64) {
+                    Ok(activity_type) => presence.activity_type = activity_type,
+                    Err(e) => eprintln!("Invalid activity type for {}: {}", bot_id, e),
+                }
+
+                match row.status_type.parse() {
+                    Ok(status_type) => presence.status_type = status_type,
+                    Err(e) => eprintln!("Invalid status type for {}: {}", bot_id, e),
+                }
+
+                presence.messages = iter::once(row.status)
+                    .chain(row.rotating_statuses)
+                    .collect();
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!(
+                "Error occurred while retrieving status for {}: {:?}",
+                bot_id, e
+            ),
+        }
+
+        presence
+    }
+
+    async fn delete_from_db(&self, token: &str) {
+        if let Err(e) = self.database.whitelabel.delete_by_token(token).await {
+            eprintln!("Error removing bot: {}", e);
+        }
+    }
+
+    /// Runs the command for a bot this sharder holds the lease for, and publishes an
+    /// acknowledgement once it has taken effect, or failed
+    pub async fn handle_request(self: &Arc<Self>, request: &whitelabel_control::Request) {
+        let result = self.handle_command(request.bot_id, request.command).await;
+        if let Err(e) = &result {
+            error!(
+                "Control command {:?} ({}) failed for {}: {}",
+                request.command, request.request_id, request.bot_id, e
+            );
+        }
+
+        let ack = whitelabel_control::Ack::new(request, self.config.sharder_id, result);
+        if let Err(e) = self.publish_ack(&ack).await {
+            // whitelabel bots run a single shard
+            METRICS
+                .control_ack_failures
+                .inc(ShardLabels::new(request.bot_id, 0));
+            error!(
+                "An error occurred while acknowledging control request {}: {}",
+                request.request_id, e
+            );
+        }
+    }
+
+    /// Handles the commands published to the whitelabel control channel for bots run by this
+    /// sharder, one at a time, and publishes an acknowledgement once each has taken effect
+    pub async fn listen_control(self: Arc<Self>) -> Result<(), GatewayError> {
         let mut conn = redis::Client::open(self.config.get_redis_uri())
             .unwrap()
             .get_async_connection()
             .await?
             .into_pubsub();
 
-        conn.subscribe(common::token_change::KEY).await?;
+        conn.subscribe(whitelabel_control::KEY).await?;
 
         tokio::spawn(async move {
             let mut stream = conn.on_message();
 
             while let Some(m) = stream.next().await {
-                let manager = Arc::clone(&self);
-
-                match serde_json::from_slice::<token_change::Payload>(m.get_payload_bytes()) {
-                    Ok(payload) => {
-                        // check whether this shard has the old bot
-                        if payload.old_id.0 % (manager.config.sharder_total as u64)
-                            == manager.config.sharder_id as u64
-                        {
-                            if let Some(shard) = self.shards.write().await.remove(&payload.old_id) {
-                                shard.log("Received token update payload, stopping");
-                                shard.kill();
-                            }
-                        }
-
-                        // start new bot
-                        if payload.new_id.0 % (manager.config.sharder_total as u64)
-                            == manager.config.sharder_id as u64
-                        {
-                            match self.database.whitelabel.get_bot_by_id(payload.new_id).await {
-                                Ok(Some(bot)) => {
-                                    manager.connect_bot(bot).await;
-                                }
-                                Ok(None) => {
-                                    eprintln!("Couldn't find row for bot {}", payload.new_id)
-                                }
-                                Err(e) => eprintln!("Error retrieving bot from DB: {}", e),
-                            }
-                        }
-                    }
+                let request = match serde_json::from_slice::<whitelabel_control::Request>(
+                    m.get_payload_bytes(),
+                ) {
+                    Ok(request) => request,
                     Err(e) => {
-                        eprintln!("An error occurred while decoding new token payload: {}", e)
+                        error!("An error occurred while decoding control payload: {}", e);
+                        continue;
                     }
+                };
+
+                // bots leased by other sharders are acknowledged by them
+                if !self.hold_lease(request.bot_id, request.command).await {
+                    continue;
                 }
+
+                self.handle_request(&request).await;
             }
         });
 
         Ok(())
     }
 
-    pub async fn listen_delete(self: Arc<Self>) -> Result<(), GatewayError> {
+    pub async fn listen_request_guild_members(self: Arc<Self>) -> Result<(), GatewayError> {
         let mut conn = redis::Client::open(self.config.get_redis_uri())
             .unwrap()
             .get_async_connection()
             .await?
             .into_pubsub();
 
-        conn.subscribe(common::status_updates::KEY).await?;
+        conn.subscribe(request_guild_members::KEY).await?;
 
         tokio::spawn(async move {
             let mut stream = conn.on_message();
 
             while let Some(m) = stream.next().await {
-                match m.get_payload::<String>().map(|s| s.parse::<Snowflake>()) {
-                    Ok(Ok(user_id)) => {
-                        if let Some(bot_id) = self.user_ids.write().await.remove(&user_id) {
-                            if let Some(shard) = self.shards.write().await.remove(&bot_id) {
-                                shard.log("Received delete payload, stopping");
-                                shard.kill();
-                            }
+                match serde_json::from_slice::<request_guild_members::Payload>(
+                    m.get_payload_bytes(),
+                ) {
+                    Ok(payload) => {
+                        // bots run by other sharders won't be present
+                        if let Some(bot) = self.bots.read().await.get(&payload.bot_id) {
+                            super::request_guild_members(Arc::clone(&bot.shard), payload);
                         }
                     }
-                    Ok(Err(e)) => {
-                        eprintln!("An error occurred while reading delete payload: {}", e)
-                    }
-                    Err(e) => eprintln!("An error occurred while reading delete payload: {}", e),
+                    Err(e) => error!(
+                        "An error occurred while decoding request guild members payload: {}",
+                        e
+                    ),
                 }
             }
         });
@@ -292,19 +675,37 @@ impl<T: EventForwarder> WhitelabelShardManager<T> {
 }
 
 #[async_trait]
-#[cfg(feature = "whitelabel")]
-impl<T: EventForwarder> ShardManager for WhitelabelShardManager<T> {
+impl<T: EventForwarder, C: Cache> ShardManager for WhitelabelShardManager<T, C> {
+    type Forwarder = T;
+    type Mode = WhitelabelMode;
+    type Cache = C;
+
     async fn connect(self: Arc<Self>) {
         // we should panic if we cant read db
-        let bots = self
-            .database
-            .whitelabel
-            .get_bots_by_sharder(self.config.sharder_total, self.config.sharder_id)
-            .await
-            .unwrap();
+        self.rebalance().await.unwrap();
+        tokio::spawn(Arc::clone(&self).maintain_leases());
+    }
 
-        for bot in bots {
-            Arc::clone(&self).connect_bot(bot).await;
-        }
+    async fn shutdown(self: Arc<Self>) {
+        // leases are kept, so that the bots are resumed here after a restart, or claimed by
+        // other sharders once they expire
+        self.shutting_down.store(true, Ordering::Relaxed);
+
+        let shards = self.shards().await;
+        futures::future::join_all(shards.iter().map(|shard| shard.shutdown())).await;
+
+        // events still in flight are being retried, so dead-letter them rather than losing them
+        // when the process exits
+        self.event_forwarder.stop_retrying();
+        futures::future::join_all(shards.iter().map(|shard| shard.wait_for_in_flight())).await;
+    }
+
+    async fn shards(&self) -> Vec<Arc<Shard<T, WhitelabelMode, C>>> {
+        self.bots
+            .read()
+            .await
+            .values()
+            .map(|bot| Arc::clone(&bot.shard))
+            .collect()
     }
 }
@@ -0,0 +1,68 @@
+use super::ShardLabels;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// A counter per shard, optionally split further by the value of a second label
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    label: Option<&'static str>,
+    values: Mutex<BTreeMap<(ShardLabels, Cow<'static, str>), u64>>,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str, help: &'static str) -> Counter {
+        Counter {
+            name,
+            help,
+            label: None,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub const fn with_label(
+        name: &'static str,
+        help: &'static str,
+        label: &'static str,
+    ) -> Counter {
+        Counter {
+            name,
+            help,
+            label: Some(label),
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, shard: ShardLabels) {
+        self.inc_with(shard, "");
+    }
+
+    pub fn inc_with(&self, shard: ShardLabels, value: impl Into<Cow<'static, str>>) {
+        let mut values = self.values.lock().unwrap();
+        *values.entry((shard, value.into())).or_insert(0) += 1;
+    }
+
+    pub fn get(&self, shard: ShardLabels, value: &'static str) -> u64 {
+        let values = self.values.lock().unwrap();
+        values
+            .get(&(shard, Cow::Borrowed(value)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} counter", self.name);
+
+        for ((shard, value), count) in self.values.lock().unwrap().iter() {
+            let _ = write!(out, "{}{{", self.name);
+            shard.render(out);
+            if let Some(label) = self.label {
+                let _ = write!(out, ",{}=\"{}\"", label, value);
+            }
+            let _ = writeln!(out, "}} {}", count);
+        }
+    }
+}
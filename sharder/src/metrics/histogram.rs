@@ -0,0 +1,84 @@
+use super::ShardLabels;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A histogram of durations per shard, in seconds
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    buckets: &'static [f64],
+    values: Mutex<BTreeMap<ShardLabels, Observations>>,
+}
+
+struct Observations {
+    // per bucket, not cumulative
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub const fn new(name: &'static str, help: &'static str, buckets: &'static [f64]) -> Histogram {
+        Histogram {
+            name,
+            help,
+            buckets,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, shard: ShardLabels, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+
+        let mut values = self.values.lock().unwrap();
+        let observations = values.entry(shard).or_insert_with(|| Observations {
+            counts: vec![0; self.buckets.len()],
+            sum: 0.0,
+            count: 0,
+        });
+
+        if let Some(bucket) = self.buckets.iter().position(|&le| seconds <= le) {
+            observations.counts[bucket] += 1;
+        }
+
+        observations.sum += seconds;
+        observations.count += 1;
+    }
+
+    pub fn count(&self, shard: ShardLabels) -> u64 {
+        let values = self.values.lock().unwrap();
+        values
+            .get(&shard)
+            .map_or(0, |observations| observations.count)
+    }
+
+    pub(crate) fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} histogram", self.name);
+
+        for (shard, observations) in self.values.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (le, count) in self.buckets.iter().zip(observations.counts.iter()) {
+                cumulative += count;
+
+                let _ = write!(out, "{}_bucket{{", self.name);
+                shard.render(out);
+                let _ = writeln!(out, ",le=\"{}\"}} {}", le, cumulative);
+            }
+
+            let _ = write!(out, "{}_bucket{{", self.name);
+            shard.render(out);
+            let _ = writeln!(out, ",le=\"+Inf\"}} {}", observations.count);
+
+            let _ = write!(out, "{}_sum{{", self.name);
+            shard.render(out);
+            let _ = writeln!(out, "}} {}", observations.sum);
+
+            let _ = write!(out, "{}_count{{", self.name);
+            shard.render(out);
+            let _ = writeln!(out, "}} {}", observations.count);
+        }
+    }
+}
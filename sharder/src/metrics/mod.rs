@@ -0,0 +1,175 @@
+mod counter;
+pub use counter::Counter;
+
+mod histogram;
+pub use histogram::Histogram;
+
+use crate::gateway::{ConnectionState, ShardStatus};
+use model::Snowflake;
+use std::fmt::Write;
+
+/// Metrics for every shard in the process, served in the Prometheus text format by the admin API
+pub static METRICS: Metrics = Metrics::new();
+
+const HEARTBEAT_RTT_BUCKETS: &[f64] = &[0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+const IDENTIFY_WAIT_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ShardLabels {
+    pub bot_id: Snowflake,
+    pub shard_id: u16,
+}
+
+impl ShardLabels {
+    pub fn new(bot_id: Snowflake, shard_id: u16) -> ShardLabels {
+        ShardLabels { bot_id, shard_id }
+    }
+
+    fn render(&self, out: &mut String) {
+        let _ = write!(
+            out,
+            "bot_id=\"{}\",shard_id=\"{}\"",
+            self.bot_id, self.shard_id
+        );
+    }
+}
+
+pub struct Metrics {
+    pub events_received: Counter,
+    pub events_forwarded: Counter,
+    pub event_forward_failures: Counter,
+    pub worker_request_failures: Counter,
+    pub cache_errors: Counter,
+    pub reconnects: Counter,
+    pub identifies: Counter,
+    pub resumes: Counter,
+    pub decompression_errors: Counter,
+    pub recorded_payloads_dropped: Counter,
+    pub control_ack_failures: Counter,
+    pub heartbeat_rtt: Histogram,
+    pub identify_ratelimit_wait: Histogram,
+}
+
+impl Metrics {
+    const fn new() -> Metrics {
+        Metrics {
+            events_received: Counter::with_label(
+                "sharder_events_received_total",
+                "Dispatches received from the gateway",
+                "event",
+            ),
+            events_forwarded: Counter::new(
+                "sharder_events_forwarded_total",
+                "Events forwarded to the workers",
+            ),
+            event_forward_failures: Counter::new(
+                "sharder_event_forward_failures_total",
+                "Events that could not be forwarded to the workers",
+            ),
+            worker_request_failures: Counter::new(
+                "sharder_worker_request_failures_total",
+                "Failed HTTP requests to workers, including those that were retried",
+            ),
+            cache_errors: Counter::new(
+                "sharder_cache_errors_total",
+                "Errors writing events to the cache",
+            ),
+            reconnects: Counter::with_label(
+                "sharder_reconnects_total",
+                "Connections dropped by the shard or the gateway",
+                "reason",
+            ),
+            identifies: Counter::new("sharder_identifies_total", "Identify payloads sent"),
+            resumes: Counter::new("sharder_resumes_total", "Resume payloads sent"),
+            decompression_errors: Counter::new(
+                "sharder_decompression_errors_total",
+                "Payloads that could not be decompressed",
+            ),
+            recorded_payloads_dropped: Counter::new(
+                "sharder_recorded_payloads_dropped_total",
+                "Payloads left out of the traffic recording because the writer fell behind",
+            ),
+            control_ack_failures: Counter::new(
+                "sharder_control_ack_failures_total",
+                "Whitelabel control acknowledgements that could not be published",
+            ),
+            heartbeat_rtt: Histogram::new(
+                "sharder_heartbeat_rtt_seconds",
+                "Time between sending a heartbeat and receiving its ACK",
+                HEARTBEAT_RTT_BUCKETS,
+            ),
+            identify_ratelimit_wait: Histogram::new(
+                "sharder_identify_ratelimit_wait_seconds",
+                "Time spent waiting for the identify and session start ratelimits",
+                IDENTIFY_WAIT_BUCKETS,
+            ),
+        }
+    }
+
+    /// Renders every metric, plus gauges read from the current state of the given shards
+    pub fn render(&self, shards: &[ShardStatus]) -> String {
+        let mut out = String::new();
+
+        self.events_received.render(&mut out);
+        self.events_forwarded.render(&mut out);
+        self.event_forward_failures.render(&mut out);
+        self.worker_request_failures.render(&mut out);
+        self.cache_errors.render(&mut out);
+        self.reconnects.render(&mut out);
+        self.identifies.render(&mut out);
+        self.resumes.render(&mut out);
+        self.decompression_errors.render(&mut out);
+        self.recorded_payloads_dropped.render(&mut out);
+        self.control_ack_failures.render(&mut out);
+        self.heartbeat_rtt.render(&mut out);
+        self.identify_ratelimit_wait.render(&mut out);
+
+        render_gauge(
+            &mut out,
+            "sharder_shard_connected",
+            "Whether the shard has an active session on the gateway",
+            shards,
+            |status| (status.state == ConnectionState::Connected) as usize,
+        );
+        render_gauge(
+            &mut out,
+            "sharder_shard_guilds",
+            "Guilds on the shard",
+            shards,
+            |status| status.guild_count,
+        );
+        render_gauge(
+            &mut out,
+            "sharder_shard_unavailable_guilds",
+            "Guilds on the shard that Discord has reported an outage in",
+            shards,
+            |status| status.unavailable_guild_count,
+        );
+        render_gauge(
+            &mut out,
+            "sharder_outbound_queue_depth",
+            "Messages waiting for capacity under the gateway send limit",
+            shards,
+            |status| status.outbound_queue_depth,
+        );
+
+        out
+    }
+}
+
+fn render_gauge(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    shards: &[ShardStatus],
+    value: impl Fn(&ShardStatus) -> usize,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+
+    for status in shards {
+        let _ = write!(out, "{}{{", name);
+        ShardLabels::new(status.bot_id, status.shard_id).render(out);
+        let _ = writeln!(out, "}} {}", value(status));
+    }
+}
@@ -0,0 +1,31 @@
+use crate::ShardManager;
+use log::{info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::timeout;
+
+/// Resolves once SIGINT or SIGTERM is received
+pub async fn wait_for_shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res.expect("Failed to listen for ctrl_c"),
+        _ = sigterm.recv() => {}
+    }
+}
+
+/// Waits for a shutdown signal, then gracefully shuts down all shards, giving up once the deadline
+/// has passed
+pub async fn shutdown_on_signal<T: ShardManager>(sm: Arc<T>, deadline: Duration) {
+    wait_for_shutdown_signal().await;
+    info!("Received shutdown signal, shutting down shards");
+
+    match timeout(deadline, sm.shutdown()).await {
+        Ok(()) => info!("All shards shut down"),
+        Err(_) => warn!(
+            "Shards did not shut down within {:?}, exiting anyway",
+            deadline
+        ),
+    }
+}
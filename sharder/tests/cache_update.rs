@@ -0,0 +1,161 @@
+mod common;
+
+use cache::Cache;
+use common::MemoryCache;
+use model::Snowflake;
+use serde_json::value::to_raw_value;
+use serde_json::{json, Value};
+use sharder::{is_cached, update_cache, Event};
+
+fn event(name: &str, data: Value) -> Event {
+    Event::parse(name, &to_raw_value(&data).unwrap()).unwrap()
+}
+
+#[test]
+fn skips_events_that_arent_cached() {
+    assert!(is_cached("GUILD_MEMBER_ADD"));
+    assert!(is_cached("USER_UPDATE"));
+    assert!(!is_cached("TYPING_START"));
+    assert!(!is_cached("PRESENCE_UPDATE"));
+}
+
+#[tokio::test]
+async fn stores_and_removes_members() {
+    let cache = MemoryCache::default();
+    let user = json!({ "id": "2", "username": "user", "discriminator": "0001", "avatar": null });
+
+    let add = event(
+        "GUILD_MEMBER_ADD",
+        json!({
+            "guild_id": "1",
+            "user": user,
+            "roles": [],
+            "joined_at": "2021-01-01T00:00:00+00:00",
+            "deaf": false,
+            "mute": false
+        }),
+    );
+    update_cache(&cache, add).await.unwrap();
+    assert!(cache
+        .get_member(Snowflake(2), Snowflake(1))
+        .await
+        .unwrap()
+        .is_some());
+
+    let remove = event(
+        "GUILD_MEMBER_REMOVE",
+        json!({ "guild_id": "1", "user": user }),
+    );
+    update_cache(&cache, remove).await.unwrap();
+    assert!(cache
+        .get_member(Snowflake(2), Snowflake(1))
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn stores_threads_as_channels() {
+    let cache = MemoryCache::default();
+
+    let create = event(
+        "THREAD_CREATE",
+        json!({ "id": "3", "type": 11, "guild_id": "1", "name": "thread" }),
+    );
+    update_cache(&cache, create).await.unwrap();
+
+    let thread = cache.get_channel(Snowflake(3)).await.unwrap().unwrap();
+    assert_eq!(thread.name.as_deref(), Some("thread"));
+}
+
+#[tokio::test]
+async fn stores_and_removes_roles() {
+    let cache = MemoryCache::default();
+    let role = json!({
+        "id": "4",
+        "name": "role",
+        "color": 0,
+        "hoist": false,
+        "position": 0,
+        "permissions": "0",
+        "managed": false,
+        "mentionable": false
+    });
+
+    update_cache(
+        &cache,
+        event(
+            "GUILD_ROLE_CREATE",
+            json!({ "guild_id": "1", "role": role }),
+        ),
+    )
+    .await
+    .unwrap();
+    assert!(cache.has_role(Snowflake(4)));
+
+    update_cache(
+        &cache,
+        event(
+            "GUILD_ROLE_DELETE",
+            json!({ "guild_id": "1", "role_id": "4" }),
+        ),
+    )
+    .await
+    .unwrap();
+    assert!(!cache.has_role(Snowflake(4)));
+}
+
+#[tokio::test]
+async fn member_updates_keep_fields_that_werent_sent() {
+    let cache = MemoryCache::default();
+    let user = json!({ "id": "2", "username": "user", "discriminator": "0001", "avatar": null });
+
+    let add = event(
+        "GUILD_MEMBER_ADD",
+        json!({
+            "guild_id": "1",
+            "user": user,
+            "roles": [],
+            "joined_at": "2021-01-01T00:00:00+00:00",
+            "deaf": true,
+            "mute": true
+        }),
+    );
+    update_cache(&cache, add).await.unwrap();
+
+    let update = event(
+        "GUILD_MEMBER_UPDATE",
+        json!({
+            "guild_id": "1",
+            "user": user,
+            "nick": "nick",
+            "roles": ["4"],
+            "joined_at": "2021-01-01T00:00:00+00:00",
+            "mute": false
+        }),
+    );
+    update_cache(&cache, update).await.unwrap();
+
+    let member = cache
+        .get_member(Snowflake(2), Snowflake(1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(member.nick.as_deref(), Some("nick"));
+    assert_eq!(member.roles, vec![Snowflake(4)]);
+    assert!(member.deaf);
+    assert!(!member.mute);
+}
+
+#[tokio::test]
+async fn guild_updates_refresh_emojis() {
+    let cache = MemoryCache::default();
+
+    let mut guild = common::guild("1");
+    guild["emojis"] = json!([{ "id": "5", "name": "emoji", "roles": [], "animated": false }]);
+
+    update_cache(&cache, event("GUILD_UPDATE", guild))
+        .await
+        .unwrap();
+    assert!(cache.has_emoji(Snowflake(5)));
+}
@@ -0,0 +1,259 @@
+use async_trait::async_trait;
+use cache::{Cache, Result};
+use model::channel::Channel;
+use model::guild::{Emoji, Guild, Member, MemberUpdate, Role, VoiceState};
+use model::user::User;
+use model::Snowflake;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// Keeps the cache in memory, so that tests can check what was written to it. Only the IDs of
+/// guilds, roles, emojis and voice states are kept, as the models can't be cloned.
+#[derive(Default)]
+pub struct MemoryCache {
+    guilds: Mutex<HashSet<Snowflake>>,
+    unavailable_guilds: Mutex<HashSet<Snowflake>>,
+    channels: Mutex<HashMap<Snowflake, Channel>>,
+    users: Mutex<HashMap<Snowflake, User>>,
+    // (guild ID, user ID)
+    members: Mutex<HashMap<(Snowflake, Snowflake), Member>>,
+    roles: Mutex<HashSet<Snowflake>>,
+    emojis: Mutex<HashSet<Snowflake>>,
+    voice_states: Mutex<HashSet<(Snowflake, Snowflake)>>,
+}
+
+impl MemoryCache {
+    pub fn has_guild(&self, id: Snowflake) -> bool {
+        self.guilds.lock().unwrap().contains(&id)
+    }
+
+    pub fn is_unavailable(&self, id: Snowflake) -> bool {
+        self.unavailable_guilds.lock().unwrap().contains(&id)
+    }
+
+    pub fn has_role(&self, id: Snowflake) -> bool {
+        self.roles.lock().unwrap().contains(&id)
+    }
+
+    pub fn has_emoji(&self, id: Snowflake) -> bool {
+        self.emojis.lock().unwrap().contains(&id)
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn store_guild(&self, guild: Guild) -> Result<()> {
+        self.store_guilds(vec![guild]).await
+    }
+
+    async fn store_guilds(&self, guilds: Vec<Guild>) -> Result<()> {
+        for guild in guilds {
+            if let Some(channels) = guild.channels {
+                self.store_channels(channels).await?;
+            }
+
+            if let Some(members) = guild.members {
+                self.store_members(members, guild.id).await?;
+            }
+
+            self.store_roles(guild.roles, guild.id).await?;
+            self.guilds.lock().unwrap().insert(guild.id);
+            self.unavailable_guilds.lock().unwrap().remove(&guild.id);
+        }
+
+        Ok(())
+    }
+
+    async fn update_guild(&self, guild: Guild) -> Result<()> {
+        self.store_roles(guild.roles, guild.id).await?;
+        self.store_emojis(guild.emojis, guild.id).await?;
+        self.guilds.lock().unwrap().insert(guild.id);
+        Ok(())
+    }
+
+    async fn get_guild(&self, _: Snowflake) -> Result<Option<Guild>> {
+        Ok(None)
+    }
+
+    async fn delete_guild(&self, id: Snowflake) -> Result<()> {
+        self.guilds.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn mark_guild_unavailable(&self, id: Snowflake) -> Result<()> {
+        if self.guilds.lock().unwrap().contains(&id) {
+            self.unavailable_guilds.lock().unwrap().insert(id);
+        }
+
+        Ok(())
+    }
+
+    async fn get_guild_count(&self) -> Result<usize> {
+        Ok(self.guilds.lock().unwrap().len())
+    }
+
+    async fn store_channel(&self, channel: Channel) -> Result<()> {
+        self.store_channels(vec![channel]).await
+    }
+
+    async fn store_channels(&self, channels: Vec<Channel>) -> Result<()> {
+        let mut stored = self.channels.lock().unwrap();
+        for channel in channels {
+            stored.insert(channel.id, channel);
+        }
+
+        Ok(())
+    }
+
+    async fn get_channel(&self, id: Snowflake) -> Result<Option<Channel>> {
+        Ok(self.channels.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn delete_channel(&self, id: Snowflake) -> Result<()> {
+        self.channels.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn store_user(&self, user: User) -> Result<()> {
+        self.store_users(vec![user]).await
+    }
+
+    async fn store_users(&self, users: Vec<User>) -> Result<()> {
+        let mut stored = self.users.lock().unwrap();
+        for user in users {
+            stored.insert(user.id, user);
+        }
+
+        Ok(())
+    }
+
+    async fn get_user(&self, id: Snowflake) -> Result<Option<User>> {
+        Ok(self.users.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn delete_user(&self, id: Snowflake) -> Result<()> {
+        self.users.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn store_member(&self, member: Member, guild_id: Snowflake) -> Result<()> {
+        self.store_members(vec![member], guild_id).await
+    }
+
+    async fn store_members(&self, members: Vec<Member>, guild_id: Snowflake) -> Result<()> {
+        let mut stored = self.members.lock().unwrap();
+        for member in members {
+            if let Some(user) = &member.user {
+                stored.insert((guild_id, user.id), member);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_member(&self, update: MemberUpdate, guild_id: Snowflake) -> Result<()> {
+        let key = (guild_id, update.user.id);
+        let mut members = self.members.lock().unwrap();
+        let cached = members.get(&key);
+
+        let member = Member {
+            deaf: update
+                .deaf
+                .unwrap_or_else(|| cached.is_some_and(|m| m.deaf)),
+            mute: update
+                .mute
+                .unwrap_or_else(|| cached.is_some_and(|m| m.mute)),
+            nick: update.nick,
+            roles: update.roles,
+            joined_at: update.joined_at,
+            premium_since: update.premium_since,
+            user: Some(update.user),
+        };
+
+        members.insert(key, member);
+        Ok(())
+    }
+
+    async fn get_member(&self, user_id: Snowflake, guild_id: Snowflake) -> Result<Option<Member>> {
+        Ok(self
+            .members
+            .lock()
+            .unwrap()
+            .get(&(guild_id, user_id))
+            .cloned())
+    }
+
+    async fn delete_member(&self, user_id: Snowflake, guild_id: Snowflake) -> Result<()> {
+        self.members.lock().unwrap().remove(&(guild_id, user_id));
+        Ok(())
+    }
+
+    async fn store_role(&self, role: Role, guild_id: Snowflake) -> Result<()> {
+        self.store_roles(vec![role], guild_id).await
+    }
+
+    async fn store_roles(&self, roles: Vec<Role>, _: Snowflake) -> Result<()> {
+        self.roles
+            .lock()
+            .unwrap()
+            .extend(roles.into_iter().map(|role| role.id));
+        Ok(())
+    }
+
+    async fn get_role(&self, _: Snowflake) -> Result<Option<Role>> {
+        Ok(None)
+    }
+
+    async fn delete_role(&self, id: Snowflake) -> Result<()> {
+        self.roles.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn store_emoji(&self, emoji: Emoji, guild_id: Snowflake) -> Result<()> {
+        self.store_emojis(vec![emoji], guild_id).await
+    }
+
+    async fn store_emojis(&self, emojis: Vec<Emoji>, _: Snowflake) -> Result<()> {
+        self.emojis
+            .lock()
+            .unwrap()
+            .extend(emojis.into_iter().filter_map(|emoji| emoji.id));
+        Ok(())
+    }
+
+    async fn get_emoji(&self, _: Snowflake) -> Result<Option<Emoji>> {
+        Ok(None)
+    }
+
+    async fn delete_emoji(&self, id: Snowflake) -> Result<()> {
+        self.emojis.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn store_voice_state(&self, voice_state: VoiceState) -> Result<()> {
+        self.store_voice_states(vec![voice_state]).await
+    }
+
+    async fn store_voice_states(&self, voice_states: Vec<VoiceState>) -> Result<()> {
+        let mut stored = self.voice_states.lock().unwrap();
+        for voice_state in voice_states {
+            if let Some(guild_id) = voice_state.guild_id {
+                stored.insert((guild_id, voice_state.user_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_voice_state(&self, _: Snowflake, _: Snowflake) -> Result<Option<VoiceState>> {
+        Ok(None)
+    }
+
+    async fn delete_voice_state(&self, user_id: Snowflake, guild_id: Snowflake) -> Result<()> {
+        self.voice_states
+            .lock()
+            .unwrap()
+            .remove(&(guild_id, user_id));
+        Ok(())
+    }
+}
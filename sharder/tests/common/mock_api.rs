@@ -0,0 +1,121 @@
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub path: String,
+    pub authorization: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    // responses are served in order, with the last one repeated
+    responses: VecDeque<Value>,
+    requests: Vec<Request>,
+}
+
+/// A minimal HTTP server standing in for the Discord REST API, answering every request with a
+/// queued JSON body.
+pub struct MockApi {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockApi {
+    pub async fn start() -> MockApi {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind mock api");
+
+        let url = format!("http://{}/api/v9", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let accept_state = Arc::clone(&state);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, Arc::clone(&accept_state)));
+            }
+        });
+
+        MockApi { url, state }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url[..]
+    }
+
+    pub fn push_response(&self, body: Value) {
+        self.state.lock().unwrap().responses.push_back(body);
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+async fn handle_connection(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+            return;
+        }
+
+        let path = request_line
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or_default()
+            .to_owned();
+
+        // requests from the sharder are all GETs, so there's no body to read
+        let mut authorization = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await.unwrap_or(0) == 0 {
+                return;
+            }
+
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("authorization") {
+                    authorization = Some(value.trim().to_owned());
+                }
+            }
+        }
+
+        let body = {
+            let mut state = state.lock().unwrap();
+            state.requests.push(Request {
+                path,
+                authorization,
+            });
+
+            let body = if state.responses.len() > 1 {
+                state.responses.pop_front()
+            } else {
+                state.responses.front().cloned()
+            };
+
+            body.unwrap_or(Value::Null).to_string()
+        };
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{accept_async, WebSocketStream};
+
+use futures::{SinkExt, StreamExt};
+
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An in-process stand-in for the Discord gateway. Each connection made by a shard is handed to
+/// the test as a `MockConnection`, which the test then drives payload by payload.
+pub struct MockGateway {
+    listener: TcpListener,
+}
+
+impl MockGateway {
+    pub async fn bind() -> MockGateway {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind mock gateway");
+
+        MockGateway { listener }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.listener.local_addr().unwrap())
+    }
+
+    pub async fn accept(&self) -> MockConnection {
+        let (stream, _) = timeout(RECV_TIMEOUT, self.listener.accept())
+            .await
+            .expect("Timed out waiting for shard to connect")
+            .expect("Failed to accept connection");
+
+        let ws = accept_async(stream)
+            .await
+            .expect("Failed to complete websocket handshake");
+
+        MockConnection { ws }
+    }
+}
+
+pub struct MockConnection {
+    ws: WebSocketStream<TcpStream>,
+}
+
+impl MockConnection {
+    pub async fn send(&mut self, payload: Value) {
+        self.ws
+            .send(Message::Text(payload.to_string()))
+            .await
+            .expect("Failed to write to shard");
+    }
+
+    pub async fn send_hello(&mut self, heartbeat_interval: u32) {
+        self.send(json!({
+            "op": 10,
+            "d": { "heartbeat_interval": heartbeat_interval },
+        }))
+        .await
+    }
+
+    pub async fn send_dispatch(&mut self, event_type: &str, data: Value, seq: usize) {
+        self.send(json!({
+            "op": 0,
+            "t": event_type,
+            "s": seq,
+            "d": data,
+        }))
+        .await
+    }
+
+    pub async fn send_ready(&mut self, session_id: &str, shard_info: [u16; 2], seq: usize) {
+        let data = json!({
+            "v": 9,
+            "user": {
+                "id": "1",
+                "username": "tickets",
+                "discriminator": "0001",
+                "avatar": null,
+            },
+            "guilds": [],
+            "session_id": session_id,
+            "shard": shard_info,
+        });
+
+        self.send_dispatch("READY", data, seq).await
+    }
+
+    pub async fn send_resumed(&mut self, seq: usize) {
+        self.send_dispatch("RESUMED", Value::Null, seq).await
+    }
+
+    pub async fn send_heartbeat_request(&mut self) {
+        self.send(json!({ "op": 1, "d": null })).await
+    }
+
+    pub async fn send_heartbeat_ack(&mut self) {
+        self.send(json!({ "op": 11 })).await
+    }
+
+    pub async fn send_reconnect(&mut self) {
+        self.send(json!({ "op": 7, "d": null })).await
+    }
+
+    pub async fn send_invalid_session(&mut self, resumable: bool) {
+        self.send(json!({ "op": 9, "d": resumable })).await
+    }
+
+    pub async fn close(&mut self, code: u16, reason: &str) {
+        let frame = CloseFrame {
+            code: CloseCode::from(code),
+            reason: Cow::Owned(reason.to_owned()),
+        };
+
+        // the shard may hang up before the close handshake completes
+        let _ = self.ws.close(Some(frame)).await;
+    }
+
+    /// Returns the next payload sent by the shard, or None if the connection was closed
+    pub async fn recv(&mut self) -> Option<Value> {
+        loop {
+            let msg = timeout(RECV_TIMEOUT, self.ws.next())
+                .await
+                .expect("Timed out waiting for payload from shard");
+
+            match msg {
+                Some(Ok(Message::Text(data))) => {
+                    return Some(serde_json::from_str(&data).expect("Shard sent invalid json"))
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return None,
+                Some(Ok(_)) => continue,
+            }
+        }
+    }
+
+    /// Waits for the shard to close the connection, returning the close code, acknowledging any
+    /// heartbeats received in the meantime
+    pub async fn expect_close(&mut self) -> Option<u16> {
+        loop {
+            let msg = timeout(RECV_TIMEOUT, self.ws.next())
+                .await
+                .expect("Timed out waiting for close from shard");
+
+            match msg {
+                Some(Ok(Message::Close(frame))) => return frame.map(|frame| frame.code.into()),
+                Some(Ok(Message::Text(_))) => self.send_heartbeat_ack().await,
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => panic!("Connection dropped without a close frame"),
+            }
+        }
+    }
+
+    /// Waits for a payload with the given opcode, acknowledging any heartbeats received in the
+    /// meantime. Panics if any other opcode is received first.
+    pub async fn expect_op(&mut self, opcode: u8) -> Value {
+        loop {
+            let payload = self
+                .recv()
+                .await
+                .unwrap_or_else(|| panic!("Connection closed while waiting for op {}", opcode));
+
+            let received = payload["op"].as_u64().expect("Payload was missing op");
+            if received == opcode as u64 {
+                return payload;
+            }
+
+            if received == 1 {
+                self.send_heartbeat_ack().await;
+                continue;
+            }
+
+            panic!("Expected op {}, got {}", opcode, payload);
+        }
+    }
+}
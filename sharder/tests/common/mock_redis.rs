@@ -0,0 +1,406 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_expired(&self) -> bool {
+        matches!(self.expires_at, Some(expiry) if expiry <= Instant::now())
+    }
+}
+
+#[derive(Default)]
+struct Data {
+    strings: HashMap<String, Entry>,
+    sets: HashMap<String, HashSet<String>>,
+    // only recorded, sets are never expired
+    set_expiries: HashMap<String, Instant>,
+    lists: HashMap<String, VecDeque<String>>,
+    // field-value pairs of each entry, oldest first
+    streams: HashMap<String, Vec<Vec<(String, String)>>>,
+    // messages published to each channel, oldest first. There are never any subscribers.
+    published: HashMap<String, Vec<String>>,
+}
+
+type Store = Arc<Mutex<Data>>;
+
+/// A minimal RESP server implementing the handful of commands the shard issues (GET, SET, DEL,
+/// EXISTS, EXPIRE, PTTL, SADD, SREM, SCARD, LPUSH, XADD, PUBLISH, MULTI, EXEC and PING), so that
+/// tests don't need a real Redis instance.
+pub struct MockRedis {
+    addr: String,
+    store: Store,
+}
+
+impl MockRedis {
+    pub async fn start() -> MockRedis {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind mock redis");
+
+        let addr = listener.local_addr().unwrap().to_string();
+        let store: Store = Arc::new(Mutex::new(Data::default()));
+
+        let accept_store = Arc::clone(&store);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, Arc::clone(&accept_store)));
+            }
+        });
+
+        MockRedis { addr, store }
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr[..]
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let store = self.store.lock().unwrap();
+        store
+            .strings
+            .get(key)
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| String::from_utf8_lossy(&entry.value).into_owned())
+    }
+
+    pub fn set(&self, key: &str, value: &str) {
+        self.store.lock().unwrap().strings.insert(
+            key.to_owned(),
+            Entry {
+                value: value.as_bytes().to_vec(),
+                expires_at: None,
+            },
+        );
+    }
+
+    pub fn del(&self, key: &str) {
+        let mut store = self.store.lock().unwrap();
+        store.strings.remove(key);
+        store.sets.remove(key);
+        store.set_expiries.remove(key);
+    }
+
+    pub fn sadd(&self, key: &str, member: &str) {
+        self.store
+            .lock()
+            .unwrap()
+            .sets
+            .entry(key.to_owned())
+            .or_default()
+            .insert(member.to_owned());
+    }
+
+    pub fn smembers(&self, key: &str) -> HashSet<String> {
+        let store = self.store.lock().unwrap();
+        store.sets.get(key).cloned().unwrap_or_default()
+    }
+
+    /// How long until the set expires, if an expiry has been set
+    pub fn set_ttl(&self, key: &str) -> Option<Duration> {
+        let store = self.store.lock().unwrap();
+        store
+            .set_expiries
+            .get(key)
+            .map(|expiry| expiry.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn list(&self, key: &str) -> Vec<String> {
+        let store = self.store.lock().unwrap();
+        store
+            .lists
+            .get(key)
+            .map_or_else(Vec::new, |list| list.iter().cloned().collect())
+    }
+
+    pub fn stream(&self, key: &str) -> Vec<Vec<(String, String)>> {
+        let store = self.store.lock().unwrap();
+        store.streams.get(key).cloned().unwrap_or_default()
+    }
+
+    pub fn published(&self, channel: &str) -> Vec<String> {
+        let store = self.store.lock().unwrap();
+        store.published.get(channel).cloned().unwrap_or_default()
+    }
+}
+
+async fn handle_connection(stream: TcpStream, store: Store) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    // commands queued since MULTI, run together on EXEC
+    let mut transaction: Option<Vec<Vec<Vec<u8>>>> = None;
+
+    while let Some(args) = read_command(&mut reader).await {
+        let name = String::from_utf8_lossy(&args[0]).to_uppercase();
+
+        let response = match (&name[..], transaction.as_mut()) {
+            ("MULTI", None) => {
+                transaction = Some(Vec::new());
+                b"+OK\r\n".to_vec()
+            }
+            ("EXEC", Some(_)) => {
+                let queued = transaction.take().unwrap_or_default();
+                let mut store = store.lock().unwrap();
+
+                let mut response = format!("*{}\r\n", queued.len()).into_bytes();
+                for args in queued {
+                    response.extend(execute(&mut store, args));
+                }
+
+                response
+            }
+            ("MULTI", Some(_)) | ("EXEC", None) => error("unexpected MULTI or EXEC"),
+            (_, Some(queued)) => {
+                queued.push(args);
+                b"+QUEUED\r\n".to_vec()
+            }
+            (_, None) => execute(&mut store.lock().unwrap(), args),
+        };
+
+        if writer.write_all(&response).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn read_command<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Option<Vec<Vec<u8>>> {
+    let count: usize = read_line(reader).await?.strip_prefix('*')?.parse().ok()?;
+
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len: usize = read_line(reader).await?.strip_prefix('$')?.parse().ok()?;
+
+        let mut buf = vec![0u8; len + 2]; // trailing \r\n
+        reader.read_exact(&mut buf).await.ok()?;
+        buf.truncate(len);
+
+        args.push(buf);
+    }
+
+    Some(args)
+}
+
+async fn read_line<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Option<String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line).await {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end().to_owned()),
+    }
+}
+
+fn execute(data: &mut Data, args: Vec<Vec<u8>>) -> Vec<u8> {
+    let args: Vec<String> = args
+        .into_iter()
+        .map(|arg| String::from_utf8_lossy(&arg).into_owned())
+        .collect();
+
+    let store = &mut data.strings;
+    store.retain(|_, entry| !entry.is_expired());
+
+    // deadpool sends "PING <n>" as a single argument when recycling connections
+    let mut command = args[0].splitn(2, ' ');
+    let name = command.next().unwrap_or_default().to_uppercase();
+
+    match &name[..] {
+        "PING" => match command.next().or_else(|| args.get(1).map(|s| &s[..])) {
+            Some(echo) => bulk(echo.as_bytes()),
+            None => b"+PONG\r\n".to_vec(),
+        },
+
+        "GET" => match store.get(&args[1]) {
+            Some(entry) => bulk(&entry.value),
+            None => b"$-1\r\n".to_vec(),
+        },
+
+        "SET" => {
+            let mut nx = false;
+            let mut expires_at = None;
+
+            let mut options = args[3..].iter();
+            while let Some(option) = options.next() {
+                match &option.to_uppercase()[..] {
+                    "NX" => nx = true,
+                    "EX" => {
+                        let secs = options.next().unwrap().parse().unwrap();
+                        expires_at = Some(Instant::now() + Duration::from_secs(secs));
+                    }
+                    "PX" => {
+                        let millis = options.next().unwrap().parse().unwrap();
+                        expires_at = Some(Instant::now() + Duration::from_millis(millis));
+                    }
+                    other => return error(&format!("unsupported SET option {}", other)),
+                }
+            }
+
+            if nx && store.contains_key(&args[1]) {
+                return b"$-1\r\n".to_vec();
+            }
+
+            store.insert(
+                args[1].clone(),
+                Entry {
+                    value: args[2].as_bytes().to_vec(),
+                    expires_at,
+                },
+            );
+
+            b"+OK\r\n".to_vec()
+        }
+
+        "DEL" => {
+            let sets = &mut data.sets;
+            let set_expiries = &mut data.set_expiries;
+            let removed = args[1..]
+                .iter()
+                .filter(|key| {
+                    let string = store.remove(&key[..]).is_some();
+                    set_expiries.remove(&key[..]);
+                    let set = sets.remove(&key[..]).is_some();
+                    string || set
+                })
+                .count();
+
+            integer(removed as i64)
+        }
+
+        "EXISTS" => {
+            let sets = &data.sets;
+            let existing = args[1..]
+                .iter()
+                .filter(|key| store.contains_key(&key[..]) || sets.contains_key(&key[..]))
+                .count();
+
+            integer(existing as i64)
+        }
+
+        "EXPIRE" => {
+            let secs = args[2].parse().unwrap();
+            let expires_at = Instant::now() + Duration::from_secs(secs);
+
+            if let Some(entry) = store.get_mut(&args[1]) {
+                entry.expires_at = Some(expires_at);
+                integer(1)
+            } else if data.sets.contains_key(&args[1]) {
+                data.set_expiries.insert(args[1].clone(), expires_at);
+                integer(1)
+            } else {
+                integer(0)
+            }
+        }
+
+        "PTTL" => match store.get(&args[1]) {
+            Some(Entry {
+                expires_at: Some(expiry),
+                ..
+            }) => integer(expiry.saturating_duration_since(Instant::now()).as_millis() as i64),
+            Some(_) => integer(-1),
+            None => integer(-2),
+        },
+
+        "SADD" => {
+            let set = data.sets.entry(args[1].clone()).or_default();
+            let added = args[2..]
+                .iter()
+                .filter(|member| set.insert(member.to_string()))
+                .count();
+
+            integer(added as i64)
+        }
+
+        "SREM" => {
+            let set = data.sets.entry(args[1].clone()).or_default();
+            let removed = args[2..]
+                .iter()
+                .filter(|member| set.remove(&member[..]))
+                .count();
+
+            // redis deletes sets once they're empty
+            if set.is_empty() {
+                data.sets.remove(&args[1]);
+                data.set_expiries.remove(&args[1]);
+            }
+
+            integer(removed as i64)
+        }
+
+        "LPUSH" => {
+            let list = data.lists.entry(args[1].clone()).or_default();
+            for value in &args[2..] {
+                list.push_front(value.clone());
+            }
+
+            integer(list.len() as i64)
+        }
+
+        "SCARD" => integer(data.sets.get(&args[1]).map_or(0, |set| set.len()) as i64),
+
+        // XADD key [MAXLEN [~] n] * field value...
+        "XADD" => {
+            let mut rest = args[2..].iter().peekable();
+            let mut max_len = None;
+
+            if rest.peek().map(|arg| arg.to_uppercase()) == Some("MAXLEN".to_owned()) {
+                rest.next();
+                if rest.peek().map(|arg| &arg[..]) == Some("~") {
+                    rest.next();
+                }
+
+                max_len = rest.next().and_then(|len| len.parse::<usize>().ok());
+            }
+
+            if rest.next().map(|id| &id[..]) != Some("*") {
+                return error("only generated IDs are supported");
+            }
+
+            let fields: Vec<String> = rest.cloned().collect();
+            let entry = fields
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+
+            let stream = data.streams.entry(args[1].clone()).or_default();
+            stream.push(entry);
+
+            // trimmed exactly, which redis is free to do with ~
+            if let Some(max_len) = max_len {
+                let excess = stream.len().saturating_sub(max_len);
+                stream.drain(..excess);
+            }
+
+            bulk(format!("0-{}", stream.len()).as_bytes())
+        }
+
+        "PUBLISH" => {
+            data.published
+                .entry(args[1].clone())
+                .or_default()
+                .push(args[2].clone());
+
+            integer(0)
+        }
+
+        other => error(&format!("unsupported command {}", other)),
+    }
+}
+
+fn bulk(data: &[u8]) -> Vec<u8> {
+    let mut res = format!("${}\r\n", data.len()).into_bytes();
+    res.extend_from_slice(data);
+    res.extend_from_slice(b"\r\n");
+    res
+}
+
+fn integer(i: i64) -> Vec<u8> {
+    format!(":{}\r\n", i).into_bytes()
+}
+
+fn error(msg: &str) -> Vec<u8> {
+    format!("-ERR {}\r\n", msg).into_bytes()
+}
@@ -0,0 +1,199 @@
+#![allow(dead_code)]
+
+pub mod mock_api;
+
+mod mock_gateway;
+pub use mock_gateway::MockGateway;
+
+mod mock_redis;
+pub use mock_redis::MockRedis;
+
+mod memory_cache;
+pub use memory_cache::MemoryCache;
+
+use cache::Cache;
+use model::Snowflake;
+use sharder::event_forwarding::{EventForwarder, HttpEventForwarder};
+use sharder::{
+    build_redis, Config, ForwardingGate, Identify, Presence, PublicMode, PublicShardManager,
+    SessionStartLimit, SessionStartLimiter, Shard, ShardCount, ShardInfo, ShardMode, ShardServices,
+    TrafficRecorder,
+};
+use std::sync::Arc;
+
+pub const TOKEN: &str = "mock-token";
+pub const BOT_ID: Snowflake = Snowflake(1);
+
+pub fn build_config(gateway: &MockGateway, redis: &MockRedis) -> Config {
+    let vars = vec![
+        ("SHARDER_ID", "0".to_owned()),
+        ("SHARDER_TOTAL", "1".to_owned()),
+        ("REDIS_ADDR", redis.addr().to_owned()),
+        ("REDIS_THREADS", "4".to_owned()),
+        ("WORKER_SVC_URI", "127.0.0.1:1".to_owned()),
+        ("SENTRY_DSN", "".to_owned()),
+        ("GATEWAY_URL", gateway.url()),
+        ("SHARDER_TOKEN", TOKEN.to_owned()),
+        ("SHARDER_CLUSTER_SIZE", "1".to_owned()),
+        ("BOT_ID", BOT_ID.to_string()),
+    ];
+
+    envy::from_iter(vars.into_iter().map(|(k, v)| (k.to_owned(), v)))
+        .expect("Failed to build mock config")
+}
+
+pub fn build_shard(config: Config) -> Arc<Shard<HttpEventForwarder, PublicMode, MemoryCache>> {
+    build_shard_with(config, PublicMode, Arc::new(MemoryCache::default()))
+}
+
+pub fn build_shard_with_mode<M: ShardMode>(
+    config: Config,
+    mode: M,
+) -> Arc<Shard<HttpEventForwarder, M, MemoryCache>> {
+    build_shard_with(config, mode, Arc::new(MemoryCache::default()))
+}
+
+pub fn build_shard_with<M: ShardMode, C: Cache>(
+    config: Config,
+    mode: M,
+    cache: Arc<C>,
+) -> Arc<Shard<HttpEventForwarder, M, C>> {
+    let event_forwarder =
+        HttpEventForwarder::from_config(&config, HttpEventForwarder::build_http_client());
+
+    build_shard_from(config, mode, cache, event_forwarder)
+}
+
+pub fn build_shard_with_forwarder<T: EventForwarder>(
+    config: Config,
+    event_forwarder: T,
+) -> Arc<Shard<T, PublicMode, MemoryCache>> {
+    build_shard_from(
+        config,
+        PublicMode,
+        Arc::new(MemoryCache::default()),
+        event_forwarder,
+    )
+}
+
+fn build_shard_from<T: EventForwarder, M: ShardMode, C: Cache>(
+    config: Config,
+    mode: M,
+    cache: Arc<C>,
+    event_forwarder: T,
+) -> Arc<Shard<T, M, C>> {
+    let redis = build_redis(&config);
+    let identify = Identify::new(TOKEN.to_owned(), None, ShardInfo::new(0, 1), None, 0);
+
+    let event_whitelist = Arc::new(config.forward_events.clone());
+    let session_start_limiter = build_session_start_limiter(&config);
+    let recorder = TrafficRecorder::from_config(&config).map(Arc::new);
+
+    let services = ShardServices {
+        cache,
+        redis: Arc::new(redis),
+        event_forwarder: Arc::new(event_forwarder),
+        event_whitelist,
+        session_start_limiter,
+        forwarding_gate: Arc::new(ForwardingGate::new(1)),
+        recorder,
+    };
+
+    Shard::new(Arc::new(config), identify, 1, BOT_ID, services, mode)
+}
+
+pub async fn build_manager(
+    config: Config,
+    shard_total: u16,
+) -> PublicShardManager<HttpEventForwarder, MemoryCache> {
+    let event_forwarder =
+        HttpEventForwarder::from_config(&config, HttpEventForwarder::build_http_client());
+
+    build_manager_with(config, shard_total, event_forwarder).await
+}
+
+pub async fn build_manager_with<T: EventForwarder>(
+    config: Config,
+    shard_total: u16,
+    event_forwarder: T,
+) -> PublicShardManager<T, MemoryCache> {
+    let redis = build_redis(&config);
+
+    let options = sharder::Options {
+        token: Box::from(TOKEN),
+        shard_count: ShardCount {
+            total: shard_total,
+            lowest: 0,
+            highest: shard_total,
+        },
+        presence: Presence::from_config(&config),
+        large_sharding_buckets: 1,
+        user_id: BOT_ID,
+        session_start_limiter: build_session_start_limiter(&config),
+    };
+
+    PublicShardManager::new(
+        config,
+        options,
+        Arc::new(MemoryCache::default()),
+        Arc::new(redis),
+        Arc::new(event_forwarder),
+    )
+    .await
+}
+
+fn build_session_start_limiter(config: &Config) -> Arc<SessionStartLimiter> {
+    // seed the limit, so that identifying doesn't call out to Discord
+    let session_start_limit = SessionStartLimit {
+        total: 1000,
+        remaining: 1000,
+        reset_after: 0,
+        max_concurrency: 1,
+    };
+
+    Arc::new(SessionStartLimiter::new(
+        reqwest::Client::new(),
+        config,
+        TOKEN.to_owned(),
+        Some(session_start_limit),
+    ))
+}
+
+pub fn resume_key() -> &'static str {
+    "tickets:resume:public:0-1"
+}
+
+pub fn seq_key() -> &'static str {
+    "tickets:seq:public:0-1"
+}
+
+pub fn identify_ratelimit_key() -> &'static str {
+    "ratelimiter:public:identify:0"
+}
+
+/// A GUILD_CREATE payload for an empty guild
+pub fn guild(id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "name": "guild",
+        "icon": null,
+        "owner_id": "2",
+        "region": "europe",
+        "afk_channel_id": null,
+        "afk_timeout": 300,
+        "verification_level": 0,
+        "default_message_notifications": 0,
+        "explicit_content_filter": 0,
+        "roles": [],
+        "emojis": [],
+        "features": [],
+        "mfa_level": 0,
+        "application_id": null,
+        "system_channel_id": null,
+        "rules_channel_id": null,
+        "premium_tier": 0,
+        "preferred_locale": "en-US",
+        "public_updates_channel_id": null,
+        "nsfw_level": 0
+    })
+}
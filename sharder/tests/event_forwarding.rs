@@ -0,0 +1,222 @@
+mod common;
+
+// the common crate, rather than the test helpers
+use ::common::event_forwarding as common_events;
+use async_trait::async_trait;
+use common::{build_config, build_shard_with_forwarder, MockGateway, MockRedis};
+use model::Snowflake;
+use serde_json::json;
+use serde_json::value::{to_raw_value, RawValue};
+use sharder::event_forwarding::{EventForwarder, RedisStreamEventForwarder, RetryEventForwarder};
+use sharder::{build_redis, Config, GatewayError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+const HEARTBEAT_INTERVAL: u32 = 41250;
+
+fn event(event: &RawValue) -> common_events::Event<'_> {
+    common_events::Event {
+        bot_token: "token",
+        bot_id: 1,
+        is_whitelabel: false,
+        shard_id: 0,
+        event,
+    }
+}
+
+/// Fails every forward, counting the attempts
+#[derive(Default)]
+struct FailingForwarder {
+    attempts: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl EventForwarder for FailingForwarder {
+    async fn forward_event(
+        &self,
+        _config: &Config,
+        _event: common_events::Event<'_>,
+        _guild_id: Option<Snowflake>,
+    ) -> Result<(), GatewayError> {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        Err(GatewayError::GenericError("worker unavailable".to_owned()))
+    }
+}
+
+/// Records the role IDs of forwarded GUILD_ROLE_DELETE events, failing the first few attempts
+/// to forward the deletion of role 1
+#[derive(Default)]
+struct RoleDeleteForwarder {
+    failures: AtomicUsize,
+    forwarded: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl EventForwarder for RoleDeleteForwarder {
+    async fn forward_event(
+        &self,
+        _config: &Config,
+        event: common_events::Event<'_>,
+        _guild_id: Option<Snowflake>,
+    ) -> Result<(), GatewayError> {
+        let event: serde_json::Value = serde_json::from_str(event.event.get()).unwrap();
+        let role_id = event["d"]["role_id"].as_str().unwrap().to_owned();
+
+        if role_id == "1" && self.failures.fetch_add(1, Ordering::SeqCst) < 3 {
+            return Err(GatewayError::GenericError("worker unavailable".to_owned()));
+        }
+
+        self.forwarded.lock().unwrap().push(role_id);
+        Ok(())
+    }
+}
+
+/// The events in a stream, decoded from the event field of each entry
+fn decode(entries: Vec<Vec<(String, String)>>) -> Vec<serde_json::Value> {
+    entries
+        .into_iter()
+        .map(|fields| {
+            assert_eq!(fields.len(), 1);
+            assert_eq!(fields[0].0, common_events::EVENT_FIELD);
+            serde_json::from_str(&fields[0].1).unwrap()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn adds_events_to_a_single_stream() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let config = build_config(&gateway, &redis);
+    let forwarder = RedisStreamEventForwarder::new(Arc::new(build_redis(&config)), 100, None);
+
+    let data = to_raw_value(&json!({"t": "MESSAGE_CREATE", "d": {"id": "1"}})).unwrap();
+    forwarder
+        .forward_event(&config, event(&data), Some(Snowflake(1 << 22)))
+        .await
+        .unwrap();
+
+    let events = decode(redis.stream(common_events::EVENT_KEY));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["bot_id"], json!(1));
+    assert_eq!(events[0]["event"]["t"], json!("MESSAGE_CREATE"));
+}
+
+#[tokio::test]
+async fn partitions_streams_by_guild_and_trims_them() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let config = build_config(&gateway, &redis);
+    let forwarder = RedisStreamEventForwarder::new(Arc::new(build_redis(&config)), 2, Some(4));
+
+    // partitioned like shards, by the timestamp bits of the guild ID
+    let guild_id = Snowflake((4 * 10 + 3) << 22);
+    for i in 0..3 {
+        let data = to_raw_value(&json!({"t": "MESSAGE_CREATE", "d": {"id": i}})).unwrap();
+        forwarder
+            .forward_event(&config, event(&data), Some(guild_id))
+            .await
+            .unwrap();
+    }
+
+    // events without a guild go to the first partition
+    let data = to_raw_value(&json!({"t": "USER_UPDATE", "d": {}})).unwrap();
+    forwarder
+        .forward_event(&config, event(&data), None)
+        .await
+        .unwrap();
+
+    let partition = decode(redis.stream(&common_events::partitioned_key(3)));
+    let ids: Vec<_> = partition
+        .iter()
+        .map(|e| e["event"]["d"]["id"].clone())
+        .collect();
+    assert_eq!(ids, vec![json!(1), json!(2)]);
+
+    let first = decode(redis.stream(&common_events::partitioned_key(0)));
+    assert_eq!(first.len(), 1);
+    assert_eq!(first[0]["event"]["t"], json!("USER_UPDATE"));
+
+    assert!(redis.stream(common_events::EVENT_KEY).is_empty());
+}
+
+#[tokio::test]
+async fn dead_letters_events_once_retries_are_exhausted() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let config = build_config(&gateway, &redis);
+
+    let inner = FailingForwarder::default();
+    let attempts = Arc::clone(&inner.attempts);
+    let forwarder = RetryEventForwarder::new(
+        inner,
+        Arc::new(build_redis(&config)),
+        Duration::from_millis(1),
+        Duration::from_millis(5),
+        Duration::from_millis(50),
+        true,
+    );
+
+    let data = to_raw_value(&json!({"t": "MESSAGE_CREATE", "d": {"id": "1"}})).unwrap();
+    let res = forwarder
+        .forward_event(&config, event(&data), Some(Snowflake(1 << 22)))
+        .await;
+
+    assert!(matches!(res, Err(GatewayError::GenericError(_))));
+    assert!(attempts.load(Ordering::SeqCst) > 1);
+
+    let dead_letters = redis.list(common_events::DEAD_LETTER_KEY);
+    assert_eq!(dead_letters.len(), 1);
+
+    let dead_letter: common_events::DeadLetter = serde_json::from_str(&dead_letters[0]).unwrap();
+    assert_eq!(dead_letter.guild_id, Some(Snowflake(1 << 22)));
+    assert_eq!(dead_letter.error, "worker unavailable");
+    assert_eq!(dead_letter.event.event.get(), data.get());
+}
+
+#[tokio::test]
+async fn forwards_each_guilds_events_in_order() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let config = build_config(&gateway, &redis);
+
+    let inner = RoleDeleteForwarder::default();
+    let forwarded = Arc::clone(&inner.forwarded);
+    let forwarder = RetryEventForwarder::new(
+        inner,
+        Arc::new(build_redis(&config)),
+        Duration::from_millis(50),
+        Duration::from_millis(50),
+        Duration::from_secs(5),
+        false,
+    );
+
+    let shard = build_shard_with_forwarder(config, forwarder);
+    tokio::spawn(Arc::clone(&shard).connect(None));
+
+    let mut conn = gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session", [0, 1], 1).await;
+
+    let role_delete =
+        |guild_id: &str, role_id: &str| json!({"guild_id": guild_id, "role_id": role_id});
+    conn.send_dispatch("GUILD_ROLE_DELETE", role_delete("1", "1"), 2)
+        .await;
+    conn.send_dispatch("GUILD_ROLE_DELETE", role_delete("1", "2"), 3)
+        .await;
+    conn.send_dispatch("GUILD_ROLE_DELETE", role_delete("2", "3"), 4)
+        .await;
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while forwarded.lock().unwrap().len() < 3 {
+        assert!(Instant::now() < deadline, "Timed out waiting for events");
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    // the second event of the first guild waits for the first to be retried, while the other
+    // guild's isn't held up
+    assert_eq!(*forwarded.lock().unwrap(), vec!["3", "1", "2"]);
+}
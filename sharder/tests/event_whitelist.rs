@@ -0,0 +1,82 @@
+mod common;
+
+use common::{build_config, MockGateway, MockRedis};
+use sharder::event_forwarding::EventWhitelist;
+use sharder::Config;
+use std::collections::HashSet;
+
+fn events(whitelist: &EventWhitelist) -> HashSet<&'static str> {
+    whitelist.events().collect()
+}
+
+#[test]
+fn matches_names_case_insensitively() {
+    let whitelist = EventWhitelist::new(&["message_create", " Guild_Create "]).unwrap();
+
+    assert_eq!(
+        events(&whitelist),
+        ["MESSAGE_CREATE", "GUILD_CREATE"].iter().copied().collect()
+    );
+    assert!(whitelist.is_whitelisted("MESSAGE_CREATE"));
+    assert!(!whitelist.is_whitelisted("TYPING_START"));
+}
+
+#[test]
+fn rejects_unknown_names() {
+    let err = EventWhitelist::new(&["MESSAGE_CREATE", "MESAGE_DELETE"]).unwrap_err();
+    assert_eq!(err.0, "MESAGE_DELETE");
+}
+
+#[test]
+fn defaults_to_events_workers_handle() {
+    let whitelist = EventWhitelist::default();
+
+    assert!(whitelist.is_whitelisted("MESSAGE_CREATE"));
+    assert!(whitelist.is_whitelisted("GUILD_DELETE"));
+    assert!(!whitelist.is_whitelisted("PRESENCE_UPDATE"));
+}
+
+#[tokio::test]
+async fn reads_whitelist_from_config() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+
+    // the other tests rely on the default being used when FORWARD_EVENTS isn't set
+    let config = build_config(&gateway, &redis);
+    assert_eq!(
+        events(&config.forward_events),
+        events(&EventWhitelist::default())
+    );
+
+    let vars = vec![
+        ("SHARDER_ID", "0"),
+        ("SHARDER_TOTAL", "1"),
+        ("REDIS_ADDR", redis.addr()),
+        ("REDIS_THREADS", "4"),
+        ("WORKER_SVC_URI", "127.0.0.1:1"),
+        ("SENTRY_DSN", ""),
+        ("SHARDER_TOKEN", "token"),
+        ("SHARDER_CLUSTER_SIZE", "1"),
+        ("BOT_ID", "1"),
+        ("FORWARD_EVENTS", "MESSAGE_CREATE,THREAD_CREATE"),
+    ];
+
+    let config: Config =
+        envy::from_iter(vars.iter().map(|(k, v)| (k.to_string(), v.to_string()))).unwrap();
+    assert_eq!(
+        events(&config.forward_events),
+        ["MESSAGE_CREATE", "THREAD_CREATE"]
+            .iter()
+            .copied()
+            .collect()
+    );
+
+    let vars = vars
+        .into_iter()
+        .map(|(k, v)| match k {
+            "FORWARD_EVENTS" => (k, "MESSAGE_CREATE,NOT_AN_EVENT"),
+            _ => (k, v),
+        })
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+    assert!(envy::from_iter::<_, Config>(vars).is_err());
+}
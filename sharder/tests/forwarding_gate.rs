@@ -0,0 +1,38 @@
+use sharder::ForwardingGate;
+
+const ROLE_DELETE: &str = r#"{"guild_id":"1","role_id":"1"}"#;
+const OTHER_ROLE_DELETE: &str = r#"{"guild_id":"1","role_id":"2"}"#;
+
+#[test]
+fn only_active_set_forwards_before_switch() {
+    let gate = ForwardingGate::new(1);
+
+    assert!(gate.admit(1, "GUILD_ROLE_DELETE", ROLE_DELETE));
+    assert!(!gate.admit(2, "GUILD_ROLE_DELETE", ROLE_DELETE));
+}
+
+#[test]
+fn old_set_forwards_events_the_new_set_held_back() {
+    let gate = ForwardingGate::new(1);
+
+    // read by the new set just before the switch, and by the old set just after
+    assert!(!gate.admit(2, "GUILD_ROLE_DELETE", ROLE_DELETE));
+    gate.switch_to(2);
+    assert!(gate.admit(1, "GUILD_ROLE_DELETE", ROLE_DELETE));
+
+    // only once, as a later event with the same data was read by the new set after the switch
+    assert!(!gate.admit(1, "GUILD_ROLE_DELETE", ROLE_DELETE));
+
+    // the new set hasn't read the event yet, so will forward it itself
+    assert!(!gate.admit(1, "GUILD_ROLE_DELETE", OTHER_ROLE_DELETE));
+    assert!(gate.admit(2, "GUILD_ROLE_DELETE", OTHER_ROLE_DELETE));
+}
+
+#[test]
+fn events_are_told_apart_by_name() {
+    let gate = ForwardingGate::new(1);
+
+    assert!(!gate.admit(2, "GUILD_ROLE_DELETE", ROLE_DELETE));
+    gate.switch_to(2);
+    assert!(!gate.admit(1, "GUILD_ROLE_UPDATE", ROLE_DELETE));
+}
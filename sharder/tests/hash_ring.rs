@@ -0,0 +1,75 @@
+use sharder::event_forwarding::HashRing;
+
+const KEYS: u64 = 10_000;
+
+fn ring(names: &[&str]) -> HashRing {
+    HashRing::new(names.iter().copied().enumerate())
+}
+
+/// The node each key is assigned to, by name
+fn assign<'a>(ring: &HashRing, names: &[&'a str]) -> Vec<&'a str> {
+    (0..KEYS)
+        .map(|key| names[ring.get(key << 22).unwrap()])
+        .collect()
+}
+
+#[test]
+fn assigns_keys_stably() {
+    let names = ["worker-a", "worker-b", "worker-c"];
+
+    let first = assign(&ring(&names), &names);
+    let second = assign(&ring(&names), &names);
+    assert_eq!(first, second);
+
+    // the name, rather than the index, determines where a node sits
+    let reordered = ["worker-c", "worker-a", "worker-b"];
+    assert_eq!(first, assign(&ring(&reordered), &reordered));
+}
+
+#[test]
+fn spreads_keys_across_nodes() {
+    let names = ["worker-a", "worker-b", "worker-c", "worker-d"];
+    let assigned = assign(&ring(&names), &names);
+
+    for name in &names {
+        let share = assigned.iter().filter(|n| *n == name).count() as u64;
+        assert!(share > KEYS / 8, "{} only got {} keys", name, share);
+    }
+}
+
+#[test]
+fn only_moves_keys_of_added_node() {
+    let before = ["worker-a", "worker-b", "worker-c"];
+    let after = ["worker-a", "worker-b", "worker-c", "worker-d"];
+
+    let old = assign(&ring(&before), &before);
+    let new = assign(&ring(&after), &after);
+
+    let moved = old.iter().zip(&new).filter(|(o, n)| o != n).count() as u64;
+    assert!(old
+        .iter()
+        .zip(&new)
+        .all(|(o, n)| o == n || *n == "worker-d"));
+
+    // ideally a quarter of the keys move to the new node
+    assert!(moved > KEYS / 8 && moved < KEYS / 2, "{} keys moved", moved);
+}
+
+#[test]
+fn only_moves_keys_of_removed_node() {
+    let before = ["worker-a", "worker-b", "worker-c"];
+    let after = ["worker-a", "worker-c"];
+
+    let old = assign(&ring(&before), &before);
+    let new = assign(&ring(&after), &after);
+
+    assert!(old
+        .iter()
+        .zip(&new)
+        .all(|(o, n)| o == n || *o == "worker-b"));
+}
+
+#[test]
+fn empty_ring_has_no_nodes() {
+    assert_eq!(ring(&[]).get(1), None);
+}
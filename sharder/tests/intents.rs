@@ -0,0 +1,84 @@
+mod common;
+
+use common::{build_config, build_manager, MockGateway, MockRedis};
+use serde_json::json;
+use sharder::{IntentSet, Intents, ShardManager};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+fn parse(values: &[&str]) -> Result<IntentSet, sharder::UnknownIntentError> {
+    IntentSet::try_from(values.iter().map(|v| v.to_string()).collect::<Vec<_>>())
+}
+
+#[test]
+fn parses_intent_names_and_bitmasks() {
+    let intents = parse(&["guilds", " GUILD_MESSAGES "]).unwrap();
+    assert_eq!(intents.bits(), Intents::Guilds | Intents::GuildMessages);
+
+    // GUILDS | GUILD_MEMBERS | GUILD_MESSAGES
+    assert_eq!(parse(&["515"]).unwrap().bits(), 515);
+
+    let intents = parse(&["3", "GUILD_PRESENCES"]).unwrap();
+    assert_eq!(intents.bits(), 3 | Intents::GuildPresences);
+    assert!(intents.contains(Intents::GuildMembers));
+    assert!(!intents.contains(Intents::GuildMessages));
+}
+
+#[test]
+fn rejects_unknown_intents() {
+    let err = parse(&["GUILDS", "GUILD_MESAGES"]).unwrap_err();
+    assert_eq!(err.0, "GUILD_MESAGES");
+}
+
+#[test]
+fn defaults_to_previous_intents() {
+    assert_eq!(
+        IntentSet::default().bits(),
+        Intents::build(vec![
+            Intents::Guilds,
+            Intents::GuildMembers,
+            Intents::GuildMessages,
+        ])
+    );
+}
+
+#[test]
+fn maps_events_to_required_intents() {
+    assert!(matches!(
+        Intents::required_for("MESSAGE_CREATE"),
+        Some(Intents::GuildMessages)
+    ));
+    assert!(matches!(
+        Intents::required_for("GUILD_MEMBER_UPDATE"),
+        Some(Intents::GuildMembers)
+    ));
+    assert!(matches!(
+        Intents::required_for("CHANNEL_DELETE"),
+        Some(Intents::Guilds)
+    ));
+
+    // sent regardless of intents
+    assert!(Intents::required_for("READY").is_none());
+    assert!(Intents::required_for("INTERACTION_CREATE").is_none());
+}
+
+#[tokio::test]
+async fn identifies_with_configured_intents() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+
+    let mut config = build_config(&gateway, &redis);
+    config.intents = parse(&["GUILDS", "GUILD_MESSAGE_REACTIONS"]).unwrap();
+
+    let sm = Arc::new(build_manager(config, 1).await);
+    Arc::clone(&sm).reshard(2).await.unwrap();
+
+    let mut conn = gateway.accept().await;
+    conn.send_hello(41250).await;
+
+    let identify = conn.expect_op(2).await;
+    assert_eq!(
+        identify["d"]["intents"],
+        json!(Intents::Guilds | Intents::GuildMessageReactions)
+    );
+}
@@ -0,0 +1,262 @@
+mod common;
+
+use ::common::event_forwarding as common_events;
+use common::{
+    build_config, build_manager, build_manager_with, guild, identify_ratelimit_key, resume_key,
+    seq_key, MockGateway, MockRedis,
+};
+use model::Snowflake;
+use serde_json::{json, Value};
+use sharder::event_forwarding::{
+    HttpEventForwarder, RedisStreamEventForwarder, RetryEventForwarder,
+};
+use sharder::{build_redis, lease_target, GatewayError, PublicMode, ShardManager, ShardMode};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::time::{sleep, timeout, Instant};
+
+const HEARTBEAT_INTERVAL: u32 = 41250;
+
+#[tokio::test]
+async fn rejects_invalid_shard_totals() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let sm = Arc::new(build_manager(build_config(&gateway, &redis), 1).await);
+
+    assert!(matches!(
+        Arc::clone(&sm).reshard(0).await,
+        Err(GatewayError::InvalidShardTotalError(0))
+    ));
+
+    // already running 1 shard
+    assert!(matches!(
+        Arc::clone(&sm).reshard(1).await,
+        Err(GatewayError::InvalidShardTotalError(1))
+    ));
+}
+
+#[tokio::test]
+async fn reshard_runs_new_shards_alongside_old() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let sm = Arc::new(build_manager(build_config(&gateway, &redis), 1).await);
+    assert_eq!(sm.shards().await.len(), 1);
+
+    Arc::clone(&sm).reshard(2).await.unwrap();
+    assert!(matches!(
+        Arc::clone(&sm).reshard(4).await,
+        Err(GatewayError::AlreadyReshardingError)
+    ));
+
+    let mut shards = sm
+        .shards()
+        .await
+        .iter()
+        .map(|shard| (shard.get_shard_id(), shard.get_shard_total()))
+        .collect::<Vec<_>>();
+    shards.sort();
+    assert_eq!(shards, vec![(0, 1), (0, 2), (1, 2)]);
+
+    // the old set was never connected, so the first connection is from the new set
+    let mut conn = gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+
+    let identify = conn.expect_op(2).await;
+    assert_eq!(identify["d"]["shard"][1], json!(2));
+}
+
+/// The role IDs of the forwarded GUILD_ROLE_DELETE events, with the ID of the shard that
+/// forwarded each
+fn forwarded_roles(redis: &MockRedis) -> Vec<(String, u64)> {
+    redis
+        .stream(common_events::EVENT_KEY)
+        .into_iter()
+        .map(|fields| serde_json::from_str::<Value>(&fields[0].1).unwrap())
+        .filter(|event| event["event"]["t"] == "GUILD_ROLE_DELETE")
+        .map(|event| {
+            let role_id = event["event"]["d"]["role_id"].as_str().unwrap().to_owned();
+            (role_id, event["shard_id"].as_u64().unwrap())
+        })
+        .collect()
+}
+
+async fn wait_for_forwarded(redis: &MockRedis, count: usize) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while forwarded_roles(redis).len() < count {
+        assert!(Instant::now() < deadline, "Timed out waiting for events");
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn wait_for_guild_bots(redis: &MockRedis, guild_id: Snowflake) {
+    let key = PublicMode.guild_bots_key(guild_id);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while redis.smembers(&key).is_empty() {
+        assert!(Instant::now() < deadline, "Timed out waiting for guild");
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+fn role_delete(role_id: &str) -> Value {
+    json!({"guild_id": "1", "role_id": role_id})
+}
+
+#[tokio::test]
+async fn switches_forwarding_to_new_shard_set() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let config = build_config(&gateway, &redis);
+    let event_forwarder = RedisStreamEventForwarder::new(Arc::new(build_redis(&config)), 100, None);
+    let sm = Arc::new(build_manager_with(config, 1, event_forwarder).await);
+
+    tokio::spawn(Arc::clone(&sm).connect());
+    let mut old = gateway.accept().await;
+    old.send_hello(HEARTBEAT_INTERVAL).await;
+    old.expect_op(2).await;
+    old.send_ready("old", [0, 1], 1).await;
+
+    old.send_dispatch("GUILD_ROLE_DELETE", role_delete("1"), 2)
+        .await;
+    wait_for_forwarded(&redis, 1).await;
+
+    // every shard shares an identify bucket, so don't make each wait for the last
+    redis.del(identify_ratelimit_key());
+    Arc::clone(&sm).reshard(2).await.unwrap();
+    let mut new_0 = gateway.accept().await;
+    new_0.send_hello(HEARTBEAT_INTERVAL).await;
+    // the new shards are started in no particular order
+    let new_0_id = new_0.expect_op(2).await["d"]["shard"][0].as_u64().unwrap();
+    new_0.send_ready("new-0", [0, 2], 1).await;
+
+    // both sets receive every event until the switch, but only the old one forwards them
+    new_0
+        .send_dispatch("GUILD_ROLE_DELETE", role_delete("2"), 2)
+        .await;
+    old.send_dispatch("GUILD_ROLE_DELETE", role_delete("2"), 3)
+        .await;
+    wait_for_forwarded(&redis, 2).await;
+
+    // events are processed in order, so once the guild is recorded the role deletion has been
+    // handled, and can't be mistaken for one forwarded after the switch
+    new_0.send_dispatch("GUILD_CREATE", guild("1"), 3).await;
+    wait_for_guild_bots(&redis, Snowflake(1)).await;
+
+    redis.del(identify_ratelimit_key());
+    let mut new_1 = gateway.accept().await;
+    new_1.send_hello(HEARTBEAT_INTERVAL).await;
+    new_1.expect_op(2).await;
+    new_1.send_ready("new-1", [1, 2], 1).await;
+    new_1.send_dispatch("GUILD_CREATE", guild("2"), 2).await;
+
+    // the old set is shut down once the new one has taken over
+    old.expect_close().await;
+
+    new_0
+        .send_dispatch("GUILD_ROLE_DELETE", role_delete("3"), 4)
+        .await;
+    wait_for_forwarded(&redis, 3).await;
+
+    // give any event wrongly forwarded by the new set before the switch time to arrive
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(
+        forwarded_roles(&redis),
+        vec![
+            ("1".to_owned(), 0),
+            ("2".to_owned(), 0),
+            ("3".to_owned(), new_0_id)
+        ]
+    );
+    assert_eq!(
+        sm.shards()
+            .await
+            .iter()
+            .map(|shard| shard.get_shard_total())
+            .collect::<Vec<_>>(),
+        vec![2, 2]
+    );
+}
+
+/// Accepts connections, but never responds on them. The receiver resolves once the first
+/// connection is accepted.
+async fn hanging_worker() -> (String, oneshot::Receiver<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (accepted_tx, accepted_rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let mut accepted_tx = Some(accepted_tx);
+        let mut conns = Vec::new();
+        while let Ok((conn, _)) = listener.accept().await {
+            conns.push(conn);
+            if let Some(tx) = accepted_tx.take() {
+                let _ = tx.send(());
+            }
+        }
+    });
+
+    (format!("http://{}/event", addr), accepted_rx)
+}
+
+#[tokio::test]
+async fn shuts_down_resumably_while_a_worker_hangs() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let mut config = build_config(&gateway, &redis);
+    config.shutdown_timeout = 1000;
+
+    let (worker, accepted_rx) = hanging_worker().await;
+    let http = HttpEventForwarder::new(
+        HttpEventForwarder::build_http_client(),
+        vec![worker],
+        3,
+        Duration::from_secs(10),
+    );
+    let event_forwarder =
+        RetryEventForwarder::from_config(&config, http, Arc::new(build_redis(&config)));
+    let sm = Arc::new(build_manager_with(config, 1, event_forwarder).await);
+
+    tokio::spawn(Arc::clone(&sm).connect());
+    let mut conn = gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    conn.send_dispatch("GUILD_ROLE_DELETE", role_delete("1"), 2)
+        .await;
+
+    // the forward never completes, but mustn't hold up saving the session past the deadline
+    timeout(Duration::from_secs(5), accepted_rx)
+        .await
+        .expect("The event was never forwarded")
+        .unwrap();
+    let shutdown = tokio::spawn(Arc::clone(&sm).shutdown());
+    assert_eq!(conn.expect_close().await, Some(4000));
+    timeout(Duration::from_secs(1), shutdown)
+        .await
+        .expect("Shutdown overran its deadline")
+        .unwrap();
+
+    assert_eq!(redis.get(seq_key()).as_deref(), Some("2"));
+    assert_eq!(redis.get(resume_key()).as_deref(), Some("session-1"));
+
+    let dead_letters = redis.list(common_events::DEAD_LETTER_KEY);
+    assert_eq!(dead_letters.len(), 1);
+
+    let dead_letter: common_events::DeadLetter = serde_json::from_str(&dead_letters[0]).unwrap();
+    assert_eq!(dead_letter.guild_id, Some(Snowflake(1)));
+    assert!(dead_letter.event.event.get().contains("GUILD_ROLE_DELETE"));
+}
+
+#[test]
+fn spreads_whitelabel_bots_evenly() {
+    assert_eq!(lease_target(10, 3, None), 4);
+    assert_eq!(lease_target(9, 3, None), 3);
+    assert_eq!(lease_target(0, 3, None), 0);
+
+    // no live sharders is treated as one
+    assert_eq!(lease_target(10, 0, None), 10);
+
+    assert_eq!(lease_target(10, 1, Some(4)), 4);
+    assert_eq!(lease_target(10, 5, Some(4)), 2);
+}
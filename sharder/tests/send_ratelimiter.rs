@@ -0,0 +1,79 @@
+use sharder::SendRateLimiter;
+use std::time::{Duration, Instant};
+
+const PERIOD: Duration = Duration::from_secs(60);
+
+#[test]
+fn holds_back_reserve_for_heartbeats() {
+    let now = Instant::now();
+    let mut limiter = SendRateLimiter::new(10, PERIOD, 2);
+
+    for _ in 0..8 {
+        assert!(limiter.try_acquire_at(false, now));
+    }
+
+    // only heartbeats may use the last 2 tokens
+    assert!(!limiter.try_acquire_at(false, now));
+    assert!(limiter.try_acquire_at(true, now));
+    assert!(limiter.try_acquire_at(true, now));
+    assert!(!limiter.try_acquire_at(true, now));
+}
+
+#[test]
+fn heartbeats_are_available_before_commands() {
+    let now = Instant::now();
+    let mut limiter = SendRateLimiter::new(10, PERIOD, 2);
+
+    for i in 0..8 {
+        assert!(limiter.try_acquire_at(false, now + Duration::from_secs(i)));
+    }
+
+    let later = now + Duration::from_secs(10);
+    assert_eq!(limiter.available_at_from(true, later), later);
+    assert_eq!(limiter.available_at_from(false, later), now + PERIOD);
+}
+
+#[test]
+fn prevents_bursts_at_window_boundaries() {
+    let start = Instant::now();
+    let mut limiter = SendRateLimiter::new(10, PERIOD, 0);
+
+    // fill the limit just before where a fixed window would reset
+    let late = start + PERIOD - Duration::from_secs(1);
+    for _ in 0..10 {
+        assert!(limiter.try_acquire_at(false, late));
+    }
+
+    // a fixed window would allow another 10 here, 2 seconds after the first 10
+    let after_boundary = start + PERIOD + Duration::from_secs(1);
+    assert!(!limiter.try_acquire_at(false, after_boundary));
+    assert_eq!(
+        limiter.available_at_from(false, after_boundary),
+        late + PERIOD
+    );
+
+    // the whole limit is available again a full period after the burst
+    for _ in 0..10 {
+        assert!(limiter.try_acquire_at(false, late + PERIOD));
+    }
+    assert!(!limiter.try_acquire_at(false, late + PERIOD));
+}
+
+#[test]
+fn refills_as_messages_leave_the_window() {
+    let start = Instant::now();
+    let mut limiter = SendRateLimiter::new(3, PERIOD, 0);
+
+    for i in 0..3 {
+        assert!(limiter.try_acquire_at(false, start + Duration::from_secs(i * 10)));
+    }
+
+    // the first message leaves the window after 60s, freeing exactly one token
+    let now = start + PERIOD;
+    assert!(limiter.try_acquire_at(false, now));
+    assert!(!limiter.try_acquire_at(false, now));
+    assert_eq!(
+        limiter.available_at_from(false, now),
+        start + Duration::from_secs(10) + PERIOD
+    );
+}
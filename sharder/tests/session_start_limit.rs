@@ -0,0 +1,85 @@
+mod common;
+
+use common::mock_api::MockApi;
+use common::{build_config, MockGateway, MockRedis, TOKEN};
+use serde_json::json;
+use sharder::{get_gateway_bot, Config, SessionStartLimiter};
+use std::time::{Duration, Instant};
+
+const RESET_AFTER: u64 = 200;
+
+fn gateway_bot(remaining: u32) -> serde_json::Value {
+    json!({
+        "url": "wss://gateway.discord.gg",
+        "shards": 9,
+        "session_start_limit": {
+            "total": 1000,
+            "remaining": remaining,
+            "reset_after": RESET_AFTER,
+            "max_concurrency": 16
+        }
+    })
+}
+
+async fn build_config_with_api(api: &MockApi) -> Config {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+
+    let mut config = build_config(&gateway, &redis);
+    config.discord_api_url = api.url().to_owned();
+    config.session_start_reserve = 10;
+    config
+}
+
+#[tokio::test]
+async fn fetches_gateway_bot() {
+    let api = MockApi::start().await;
+    api.push_response(gateway_bot(990));
+
+    let gateway_bot = get_gateway_bot(&reqwest::Client::new(), api.url(), TOKEN)
+        .await
+        .expect("Failed to fetch /gateway/bot");
+
+    assert_eq!(gateway_bot.shards, 9);
+    assert_eq!(gateway_bot.session_start_limit.remaining, 990);
+    assert_eq!(gateway_bot.session_start_limit.max_concurrency, 16);
+
+    let requests = api.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/api/v9/gateway/bot");
+    assert_eq!(
+        requests[0].authorization.as_deref(),
+        Some(&format!("Bot {}", TOKEN)[..])
+    );
+}
+
+#[tokio::test]
+async fn counts_down_without_refetching() {
+    let api = MockApi::start().await;
+    api.push_response(gateway_bot(13));
+
+    let config = build_config_with_api(&api).await;
+    let limiter = SessionStartLimiter::new(reqwest::Client::new(), &config, TOKEN.to_owned(), None);
+
+    for _ in 0..3 {
+        limiter.acquire().await;
+    }
+
+    assert_eq!(api.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn waits_for_reset_when_budget_is_used_up() {
+    let api = MockApi::start().await;
+    api.push_response(gateway_bot(10));
+    api.push_response(gateway_bot(1000));
+
+    let config = build_config_with_api(&api).await;
+    let limiter = SessionStartLimiter::new(reqwest::Client::new(), &config, TOKEN.to_owned(), None);
+
+    let started = Instant::now();
+    limiter.acquire().await;
+
+    assert!(started.elapsed() >= Duration::from_millis(RESET_AFTER));
+    assert_eq!(api.requests().len(), 2);
+}
@@ -0,0 +1,1031 @@
+mod common;
+
+use cache::Cache;
+use common::{
+    build_config, build_shard, build_shard_with, build_shard_with_mode, guild,
+    identify_ratelimit_key, resume_key, seq_key, MemoryCache, MockGateway, MockRedis, BOT_ID,
+    TOKEN,
+};
+use event_forwarding::HttpEventForwarder;
+use model::user::{ActivityType, StatusType, StatusUpdate};
+use model::Snowflake;
+use serde_json::json;
+use serde_json::value::RawValue;
+use sharder::metrics::{ShardLabels, METRICS};
+use sharder::{
+    event_forwarding, rotate_presence, ConnectionState, Event, GatewayError, GuildMembersFilter,
+    Presence, PublicMode, RecordedPayload, Shard, ShardInfo, ShardMode,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+const HEARTBEAT_INTERVAL: u32 = 41250;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Harness {
+    gateway: MockGateway,
+    redis: MockRedis,
+    cache: Arc<MemoryCache>,
+    shard: Arc<Shard<HttpEventForwarder, PublicMode, MemoryCache>>,
+}
+
+impl Harness {
+    async fn new() -> Harness {
+        let gateway = MockGateway::bind().await;
+        let redis = MockRedis::start().await;
+        let cache = Arc::new(MemoryCache::default());
+        let shard = build_shard_with(
+            build_config(&gateway, &redis),
+            PublicMode,
+            Arc::clone(&cache),
+        );
+
+        Harness {
+            gateway,
+            redis,
+            cache,
+            shard,
+        }
+    }
+
+    fn connect(&self) -> (JoinHandle<Result<(), GatewayError>>, oneshot::Receiver<()>) {
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let handle = tokio::spawn(Arc::clone(&self.shard).connect(Some(ready_tx)));
+
+        (handle, ready_rx)
+    }
+
+    async fn wait_for_key(&self, key: &str, expected: Option<&str>) {
+        let res = timeout(TIMEOUT, async {
+            while self.redis.get(key).as_deref() != expected {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+
+        assert!(res.is_ok(), "{} never became {:?}", key, expected);
+    }
+}
+
+async fn wait_for_exit(handle: JoinHandle<Result<(), GatewayError>>) -> Result<(), GatewayError> {
+    timeout(TIMEOUT, handle)
+        .await
+        .expect("Timed out waiting for shard to exit")
+        .expect("Shard task panicked")
+}
+
+#[tokio::test]
+async fn identifies_when_no_session_is_stored() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+
+    let identify = conn.expect_op(2).await;
+    assert_eq!(identify["d"]["token"], TOKEN);
+    assert_eq!(identify["d"]["shard"], json!([0, 1]));
+
+    conn.send_ready("session-1", [0, 1], 1).await;
+    harness.wait_for_key(resume_key(), Some("session-1")).await;
+}
+
+#[tokio::test]
+async fn resumes_stored_session() {
+    let harness = Harness::new().await;
+    harness.redis.set(resume_key(), "session-1");
+    harness.redis.set(seq_key(), "42");
+
+    let (_handle, ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+
+    let resume = conn.expect_op(6).await;
+    assert_eq!(resume["d"]["token"], TOKEN);
+    assert_eq!(resume["d"]["session_id"], "session-1");
+    assert_eq!(resume["d"]["seq"], 42);
+
+    conn.send_resumed(43).await;
+    timeout(TIMEOUT, ready_rx)
+        .await
+        .expect("Timed out waiting for ready notification")
+        .expect("Ready sender was dropped");
+}
+
+/// Keys everything by bot ID, like whitelabel shards, without needing a database
+struct BotKeyedMode;
+
+impl ShardMode for BotKeyedMode {
+    fn is_whitelabel(&self) -> bool {
+        true
+    }
+
+    fn identify_ratelimit_key(&self, bot_id: Snowflake, _: &ShardInfo, _: u16) -> String {
+        format!("test:identify:{}", bot_id)
+    }
+
+    fn resume_key(&self, bot_id: Snowflake, _: &ShardInfo) -> String {
+        format!("test:resume:{}", bot_id)
+    }
+
+    fn seq_key(&self, bot_id: Snowflake, _: &ShardInfo) -> String {
+        format!("test:seq:{}", bot_id)
+    }
+
+    fn log_prefix(&self, bot_id: Snowflake, _: &ShardInfo) -> String {
+        format!("[test:{}]", bot_id)
+    }
+}
+
+#[tokio::test]
+async fn uses_mode_for_redis_keys() {
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let shard = build_shard_with_mode(build_config(&gateway, &redis), BotKeyedMode);
+    let _handle = tokio::spawn(Arc::clone(&shard).connect(None));
+
+    let mut conn = gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    assert_eq!(redis.get("test:identify:1").as_deref(), Some("1"));
+    assert_eq!(redis.get(identify_ratelimit_key()), None);
+
+    conn.send_ready("session-1", [0, 1], 1).await;
+    let res = timeout(TIMEOUT, async {
+        while redis.get("test:resume:1").as_deref() != Some("session-1") {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+
+    assert!(res.is_ok(), "Session was never stored under the mode's key");
+    assert_eq!(redis.get(resume_key()), None);
+}
+
+#[tokio::test]
+async fn recorded_traffic_can_be_replayed() {
+    let dir = std::env::temp_dir().join(format!("sharder-recording-{}", std::process::id()));
+
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let mut config = build_config(&gateway, &redis);
+    config.record_traffic_dir = Some(dir.to_string_lossy().into_owned());
+
+    let shard = build_shard(config);
+    let _handle = tokio::spawn(Arc::clone(&shard).connect(None));
+
+    let mut conn = gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+
+    let recording = timeout(TIMEOUT, async {
+        loop {
+            if let Some(recording) = read_recording(&dir).await {
+                if recording.lines().count() >= 2 {
+                    return recording;
+                }
+            }
+
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Traffic was never recorded");
+
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+
+    // replay against a fresh Redis, where the session is stored by the READY
+    let gateway = MockGateway::bind().await;
+    let redis = MockRedis::start().await;
+    let shard = build_shard(build_config(&gateway, &redis));
+
+    for line in recording.lines() {
+        let recorded: RecordedPayload = serde_json::from_str(line).unwrap();
+        assert_eq!(recorded.bot_id, BOT_ID);
+        assert_eq!(recorded.shard_id, 0);
+
+        Arc::clone(&shard)
+            .replay(recorded.payload.get().as_bytes())
+            .await
+            .unwrap();
+    }
+
+    shard.wait_for_in_flight().await;
+    assert_eq!(redis.get(resume_key()).as_deref(), Some("session-1"));
+}
+
+async fn read_recording(dir: &std::path::Path) -> Option<String> {
+    let mut entries = tokio::fs::read_dir(dir).await.ok()?;
+    let entry = entries.next_entry().await.ok()??;
+
+    tokio::fs::read_to_string(entry.path()).await.ok()
+}
+
+#[tokio::test]
+async fn caches_dispatched_events() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    conn.send_dispatch(
+        "CHANNEL_CREATE",
+        json!({ "id": "10", "type": 0, "guild_id": "20", "name": "general" }),
+        2,
+    )
+    .await;
+
+    let channel = timeout(TIMEOUT, async {
+        loop {
+            if let Some(channel) = harness.cache.get_channel(Snowflake(10)).await.unwrap() {
+                return channel;
+            }
+
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Channel was never cached");
+
+    assert_eq!(channel.name.as_deref(), Some("general"));
+}
+
+async fn wait_for_guild(cache: &MemoryCache, id: Snowflake, cached: bool) {
+    let res = timeout(TIMEOUT, async {
+        while cache.has_guild(id) != cached {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+
+    assert!(res.is_ok(), "Guild {} never became cached: {}", id, cached);
+}
+
+#[tokio::test]
+async fn keeps_guild_cached_until_no_bot_remains() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+    let key = PublicMode.guild_bots_key(Snowflake(20));
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    conn.send_dispatch("GUILD_CREATE", guild("20"), 2).await;
+    wait_for_guild(&harness.cache, Snowflake(20), true).await;
+
+    // a whitelabel bot is in the guild too
+    harness.redis.sadd(&key, "3");
+    assert!(harness.redis.smembers(&key).contains(&BOT_ID.to_string()));
+
+    // the guild becoming unavailable isn't a kick
+    conn.send_dispatch(
+        "GUILD_DELETE",
+        json!({ "id": "20", "unavailable": true }),
+        3,
+    )
+    .await;
+    conn.send_dispatch("GUILD_DELETE", json!({ "id": "20" }), 4)
+        .await;
+
+    let res = timeout(TIMEOUT, async {
+        while harness.redis.smembers(&key).len() != 1 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(res.is_ok(), "Bot was never removed from the guild");
+    assert!(harness.cache.has_guild(Snowflake(20)));
+
+    // the whitelabel bot leaves, then the bot rejoins and is kicked again
+    harness.redis.del(&key);
+    conn.send_dispatch("GUILD_CREATE", guild("20"), 5).await;
+    conn.send_dispatch("GUILD_DELETE", json!({ "id": "20" }), 6)
+        .await;
+    wait_for_guild(&harness.cache, Snowflake(20), false).await;
+    assert!(harness.redis.smembers(&key).is_empty());
+}
+
+async fn wait_for_guild_bot(redis: &MockRedis, guild_id: Snowflake) {
+    let key = PublicMode.guild_bots_key(guild_id);
+    let res = timeout(TIMEOUT, async {
+        while !redis.smembers(&key).contains(&BOT_ID.to_string()) {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+
+    assert!(res.is_ok(), "Bot was never recorded in guild {}", guild_id);
+}
+
+#[tokio::test]
+async fn records_bot_in_every_guild_on_ready() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_dispatch(
+        "READY",
+        json!({
+            "v": 9,
+            "user": { "id": "1", "username": "tickets", "discriminator": "0001", "avatar": null },
+            "guilds": [{ "id": "20", "unavailable": true }, { "id": "21", "unavailable": true }],
+            "session_id": "session-1",
+            "shard": [0, 1],
+        }),
+        1,
+    )
+    .await;
+
+    // before any GUILD_CREATE is received
+    for id in [20, 21] {
+        wait_for_guild_bot(&harness.redis, Snowflake(id)).await;
+
+        let ttl = harness
+            .redis
+            .set_ttl(&PublicMode.guild_bots_key(Snowflake(id)))
+            .expect("Set was never given an expiry");
+        assert!(ttl > Duration::from_secs(24 * 60 * 60));
+    }
+}
+
+#[tokio::test]
+async fn records_bot_in_known_guilds_on_resume() {
+    let harness = Harness::new().await;
+    let (handle, _ready_rx) = harness.connect();
+    let key = PublicMode.guild_bots_key(Snowflake(20));
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    conn.send_dispatch("GUILD_CREATE", guild("20"), 2).await;
+    wait_for_guild_bot(&harness.redis, Snowflake(20)).await;
+
+    conn.send_reconnect().await;
+    assert!(wait_for_exit(handle).await.is_ok());
+
+    // the set expired while we were disconnected
+    harness.redis.del(&key);
+    harness.redis.set(seq_key(), "2");
+
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(6).await;
+    conn.send_resumed(3).await;
+
+    // no GUILD_CREATE follows a resume
+    wait_for_guild_bot(&harness.redis, Snowflake(20)).await;
+}
+
+#[tokio::test]
+async fn evicts_guild_when_bots_are_unknown() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    conn.send_dispatch("GUILD_CREATE", guild("20"), 2).await;
+    wait_for_guild(&harness.cache, Snowflake(20), true).await;
+
+    // the set has been lost, so it's unknown whether another bot is still in the guild
+    harness.redis.del(&PublicMode.guild_bots_key(Snowflake(20)));
+    conn.send_dispatch("GUILD_DELETE", json!({ "id": "20" }), 3)
+        .await;
+    wait_for_guild(&harness.cache, Snowflake(20), false).await;
+}
+
+#[tokio::test]
+async fn forgets_guilds_on_authentication_failure() {
+    let harness = Harness::new().await;
+    let (handle, _ready_rx) = harness.connect();
+    let key = PublicMode.guild_bots_key(Snowflake(20));
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    conn.send_dispatch("GUILD_CREATE", guild("20"), 2).await;
+    wait_for_guild_bot(&harness.redis, Snowflake(20)).await;
+
+    harness.redis.sadd(&key, "3");
+    conn.close(4004, "Authentication failed").await;
+    assert!(wait_for_exit(handle).await.is_err());
+
+    // the other bot remains in the guild
+    assert_eq!(harness.redis.smembers(&key), ["3".to_owned()].into());
+    assert!(harness.cache.has_guild(Snowflake(20)));
+}
+
+#[tokio::test]
+async fn tracks_unavailable_guilds() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_dispatch(
+        "READY",
+        json!({
+            "v": 9,
+            "user": { "id": "1", "username": "tickets", "discriminator": "0001", "avatar": null },
+            "guilds": [{ "id": "20", "unavailable": true }, { "id": "21", "unavailable": true }],
+            "session_id": "session-1",
+            "shard": [0, 1],
+        }),
+        1,
+    )
+    .await;
+    conn.send_dispatch("GUILD_CREATE", guild("20"), 2).await;
+    wait_for_guild(&harness.cache, Snowflake(20), true).await;
+
+    let status = harness.shard.status().await;
+    assert_eq!(status.pending_guild_count, 1);
+    assert_eq!(status.unavailable_guild_count, 0);
+
+    conn.send_dispatch(
+        "GUILD_DELETE",
+        json!({ "id": "20", "unavailable": true }),
+        3,
+    )
+    .await;
+    let res = timeout(TIMEOUT, async {
+        while !harness.cache.is_unavailable(Snowflake(20)) {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(res.is_ok(), "Guild was never marked unavailable");
+    assert_eq!(harness.shard.status().await.unavailable_guild_count, 1);
+
+    // the outage is over
+    conn.send_dispatch("GUILD_CREATE", guild("20"), 4).await;
+    let res = timeout(TIMEOUT, async {
+        while harness.cache.is_unavailable(Snowflake(20)) {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(res.is_ok(), "Guild never became available");
+
+    let status = harness.shard.status().await;
+    assert_eq!(status.pending_guild_count, 1);
+    assert_eq!(status.unavailable_guild_count, 0);
+}
+
+#[tokio::test]
+async fn holds_presence_until_ready() {
+    let harness = Harness::new().await;
+
+    // queued while the shard was disconnected
+    harness
+        .shard
+        .status_update_tx
+        .try_send(StatusUpdate::new(
+            ActivityType::Listening,
+            "/help".to_owned(),
+            StatusType::Online,
+        ))
+        .unwrap();
+
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+
+    let early = timeout(Duration::from_millis(200), async {
+        loop {
+            match conn.recv().await {
+                Some(payload) if payload["op"] == 1 => continue,
+                payload => return payload,
+            }
+        }
+    })
+    .await;
+    assert!(early.is_err(), "Sent {:?} before ready", early);
+
+    conn.send_ready("session-1", [0, 1], 1).await;
+
+    let presence = conn.expect_op(3).await;
+    assert_eq!(presence["d"]["game"]["name"], "/help");
+}
+
+#[test]
+fn initial_presence_omits_guild_count() {
+    let mut presence = Presence {
+        activity_type: ActivityType::Watching,
+        status_type: StatusType::Online,
+        messages: vec![
+            "{guilds} servers".to_owned(),
+            "/help on {shard}/{shards}".to_owned(),
+        ],
+        rotate_interval: Duration::from_secs(60),
+    };
+
+    let initial = presence.initial(&ShardInfo::new(1, 2)).unwrap();
+    assert_eq!(initial.game.unwrap().name, "/help on 1/2");
+
+    // the rotation fills in the count once the shard is ready
+    presence.messages.remove(1);
+    assert!(presence.initial(&ShardInfo::new(1, 2)).is_none());
+}
+
+#[tokio::test]
+async fn rotates_presence() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let (presence_tx, presence_rx) = watch::channel(Arc::new(Presence {
+        activity_type: ActivityType::Watching,
+        status_type: StatusType::Idle,
+        messages: vec![
+            "{guilds} servers on {shard}/{shards}".to_owned(),
+            "/help".to_owned(),
+        ],
+        rotate_interval: Duration::from_millis(50),
+    }));
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_dispatch(
+        "READY",
+        json!({
+            "v": 9,
+            "user": { "id": "1", "username": "tickets", "discriminator": "0001", "avatar": null },
+            "guilds": [{ "id": "20", "unavailable": true }, { "id": "21", "unavailable": true }],
+            "session_id": "session-1",
+            "shard": [0, 1],
+        }),
+        1,
+    )
+    .await;
+
+    tokio::spawn(rotate_presence(Arc::downgrade(&harness.shard), presence_rx));
+
+    let mut messages = Vec::new();
+    while messages.len() < 3 {
+        let presence = conn.expect_op(3).await;
+        assert_eq!(presence["d"]["game"]["type"], 3);
+        assert_eq!(presence["d"]["status"], "idle");
+        messages.push(presence["d"]["game"]["name"].as_str().unwrap().to_owned());
+    }
+
+    assert!(messages.contains(&"2 servers on 0/1".to_owned()));
+    assert!(messages.contains(&"/help".to_owned()));
+
+    // a new presence is pushed straight away
+    presence_tx
+        .send(Arc::new(Presence {
+            activity_type: ActivityType::Listening,
+            status_type: StatusType::Online,
+            messages: vec!["/setup".to_owned()],
+            rotate_interval: Duration::from_secs(60),
+        }))
+        .unwrap();
+
+    let presence = loop {
+        let presence = conn.expect_op(3).await;
+        if presence["d"]["game"]["name"] == "/setup" {
+            break presence;
+        }
+    };
+    assert_eq!(presence["d"]["status"], "online");
+}
+
+#[tokio::test]
+async fn heartbeats_with_latest_seq() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(500).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 5).await;
+
+    let heartbeat = conn.expect_op(1).await;
+    assert_eq!(heartbeat["d"], 5);
+    conn.send_heartbeat_ack().await;
+
+    let heartbeat = conn.expect_op(1).await;
+    assert_eq!(heartbeat["d"], 5);
+}
+
+#[tokio::test]
+async fn exits_when_heartbeat_is_not_acked() {
+    let harness = Harness::new().await;
+    let (handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(300).await;
+    conn.expect_op(2).await;
+    conn.expect_op(1).await;
+
+    assert!(wait_for_exit(handle).await.is_ok());
+}
+
+#[tokio::test]
+async fn invalid_session_clears_stored_session() {
+    let harness = Harness::new().await;
+    harness.redis.set(resume_key(), "session-1");
+    harness.redis.set(seq_key(), "42");
+
+    let (handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(6).await;
+    conn.send_invalid_session(false).await;
+
+    assert!(wait_for_exit(handle).await.is_ok());
+    assert_eq!(harness.redis.get(resume_key()), None);
+    assert_eq!(harness.redis.get(seq_key()), None);
+}
+
+#[tokio::test]
+async fn exits_on_reconnect_request() {
+    let harness = Harness::new().await;
+    let (handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_reconnect().await;
+
+    assert!(wait_for_exit(handle).await.is_ok());
+}
+
+async fn close_with(code: u16) -> Result<(), GatewayError> {
+    let harness = Harness::new().await;
+    let (handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.close(code, "closed by mock gateway").await;
+
+    wait_for_exit(handle).await
+}
+
+#[tokio::test]
+async fn authentication_failed_close_is_fatal() {
+    match close_with(4004).await {
+        Err(GatewayError::AuthenticationError {
+            bot_token,
+            error_code,
+            error,
+        }) => {
+            assert_eq!(bot_token, TOKEN);
+            assert_eq!(error_code, CloseCode::Library(4004));
+            assert_eq!(error, "closed by mock gateway");
+        }
+        res => panic!("Expected authentication error, got {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn disallowed_intents_close_is_fatal() {
+    match close_with(4014).await {
+        Err(GatewayError::AuthenticationError { error_code, .. }) => {
+            assert_eq!(error_code, CloseCode::Library(4014))
+        }
+        res => panic!("Expected authentication error, got {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn other_close_codes_are_not_fatal() {
+    assert!(close_with(4000).await.is_ok());
+}
+
+fn member(user_id: &str) -> serde_json::Value {
+    json!({
+        "user": { "id": user_id, "username": "user", "discriminator": "0001", "avatar": null },
+        "roles": [],
+        "joined_at": "2021-01-01T00:00:00+00:00",
+        "deaf": false,
+        "mute": false
+    })
+}
+
+#[tokio::test]
+async fn collects_requested_member_chunks() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    harness.wait_for_key(resume_key(), Some("session-1")).await;
+
+    let shard = Arc::clone(&harness.shard);
+    let request = tokio::spawn(async move {
+        let filter = GuildMembersFilter::UserIds(vec![Snowflake(1), Snowflake(2), Snowflake(3)]);
+        shard
+            .request_guild_members(Snowflake(100), filter, false)
+            .await
+    });
+
+    let payload = conn.expect_op(8).await;
+    assert_eq!(payload["d"]["guild_id"], "100");
+    assert_eq!(payload["d"]["user_ids"], json!(["1", "2", "3"]));
+    assert!(payload["d"].get("query").is_none());
+
+    let nonce = payload["d"]["nonce"].clone();
+    for (index, (user_id, not_found)) in [("1", json!([])), ("2", json!(["3"]))].iter().enumerate()
+    {
+        let chunk = json!({
+            "guild_id": "100",
+            "members": [member(user_id)],
+            "chunk_index": index,
+            "chunk_count": 2,
+            "not_found": not_found,
+            "nonce": nonce,
+        });
+
+        conn.send_dispatch("GUILD_MEMBERS_CHUNK", chunk, index + 2)
+            .await;
+    }
+
+    let members = timeout(TIMEOUT, request)
+        .await
+        .expect("Timed out waiting for member chunks")
+        .expect("Request task panicked")
+        .expect("Request failed");
+
+    let mut user_ids = members
+        .members
+        .iter()
+        .map(|member| member.user.as_ref().unwrap().id)
+        .collect::<Vec<_>>();
+    user_ids.sort();
+
+    assert_eq!(members.guild_id, Snowflake(100));
+    assert_eq!(user_ids, vec![Snowflake(1), Snowflake(2)]);
+    assert_eq!(members.not_found, vec![Snowflake(3)]);
+}
+
+#[tokio::test]
+async fn shutdown_saves_session_and_closes_resumably() {
+    let harness = Harness::new().await;
+    let (handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 7).await;
+    harness.wait_for_key(resume_key(), Some("session-1")).await;
+
+    // seq is only saved periodically, so it won't have been written yet
+    assert_eq!(harness.redis.get(seq_key()), None);
+
+    let shard = Arc::clone(&harness.shard);
+    let shutdown = tokio::spawn(async move { shard.shutdown().await });
+
+    assert_eq!(conn.expect_close().await, Some(4000));
+
+    timeout(TIMEOUT, shutdown)
+        .await
+        .expect("Timed out waiting for shutdown")
+        .expect("Shutdown task panicked");
+
+    assert!(wait_for_exit(handle).await.is_ok());
+    assert!(harness.shard.is_shutting_down());
+    assert_eq!(harness.redis.get(seq_key()).as_deref(), Some("7"));
+    assert_eq!(
+        harness.redis.get(resume_key()).as_deref(),
+        Some("session-1")
+    );
+}
+
+#[tokio::test]
+async fn status_reflects_connection() {
+    let harness = Harness::new().await;
+    assert_eq!(
+        harness.shard.status().await.state,
+        ConnectionState::Disconnected
+    );
+
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 3).await;
+    harness.wait_for_key(resume_key(), Some("session-1")).await;
+
+    let status = harness.shard.status().await;
+    assert_eq!(status.state, ConnectionState::Connected);
+    assert_eq!(status.shard_id, 0);
+    assert!(status.has_session);
+    assert_eq!(status.seq, Some(3));
+    assert!(status.connected_for.is_some());
+}
+
+#[tokio::test]
+async fn reidentify_discards_session() {
+    let harness = Harness::new().await;
+    let (handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 3).await;
+    harness.wait_for_key(resume_key(), Some("session-1")).await;
+
+    Arc::clone(&harness.shard).reidentify().await;
+    assert!(wait_for_exit(handle).await.is_ok());
+    assert_eq!(harness.redis.get(resume_key()), None);
+
+    // skip waiting out the identify ratelimit
+    harness.redis.del(identify_ratelimit_key());
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+}
+
+#[tokio::test]
+async fn stopped_shard_waits_to_be_reconnected() {
+    let harness = Harness::new().await;
+    let (handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+
+    Arc::clone(&harness.shard).stop();
+    assert!(wait_for_exit(handle).await.is_ok());
+
+    // the manager would call connect again, which shouldn't return until started
+    let (mut handle, _ready_rx) = harness.connect();
+    assert!(timeout(Duration::from_millis(100), &mut handle)
+        .await
+        .is_err());
+    assert_eq!(harness.shard.status().await.state, ConnectionState::Stopped);
+
+    Arc::clone(&harness.shard).reconnect();
+    assert!(wait_for_exit(handle).await.is_ok());
+
+    // skip waiting out the identify ratelimit
+    harness.redis.del(identify_ratelimit_key());
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+}
+
+#[tokio::test]
+async fn records_metrics() {
+    // metrics are shared with the other tests, so only check that they increase
+    let labels = ShardLabels::new(BOT_ID, 0);
+    let identifies = METRICS.identifies.get(labels, "");
+    let readies = METRICS.events_received.get(labels, "READY");
+
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    harness.wait_for_key(resume_key(), Some("session-1")).await;
+
+    assert!(METRICS.identifies.get(labels, "") > identifies);
+    assert!(METRICS.events_received.get(labels, "READY") > readies);
+
+    let rendered = METRICS.render(&[harness.shard.status().await]);
+    assert!(rendered.contains("# TYPE sharder_identifies_total counter"));
+    assert!(rendered.contains("sharder_shard_connected{bot_id=\"1\",shard_id=\"0\"} 1"));
+}
+
+#[tokio::test]
+async fn counts_unknown_events() {
+    let labels = ShardLabels::new(BOT_ID, 0);
+    let unknown = METRICS
+        .events_received
+        .get(labels, "AUTO_MODERATION_ACTION_EXECUTION");
+
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 1).await;
+    conn.send_dispatch(
+        "AUTO_MODERATION_ACTION_EXECUTION",
+        json!({ "guild_id": "20", "rule_id": "30" }),
+        2,
+    )
+    .await;
+    // a known event that fails to parse is skipped, rather than stopping the shard
+    conn.send_dispatch("CHANNEL_CREATE", json!({ "id": false }), 3)
+        .await;
+    conn.send_dispatch(
+        "CHANNEL_CREATE",
+        json!({ "id": "10", "type": 0, "guild_id": "20", "name": "general" }),
+        4,
+    )
+    .await;
+
+    let res = timeout(TIMEOUT, async {
+        while harness
+            .cache
+            .get_channel(Snowflake(10))
+            .await
+            .unwrap()
+            .is_none()
+        {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(res.is_ok(), "Channel was never cached");
+
+    assert!(
+        METRICS
+            .events_received
+            .get(labels, "AUTO_MODERATION_ACTION_EXECUTION")
+            > unknown
+    );
+}
+
+#[test]
+fn reads_guild_id_of_unknown_events() {
+    let event = Event::Unknown {
+        t: "AUTO_MODERATION_ACTION_EXECUTION".to_owned(),
+        d: RawValue::from_string(r#"{"guild_id":"20","rule_id":"30"}"#.to_owned()).unwrap(),
+    };
+    assert_eq!(event_forwarding::get_guild_id(&event), Some(Snowflake(20)));
+
+    let event = Event::Unknown {
+        t: "APPLICATION_COMMAND_PERMISSIONS_UPDATE".to_owned(),
+        d: RawValue::from_string(r#"{"id":"30"}"#.to_owned()).unwrap(),
+    };
+    assert_eq!(event_forwarding::get_guild_id(&event), None);
+}
+
+#[tokio::test]
+async fn answers_heartbeat_requests() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    conn.send_ready("session-1", [0, 1], 4).await;
+    conn.send_heartbeat_request().await;
+
+    let heartbeat = conn.expect_op(1).await;
+    assert_eq!(heartbeat["d"], 4);
+}
+
+#[tokio::test]
+async fn records_heartbeat_latency() {
+    let harness = Harness::new().await;
+    let (_handle, _ready_rx) = harness.connect();
+
+    let mut conn = harness.gateway.accept().await;
+    conn.send_hello(HEARTBEAT_INTERVAL).await;
+    conn.expect_op(2).await;
+    assert_eq!(harness.shard.latency().await, None);
+
+    conn.send_heartbeat_request().await;
+    conn.expect_op(1).await;
+    sleep(Duration::from_millis(50)).await;
+    conn.send_heartbeat_ack().await;
+
+    let latency = timeout(TIMEOUT, async {
+        loop {
+            if let Some(latency) = harness.shard.latency().await {
+                return latency;
+            }
+
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Latency was never recorded");
+
+    assert!(latency >= Duration::from_millis(50));
+}
@@ -0,0 +1,51 @@
+use common::whitelabel_control::{Ack, Command, Request};
+use model::Snowflake;
+use serde_json::json;
+
+#[test]
+fn decodes_requests() {
+    let request: Request = serde_json::from_value(json!({
+        "request_id": "abc",
+        "bot_id": "508391840525975553",
+        "command": "reload_status",
+    }))
+    .unwrap();
+
+    assert_eq!(request.request_id, "abc");
+    assert_eq!(request.bot_id, Snowflake(508391840525975553));
+    assert_eq!(request.command, Command::ReloadStatus);
+
+    assert!(serde_json::from_value::<Request>(json!({
+        "request_id": "abc",
+        "bot_id": "508391840525975553",
+        "command": "explode",
+    }))
+    .is_err());
+}
+
+#[test]
+fn encodes_acks() {
+    let request = Request {
+        request_id: "abc".to_owned(),
+        bot_id: Snowflake(1),
+        command: Command::Ping,
+    };
+
+    let ok = Ack::new(&request, 2, Ok(()));
+    assert_eq!(
+        serde_json::to_value(&ok).unwrap(),
+        json!({"request_id": "abc", "bot_id": "1", "sharder_id": 2, "success": true})
+    );
+
+    let failed = Ack::new(&request, 2, Err("bot is not running".to_owned()));
+    assert_eq!(
+        serde_json::to_value(&failed).unwrap(),
+        json!({
+            "request_id": "abc",
+            "bot_id": "1",
+            "sharder_id": 2,
+            "success": false,
+            "error": "bot is not running",
+        })
+    );
+}
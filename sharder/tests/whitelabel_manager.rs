@@ -0,0 +1,216 @@
+//! Runs against the database given by TEST_DATABASE_URI, e.g.
+//! postgres://postgres@localhost/postgres. The tests are ignored unless run with
+//! `cargo test -- --ignored`, so that they're reported as skipped where there's no database.
+
+mod common;
+
+use ::common::whitelabel_control::{Ack, Command, Request, ACK_KEY};
+use common::mock_api::MockApi;
+use common::{build_config, MemoryCache, MockGateway, MockRedis};
+use database::sqlx::Executor;
+use database::test_support::TestDatabase;
+use database::Database;
+use model::Snowflake;
+use serde_json::json;
+use sharder::event_forwarding::HttpEventForwarder;
+use sharder::{build_redis, ShardManager, WhitelabelShardManager};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+
+const HEARTBEAT_INTERVAL: u32 = 41250;
+
+type Manager = WhitelabelShardManager<HttpEventForwarder, MemoryCache>;
+
+struct Harness {
+    database: TestDatabase,
+    gateway: MockGateway,
+    redis: MockRedis,
+    api: MockApi,
+}
+
+impl Harness {
+    async fn new(schema: &'static str, bot_ids: &[u64]) -> Harness {
+        let harness = Harness {
+            database: TestDatabase::create(schema, bot_ids).await,
+            gateway: MockGateway::bind().await,
+            redis: MockRedis::start().await,
+            api: MockApi::start().await,
+        };
+
+        // the session start limit is checked before each bot identifies
+        harness.api.push_response(json!({
+            "url": "wss://gateway.discord.gg",
+            "shards": 1,
+            "session_start_limit": {
+                "total": 1000,
+                "remaining": 1000,
+                "reset_after": 0,
+                "max_concurrency": 1
+            }
+        }));
+
+        harness
+    }
+
+    async fn database(&self) -> Database {
+        self.database.connect().await
+    }
+
+    /// A sharder with ID 0, as a fresh process would be
+    async fn build_manager(&self) -> Arc<Manager> {
+        let mut config = build_config(&self.gateway, &self.redis);
+        config.discord_api_url = self.api.url().to_owned();
+
+        let redis = build_redis(&config);
+        let event_forwarder =
+            HttpEventForwarder::from_config(&config, HttpEventForwarder::build_http_client());
+
+        Arc::new(WhitelabelShardManager::new(
+            config,
+            Arc::new(self.database().await),
+            Arc::new(MemoryCache::default()),
+            Arc::new(redis),
+            Arc::new(event_forwarder),
+        ))
+    }
+
+    async fn query_bot_ids(&self, query: &str) -> Vec<u64> {
+        database::sqlx::query_as::<_, (i64,)>(query)
+            .fetch_all(&self.database.pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| row.0 as u64)
+            .collect()
+    }
+
+    /// Waits for a bot to connect, returning the token it identified with
+    async fn expect_identify(&self) -> String {
+        let mut conn = self.gateway.accept().await;
+        conn.send_hello(HEARTBEAT_INTERVAL).await;
+
+        let identify = conn.expect_op(2).await;
+        identify["d"]["token"].as_str().unwrap().to_owned()
+    }
+
+    async fn expect_no_connection(&self) {
+        let res = timeout(Duration::from_millis(500), self.gateway.accept()).await;
+        assert!(res.is_err(), "A bot was started");
+    }
+
+    fn last_ack(&self) -> Ack {
+        let published = self.redis.published(ACK_KEY);
+        serde_json::from_str(published.last().expect("Nothing was acknowledged")).unwrap()
+    }
+}
+
+fn request(bot_id: u64, command: Command) -> Request {
+    Request {
+        request_id: format!("{:?}-{}", command, bot_id),
+        bot_id: Snowflake(bot_id),
+        command,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn acknowledges_commands_for_bots_that_are_not_running() {
+    let harness = Harness::new("test_manager_acks", &[]).await;
+    let sm = harness.build_manager().await;
+
+    let cases = [
+        (Command::Ping, "bot is not running on this sharder"),
+        (Command::Stop, "bot is not running on this sharder"),
+        (Command::ReloadStatus, "bot is not running on this sharder"),
+        (Command::ReloadIntents, "bot is not running on this sharder"),
+        (Command::Start, "bot not found in database"),
+        (Command::Restart, "bot not found in database"),
+    ];
+
+    for (i, (command, error)) in cases.iter().enumerate() {
+        let request = request(1, *command);
+        sm.handle_request(&request).await;
+
+        let published = harness.redis.published(ACK_KEY);
+        assert_eq!(published.len(), i + 1, "{:?} wasn't acknowledged", command);
+
+        let ack = harness.last_ack();
+        assert_eq!(ack.request_id, request.request_id);
+        assert_eq!(ack.bot_id, Snowflake(1));
+        assert_eq!(ack.sharder_id, 0);
+        assert!(!ack.success, "{:?} succeeded", command);
+        assert_eq!(ack.error.as_deref(), Some(*error), "{:?}", command);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn restarts_leased_bots_unless_stopped() {
+    let harness = Harness::new("test_manager_restart", &[1, 2, 3]).await;
+
+    // leased before the sharder restarted
+    harness
+        .database
+        .pool
+        .execute(
+            r#"
+INSERT INTO whitelabel_leases VALUES(1, 0, NOW() + INTERVAL '30 seconds'), (2, 0, NOW() + INTERVAL '30 seconds');
+INSERT INTO whitelabel_stopped VALUES(2);
+"#,
+        )
+        .await
+        .unwrap();
+
+    Arc::clone(&harness.build_manager().await).connect().await;
+    assert_eq!(harness.expect_identify().await, "token-1");
+    harness.expect_no_connection().await;
+
+    // the sharder may not know of every live sharder yet, so nothing else is claimed
+    assert_eq!(
+        harness
+            .query_bot_ids(r#"SELECT "bot_id" FROM whitelabel_leases ORDER BY "bot_id";"#)
+            .await,
+        vec![1, 2]
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URI"]
+async fn stopped_bots_stay_stopped_after_restart() {
+    let harness = Harness::new("test_manager_stop", &[1]).await;
+    harness
+        .database
+        .pool
+        .execute(r#"INSERT INTO whitelabel_leases VALUES(1, 0, NOW() + INTERVAL '30 seconds');"#)
+        .await
+        .unwrap();
+
+    let sm = harness.build_manager().await;
+    Arc::clone(&sm).connect().await;
+    assert_eq!(harness.expect_identify().await, "token-1");
+
+    sm.handle_request(&request(1, Command::Stop)).await;
+    assert!(harness.last_ack().success);
+    assert_eq!(
+        harness
+            .query_bot_ids(r#"SELECT "bot_id" FROM whitelabel_stopped;"#)
+            .await,
+        vec![1]
+    );
+
+    // the sharder restarts, keeping its lease
+    let sm = harness.build_manager().await;
+    Arc::clone(&sm).connect().await;
+    harness.expect_no_connection().await;
+
+    // the bot identified moments ago, so would otherwise wait out the identify ratelimit
+    harness.redis.del("ratelimiter:whitelabel:identify:1");
+    sm.handle_request(&request(1, Command::Start)).await;
+    assert!(harness.last_ack().success);
+    assert_eq!(harness.expect_identify().await, "token-1");
+    assert!(harness
+        .query_bot_ids(r#"SELECT "bot_id" FROM whitelabel_stopped;"#)
+        .await
+        .is_empty());
+}